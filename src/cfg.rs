@@ -1,71 +1,569 @@
-use tcod::colors;
-
-// actual size of the window
-pub const SCREEN_WIDTH: i32 = 68;
-pub const SCREEN_HEIGHT: i32 = 35;
-// size of the map
-pub const MAP_WIDTH: i32 = 68;
-pub const MAP_HEIGHT: i32 = 29;
-
-// sizes and coordinates relevant for the GUI
-pub const BAR_WIDTH: i32 = 20;
-pub const PANEL_HEIGHT: i32 = 7;
-pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT + 1;
-pub const MSG_X: i32 = BAR_WIDTH + 2;
-pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 3;
-pub const MSG_HEIGHT: i32 = PANEL_HEIGHT - 1;
-pub const INVENTORY_WIDTH: i32 = 40;
-pub const CHARACTER_SCREEN_WIDTH: i32 = 30;
-pub const LEVEL_SCREEN_WIDTH: i32 = 40;
-
-//parameters for dungeon generator
-pub const ROOM_MAX_SIZE: i32 = 10;
-pub const ROOM_MIN_SIZE: i32 = 6;
-pub const MAX_ROOMS: usize = 30;
-
-pub const HEAL_AMOUNT: i32 = 40;
-pub const SLINGSHOT_DAMAGE: i32 = 40;
-pub const SLINGSHOT_RANGE: i32 = 5;
-pub const BRICK_RANGE: f32 = 8.0;
-pub const BRICK_NUM_TURNS: i32 = 10;
-pub const BLASTING_RADIUS: i32 = 3;
-pub const BLASTING_DAMAGE: i32 = 25;
-
-// experience and level-ups
-pub const LEVEL_UP_BASE: i32 = 200;
-pub const LEVEL_UP_FACTOR: i32 = 150;
-
-pub const FOV_ALGO: tcod::map::FovAlgorithm = tcod::map::FovAlgorithm::Diamond;
-// default FOV algorithm
-pub const FOV_LIGHT_WALLS: bool = true;
-// light walls or not
-pub const TORCH_RADIUS: i32 = 10;
+use serde::{Deserialize, Deserializer};
+use std::sync::OnceLock;
+use std::{error::Error, fs};
+use tcod::colors::Color;
+use tcod::map::FovAlgorithm;
+
+pub const SETTINGS_PATH: &str = "assets/settings.toml";
+
+// number of save slots in the main menu
+pub const SAVE_SLOTS: usize = 8;
 
 pub const GROUND_BITMAP: usize = 0b100010000101000001010001000000001000101000001010000100010000;
 
-pub const LIMIT_FPS: i32 = 20;
-
-// colors:
-pub const COLOR_LIGHTEST_GREY: colors::Color = colors::Color::new(192, 209, 204);
-pub const COLOR_DARKEST_GREY: colors::Color = colors::Color::new(20, 24, 23);
-//pub const COLOR_LIGHT_SEPIA: colors::Color = colors::Color::new(164, 166, 153);
-pub const COLOR_SEPIA: colors::Color = colors::Color::new(129, 122, 119);
-pub const COLOR_DARK_SEPIA: colors::Color = colors::Color::new(92, 87, 82);
-pub const COLOR_DARKER_SEPIA: colors::Color = colors::Color::new(64, 61, 65);
-pub const COLOR_DARKEST_SEPIA: colors::Color = colors::Color::new(53, 50, 56);
-//pub const COLOR_LIGHT_SKY: colors::Color = colors::Color::new(165, 195, 214);
-//pub const COLOR_SKY: colors::Color = colors::Color::new(134, 162, 176);
-pub const COLOR_DARK_SKY: colors::Color = colors::Color::new(104, 127, 139);
-pub const COLOR_GREEN: colors::Color = colors::Color::new(79, 119, 84);
-pub const COLOR_DARK_RED: colors::Color = colors::Color::new(127, 78, 77);
-pub const COLOR_ORANGE: colors::Color = colors::Color::new(155, 107, 77);
-
-pub const COLOR_DARK_WALL: colors::Color = COLOR_DARK_SEPIA;
-pub const COLOR_DARK_WALL_BG: colors::Color = COLOR_DARKEST_SEPIA;
-pub const COLOR_LIGHT_WALL: colors::Color = COLOR_SEPIA;
-pub const COLOR_LIGHT_WALL_BG: colors::Color = COLOR_DARKEST_SEPIA;
-pub const COLOR_DARK_GROUND: colors::Color = COLOR_DARKEST_SEPIA;
-pub const COLOR_DARK_GROUND_BG: colors::Color = COLOR_DARKEST_SEPIA;
-pub const COLOR_LIGHT_GROUND: colors::Color = COLOR_DARK_SEPIA;
-pub const COLOR_LIGHT_GROUND_BG: colors::Color = COLOR_DARKEST_SEPIA;
-pub const COLOR_LIGHT_GROUND_BG_HIGHLIGHTED: colors::Color = COLOR_DARKER_SEPIA;
+/// The settings from `assets/settings.toml`, loaded on the first call. If the
+/// file can't be loaded or has wrong values, the default settings are used.
+pub fn settings() -> &'static Settings {
+    static SETTINGS: OnceLock<Settings> = OnceLock::new();
+    SETTINGS.get_or_init(|| {
+        Settings::load(SETTINGS_PATH).unwrap_or_else(|err| {
+            println!("{}: {}, the default settings are used", SETTINGS_PATH, err);
+            Settings::default()
+        })
+    })
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub screen: Screen,
+    pub map: Map,
+    pub gui: Gui,
+    pub dungeon: Dungeon,
+    pub level_up: LevelUp,
+    pub ai: AiSettings,
+    pub turns: Turns,
+    pub combat: Combat,
+    pub traps: Traps,
+    pub fov: Fov,
+    pub saves: Saves,
+    pub colors: Colors,
+}
+
+impl Settings {
+    pub fn load(path: &str) -> Result<Settings, Box<dyn Error>> {
+        let settings: Settings = toml::from_str(&fs::read_to_string(path)?)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Checks that the values make sense together, e.g. the map and the panel
+    /// fit the screen.
+    pub fn validate(&self) -> Result<(), String> {
+        let check = |is_ok: bool, msg: &str| if is_ok { Ok(()) } else { Err(msg.to_string()) };
+        let (screen, map, gui, dungeon) = (&self.screen, &self.map, &self.gui, &self.dungeon);
+        check(screen.limit_fps > 0, "screen.limit_fps must be positive")?;
+        check(
+            (map.width > 6) && (map.height > 6),
+            "the map must be at least 7x7",
+        )?;
+        check(
+            (map.width <= screen.width) && (map.height <= self.panel_y()),
+            "the map and the panel must fit the screen",
+        )?;
+        check(
+            (gui.panel_height > 1) && (self.msg_width() > 0),
+            "the panel is too small for the messages",
+        )?;
+        let dialog_widths = [
+            gui.inventory_width,
+            gui.character_screen_width,
+            gui.level_screen_width,
+            gui.save_slots_width,
+        ];
+        check(
+            dialog_widths
+                .iter()
+                .all(|&w| (w > 2) && (w <= screen.width)),
+            "the dialogs must fit the screen",
+        )?;
+        check(
+            (dungeon.room_min_size > 0) && (dungeon.room_min_size <= dungeon.room_max_size),
+            "dungeon.room_min_size must be positive and not greater than room_max_size",
+        )?;
+        check(
+            (dungeon.room_max_size < map.width) && (dungeon.room_max_size < map.height),
+            "the rooms must fit the map",
+        )?;
+        check(
+            dungeon.max_rooms >= 1,
+            "dungeon.max_rooms must be at least 1",
+        )?;
+        check(
+            (0..=100).contains(&dungeon.vault_chance) && (0..=100).contains(&dungeon.door_chance),
+            "dungeon.vault_chance and door_chance must be percents from 0 to 100",
+        )?;
+        check(
+            dungeon
+                .generators
+                .windows(2)
+                .all(|pair| pair[0].from_level < pair[1].from_level),
+            "dungeon.generators must be sorted by from_level",
+        )?;
+        check(
+            dungeon
+                .generators
+                .iter()
+                .all(|choice| match choice.generator {
+                    Generator::Caves { fill, .. } => (0..100).contains(&fill),
+                    Generator::Mines { floor } => (1..=80).contains(&floor),
+                    Generator::Rooms | Generator::Bsp => true,
+                }),
+            "the caves fill must be from 0 to 99 and the mines floor from 1 to 80 percents",
+        )?;
+        check(
+            (self.ai.search_turns >= 0) && (self.ai.search_radius >= 0),
+            "ai.search_turns and ai.search_radius can't be negative",
+        )?;
+        let turns = &self.turns;
+        check(
+            (turns.energy_to_act > 0)
+                && (turns.move_cost > 0)
+                && (turns.attack_cost > 0)
+                && (turns.wait_cost > 0),
+            "turns.energy_to_act and the action costs must be positive",
+        )?;
+        let combat = &self.combat;
+        let is_percent = |value: i32| (0..=100).contains(&value);
+        check(
+            is_percent(combat.hit_chance)
+                && is_percent(combat.min_hit_chance)
+                && is_percent(combat.damage_variance)
+                && is_percent(combat.crit_chance)
+                && is_percent(combat.min_damage_chance),
+            "the combat chances and damage_variance must be percents from 0 to 100",
+        )?;
+        check(
+            (combat.hit_chance_per_point >= 0) && (combat.crit_multiplier >= 1.0),
+            "combat.hit_chance_per_point can't be negative and crit_multiplier less than 1",
+        )?;
+        let traps = &self.traps;
+        check(
+            (traps.search_radius >= 0)
+                && is_percent(traps.search_chance)
+                && is_percent(traps.notice_chance),
+            "traps.search_radius can't be negative and the chances must be percents",
+        )?;
+        check(
+            self.fov.torch_radius >= 0,
+            "fov.torch_radius can't be negative",
+        )?;
+        check(
+            (self.level_up.base > 0) && (self.level_up.factor >= 0),
+            "level_up.base must be positive and level_up.factor not negative",
+        )
+    }
+
+    pub fn panel_y(&self) -> i32 {
+        self.screen.height - self.gui.panel_height + 1
+    }
+
+    pub fn msg_x(&self) -> i32 {
+        self.gui.bar_width + 2
+    }
+
+    pub fn msg_width(&self) -> i32 {
+        self.screen.width - self.gui.bar_width - 3
+    }
+
+    pub fn msg_height(&self) -> i32 {
+        self.gui.panel_height - 1
+    }
+}
+
+/// Actual size of the window.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Screen {
+    pub width: i32,
+    pub height: i32,
+    pub limit_fps: i32,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen {
+            width: 68,
+            height: 35,
+            limit_fps: 20,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Map {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for Map {
+    fn default() -> Self {
+        Map {
+            width: 68,
+            height: 29,
+        }
+    }
+}
+
+/// Sizes relevant for the GUI.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Gui {
+    pub bar_width: i32,
+    pub panel_height: i32,
+    pub inventory_width: i32,
+    pub character_screen_width: i32,
+    pub level_screen_width: i32,
+    pub save_slots_width: i32,
+    /// show shots and thrown items flying
+    pub animate_projectiles: bool,
+}
+
+impl Default for Gui {
+    fn default() -> Self {
+        Gui {
+            bar_width: 20,
+            panel_height: 7,
+            inventory_width: 40,
+            character_screen_width: 30,
+            level_screen_width: 40,
+            save_slots_width: 64,
+            animate_projectiles: true,
+        }
+    }
+}
+
+/// Parameters for the dungeon generator.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dungeon {
+    pub room_max_size: i32,
+    pub room_min_size: i32,
+    pub max_rooms: usize,
+    /// percents of the levels with a vault from `assets/vaults.toml`
+    pub vault_chance: i32,
+    /// percents of the doorways of the rooms with a door
+    pub door_chance: i32,
+    /// the mine supports in the shafts of a level, at most
+    pub max_supports: u32,
+    pub generators: Vec<GeneratorChoice>,
+}
+
+impl Default for Dungeon {
+    fn default() -> Self {
+        Dungeon {
+            room_max_size: 10,
+            room_min_size: 6,
+            max_rooms: 30,
+            vault_chance: 50,
+            door_chance: 40,
+            max_supports: 3,
+            generators: vec![
+                GeneratorChoice {
+                    from_level: 1,
+                    generator: Generator::Rooms,
+                },
+                GeneratorChoice {
+                    from_level: 3,
+                    generator: Generator::Mines { floor: 35 },
+                },
+                GeneratorChoice {
+                    from_level: 5,
+                    generator: Generator::Caves {
+                        fill: 45,
+                        iterations: 4,
+                    },
+                },
+                GeneratorChoice {
+                    from_level: 7,
+                    generator: Generator::Bsp,
+                },
+            ],
+        }
+    }
+}
+
+/// The map generator used from the level on, until the next choice.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GeneratorChoice {
+    pub from_level: u32,
+    pub generator: Generator,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Generator {
+    /// random rooms joined by tunnels
+    Rooms,
+    /// rooms in the parts of the map split in two again and again
+    Bsp,
+    /// cellular automata caves, `fill` is the percent of the walls at first
+    Caves { fill: i32, iterations: u32 },
+    /// drunkard's walk shafts, until `floor` percents of the map are dug
+    Mines { floor: i32 },
+}
+
+/// Experience needed for a level-up: `base + level * factor`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LevelUp {
+    pub base: i32,
+    pub factor: i32,
+}
+
+impl Default for LevelUp {
+    fn default() -> Self {
+        LevelUp {
+            base: 200,
+            factor: 150,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiSettings {
+    /// turns a monster looks for the player after losing sight of them
+    pub search_turns: i32,
+    /// how far from the last seen position a searching monster wanders
+    pub search_radius: i32,
+}
+
+impl Default for AiSettings {
+    fn default() -> Self {
+        AiSettings {
+            search_turns: 15,
+            search_radius: 3,
+        }
+    }
+}
+
+/// The energy of the turn scheduler. Every tick a character gains its speed in
+/// energy, and acts while it has at least `energy_to_act`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Turns {
+    pub energy_to_act: i32,
+    pub move_cost: i32,
+    pub attack_cost: i32,
+    pub wait_cost: i32,
+}
+
+impl Default for Turns {
+    fn default() -> Self {
+        Turns {
+            energy_to_act: 100,
+            move_cost: 100,
+            attack_cost: 100,
+            wait_cost: 100,
+        }
+    }
+}
+
+/// The attack rolls. An attack hits with `hit_chance` percent, plus
+/// `hit_chance_per_point` for every point of the attacker's accuracy above the
+/// target's evasion, but never less than `min_hit_chance`. The damage varies by
+/// `damage_variance` percent of the power.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Combat {
+    pub hit_chance: i32,
+    pub hit_chance_per_point: i32,
+    pub min_hit_chance: i32,
+    pub damage_variance: i32,
+    pub crit_chance: i32,
+    pub crit_multiplier: f32,
+    /// chance of a hit to do 1 damage when the defense stops it
+    pub min_damage_chance: i32,
+}
+
+impl Default for Combat {
+    fn default() -> Self {
+        Combat {
+            hit_chance: 80,
+            hit_chance_per_point: 5,
+            min_hit_chance: 10,
+            damage_variance: 25,
+            crit_chance: 5,
+            crit_multiplier: 2.0,
+            min_damage_chance: 20,
+        }
+    }
+}
+
+/// The hidden traps of a level, and the chances to find them: searching finds
+/// each one in `search_radius` by `search_chance`, and the player notices the
+/// ones next to them by `notice_chance` on every move.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Traps {
+    pub max_traps: u32,
+    pub search_radius: i32,
+    pub search_chance: i32,
+    pub notice_chance: i32,
+}
+
+impl Default for Traps {
+    fn default() -> Self {
+        Traps {
+            max_traps: 4,
+            search_radius: 2,
+            search_chance: 60,
+            notice_chance: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fov {
+    #[serde(deserialize_with = "deserialize_fov_algorithm")]
+    pub algorithm: FovAlgorithm,
+    pub light_walls: bool,
+    pub torch_radius: i32,
+}
+
+impl Default for Fov {
+    fn default() -> Self {
+        Fov {
+            algorithm: FovAlgorithm::Diamond,
+            light_walls: true,
+            torch_radius: 10,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Saves {
+    /// save games in the compact binary format instead of JSON, both can be loaded
+    pub binary: bool,
+}
+
+impl Default for Saves {
+    fn default() -> Self {
+        Saves { binary: true }
+    }
+}
+
+fn deserialize_fov_algorithm<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<FovAlgorithm, D::Error> {
+    use FovAlgorithm::*;
+    let name = String::deserialize(deserializer)?;
+    let algorithms = [
+        ("Basic", Basic),
+        ("Diamond", Diamond),
+        ("Shadow", Shadow),
+        ("Permissive0", Permissive0),
+        ("Permissive1", Permissive1),
+        ("Permissive2", Permissive2),
+        ("Permissive3", Permissive3),
+        ("Permissive4", Permissive4),
+        ("Permissive5", Permissive5),
+        ("Permissive6", Permissive6),
+        ("Permissive7", Permissive7),
+        ("Permissive8", Permissive8),
+        ("Restrictive", Restrictive),
+    ];
+    algorithms
+        .iter()
+        .find(|(algorithm_name, _)| *algorithm_name == name)
+        .map(|&(_, algorithm)| algorithm)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown FOV algorithm `{}`", name)))
+}
+
+/// The palette.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    pub lightest_grey: Color,
+    pub darkest_grey: Color,
+    pub sepia: Color,
+    pub dark_sepia: Color,
+    pub darker_sepia: Color,
+    pub darkest_sepia: Color,
+    pub dark_sky: Color,
+    pub green: Color,
+    pub dark_red: Color,
+    pub orange: Color,
+    pub dark_wall: Color,
+    pub dark_wall_bg: Color,
+    pub light_wall: Color,
+    pub light_wall_bg: Color,
+    pub dark_ground: Color,
+    pub dark_ground_bg: Color,
+    pub light_ground: Color,
+    pub light_ground_bg: Color,
+    pub light_ground_bg_highlighted: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        let sepia = Color::new(129, 122, 119);
+        let dark_sepia = Color::new(92, 87, 82);
+        let darker_sepia = Color::new(64, 61, 65);
+        let darkest_sepia = Color::new(53, 50, 56);
+        Colors {
+            lightest_grey: Color::new(192, 209, 204),
+            darkest_grey: Color::new(20, 24, 23),
+            sepia,
+            dark_sepia,
+            darker_sepia,
+            darkest_sepia,
+            dark_sky: Color::new(104, 127, 139),
+            green: Color::new(79, 119, 84),
+            dark_red: Color::new(127, 78, 77),
+            orange: Color::new(155, 107, 77),
+            dark_wall: dark_sepia,
+            dark_wall_bg: darkest_sepia,
+            light_wall: sepia,
+            light_wall_bg: darkest_sepia,
+            dark_ground: darkest_sepia,
+            dark_ground_bg: darkest_sepia,
+            light_ground: dark_sepia,
+            light_ground_bg: darkest_sepia,
+            light_ground_bg_highlighted: darker_sepia,
+        }
+    }
+}
+
+#[cfg(test)]
+mod settings_tests {
+    use super::*;
+
+    #[test]
+    fn settings_file_has_default_values() {
+        let settings = Settings::load(SETTINGS_PATH).unwrap();
+        assert_eq!(
+            format!("{:?}", settings),
+            format!("{:?}", Settings::default())
+        );
+    }
+
+    #[test]
+    fn missing_values_are_default() {
+        let settings: Settings = toml::from_str("[map]\nwidth = 40\n").unwrap();
+        assert_eq!(settings.map.width, 40);
+        assert_eq!(settings.map.height, Map::default().height);
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn wrong_settings_are_rejected() {
+        let too_tall_map: Settings = toml::from_str("[map]\nheight = 35\n").unwrap();
+        assert!(too_tall_map.validate().is_err());
+        let small_rooms: Settings = toml::from_str("[dungeon]\nroom_min_size = 11\n").unwrap();
+        assert!(small_rooms.validate().is_err());
+        let no_rooms: Settings = toml::from_str("[dungeon]\nmax_rooms = 0\n").unwrap();
+        assert!(no_rooms.validate().is_err());
+        let full_caves: Settings = toml::from_str(
+            "[dungeon]\ngenerators = [{ from_level = 1, generator = { Caves = { fill = 100, iterations = 4 } } }]\n",
+        )
+        .unwrap();
+        assert!(full_caves.validate().is_err());
+        assert!(toml::from_str::<Settings>("[fov]\nalgorithm = \"Round\"\n").is_err());
+        assert!(toml::from_str::<Settings>("[screen]\nwidht = 80\n").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use tcod::colors;
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Player {
     pub id: u32,
     pub dungeon_level: u32,
@@ -9,6 +9,8 @@ pub struct Player {
     pub action: PlayerAction,
     pub looking_at: Option<(i32, i32)>,
     pub previous_player_position: (i32, i32),
+    #[serde(default)]
+    pub turn: u32,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -29,6 +31,10 @@ pub enum PlayerAction {
     None,
     Cancel,
     SelectMenuItem(usize),
+    /// typing a text in a dialog, e.g. the name of a save slot
+    TypeChar(char),
+    EraseChar,
+    Confirm,
     LookAt(i32, i32),
     ClickAt(i32, i32),
     GoToUp,
@@ -41,6 +47,7 @@ pub enum PlayerAction {
     GoToDownRight,
     SkipTurn,
     InteractWithMap,
+    Search,
     OpenHelp,
     OpenInventory,
     OpenCharInfo,
@@ -54,7 +61,7 @@ impl Default for PlayerAction {
 }
 
 #[serde(default)]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Symbol {
     pub x: i32,
     pub y: i32,
@@ -72,7 +79,7 @@ pub struct MapCell {
 }
 
 #[serde(default)]
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct MapObject {
     pub name: String,
     pub block: bool,
@@ -80,7 +87,7 @@ pub struct MapObject {
     pub hidden: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Character {
     #[serde(default)]
     pub alive: bool,
@@ -90,10 +97,46 @@ pub struct Character {
     pub base_max_hp: i32,
     pub base_defense: i32,
     pub base_power: i32,
+    /// adds to the chance to hit
+    #[serde(default)]
+    pub base_accuracy: i32,
+    /// takes from the chance to be hit
+    #[serde(default)]
+    pub base_evasion: i32,
     pub xp: i32,
     pub on_death: DeathCallback,
     #[serde(default)]
     pub looking_right: bool,
+    /// energy gained every tick of the scheduler, 100 is the normal speed
+    #[serde(default = "normal_speed")]
+    pub speed: i32,
+    /// the character can act when it has enough energy, actions spend it
+    #[serde(default)]
+    pub energy: i32,
+}
+
+fn normal_speed() -> i32 {
+    100
+}
+
+impl Default for Character {
+    fn default() -> Self {
+        Character {
+            alive: false,
+            level: 0,
+            hp: 0,
+            base_max_hp: 0,
+            base_defense: 0,
+            base_power: 0,
+            base_accuracy: 0,
+            base_evasion: 0,
+            xp: 0,
+            on_death: DeathCallback::None,
+            looking_right: false,
+            speed: normal_speed(),
+            energy: 0,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -111,40 +154,142 @@ impl Default for DeathCallback {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Ai {
+    /// chases the player and attacks in melee
     Basic,
-    Confused {
-        // TODO: fix this unsized stuff
-        previous_ai: Box<Ai>,
-        num_turns: i32,
+    /// like `Basic`, but runs away when its HP is below the share of the maximum
+    Coward { flee_below: f32 },
+    /// keeps at least `keep_distance` tiles from the player and attacks from up
+    /// to `range` tiles
+    Ranged { range: i32, keep_distance: i32 },
+    /// waits until there are at least `allies` other monsters within `radius`,
+    /// then attacks like `Basic`
+    Pack { allies: usize, radius: i32 },
+    /// never moves, attacks only when the player is next to it
+    Stationary,
+    /// chases the player only within `radius` from its post, then goes back
+    Guard {
+        radius: i32,
+        #[serde(default)]
+        post: Option<(i32, i32)>,
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default)]
 pub struct AiOption {
     pub option: Option<Ai>,
+    #[serde(default)]
+    pub memory: AiMemory,
+}
+
+/// What a monster remembers about the player after losing sight of them.
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct AiMemory {
+    pub last_seen: Option<(i32, i32)>,
+    /// turns left to search around the last seen position before giving up
+    pub search_turns: i32,
+}
+
+/// The effects that last on a character for some turns.
+#[derive(Clone, Debug, Serialize, Deserialize, Default)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub turns: i32,
+    /// damage every turn of poison and bleeding, extra speed of haste
+    #[serde(default)]
+    pub power: i32,
+    /// the player poisoned or wounded, so a kill by it is theirs
+    #[serde(default)]
+    pub by_player: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum StatusKind {
+    /// moves in random directions
+    Confusion,
+    /// the damage of a new dose adds up
+    Poison,
+    /// every wound bleeds on its own
+    Bleeding,
+    /// skips the turns
+    Stun,
+    Haste,
+}
+
+impl std::fmt::Display for StatusKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StatusKind::Confusion => write!(f, "confused"),
+            StatusKind::Poison => write!(f, "poisoned"),
+            StatusKind::Bleeding => write!(f, "bleeding"),
+            StatusKind::Stun => write!(f, "stunned"),
+            StatusKind::Haste => write!(f, "hasted"),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ItemKind {
-    Medkit,
-    FutureUnknownDamager,
-    Brick,
-    BlastingCartridge,
+    /// used up, does what its `on_use` says
+    Consumable,
     Melee,
     Ranged(AmmoKind),
     Ammo,
     Clothing,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Item {
     pub kind: ItemKind,
     #[serde(default)]
     pub owner: u32,
+    /// status effects on the ones hit with it
+    #[serde(default)]
+    pub inflicts: Vec<StatusEffect>,
+    #[serde(default)]
+    pub on_use: Option<OnUse>,
+}
+
+/// What a consumable does when it's used.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OnUse {
+    pub target: Targeting,
+    /// how far it can be thrown, any tile in sight if not set
+    #[serde(default)]
+    pub range: Option<f32>,
+    /// everyone this close to where it lands gets the effects, only the one
+    /// hit if 0
+    #[serde(default)]
+    pub radius: i32,
+    pub effects: Vec<Effect>,
+    /// shown when it's used
+    #[serde(default)]
+    pub message: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Targeting {
+    /// the user
+    Itself,
+    /// thrown at the nearest enemy in sight
+    Nearest,
+    /// thrown at a tile, it lands where it stops
+    Tile,
+    /// thrown at a creature, it falls to the ground if it misses
+    Creature,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Effect {
+    Heal(i32),
+    Damage(i32),
+    Status(StatusEffect),
 }
 
 /// An object that can be equipped, yielding bonuses.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Equipment {
     pub slot: Slot,
     #[serde(default)]
@@ -152,6 +297,17 @@ pub struct Equipment {
     pub max_hp_bonus: i32,
     pub defense_bonus: i32,
     pub power_bonus: i32,
+    #[serde(default)]
+    pub accuracy_bonus: i32,
+    /// percents added to the chance of a critical hit
+    #[serde(default)]
+    pub crit_bonus: i32,
+    /// energy an attack with it costs instead of the usual one
+    #[serde(default)]
+    pub attack_cost: Option<i32>,
+    /// how far a ranged weapon shoots
+    #[serde(default)]
+    pub range: Option<i32>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -171,9 +327,14 @@ impl std::fmt::Display for Slot {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Ammo {
     pub kind: AmmoKind,
+}
+
+/// Many of the same item carried, or lying on a tile, as one.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Stack {
     pub count: u32,
 }
 
@@ -182,9 +343,44 @@ pub enum AmmoKind {
     SlingshotAmmo,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct LogMessage(pub String, pub colors::Color);
 
+/// Leads to the next level in its direction.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Stairs {
+    Up,
+    Down,
+}
+
+/// A part of the map to interact with, standing on it or next to it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Feature {
+    /// opened by hand, or only by the lever of its group if it has one
+    Door { open: bool, group: Option<u32> },
+    /// opens and closes the doors of its group
+    Lever { pulled: bool, group: u32 },
+    /// holds the ceiling of a shaft: knocked out, the rock comes down on its
+    /// tile, hurting the ones around
+    Support { radius: i32, damage: i32 },
+    /// can be disarmed once found, what it does is in its `Trap` component
+    Trap,
+}
+
+/// Is triggered by the one stepping on it, affecting everyone in the radius.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trap {
+    /// shown when it's triggered
+    pub message: String,
+    /// just the one stepping on it is affected if it's 0
+    #[serde(default)]
+    pub radius: i32,
+    pub effects: Vec<Effect>,
+    /// the trap is gone after it's triggered
+    #[serde(default)]
+    pub once: bool,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DialogBox {
     pub kind: DialogKind,
@@ -199,5 +395,13 @@ pub enum DialogKind {
     MessageBox,
     Inventory,
     DropItem,
+    /// how many of the stack to drop
+    DropCount(u32),
     LevelUp,
+    NewGameSlots,
+    LoadGameSlots,
+    /// the name of the new game in the slot
+    SlotName(usize),
+    /// whether to start the new game over the saved one in the slot
+    OverwriteSlot(usize),
 }
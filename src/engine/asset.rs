@@ -1,7 +1,7 @@
 use crate::cmtp;
-use crate::cmtp::{Ai, Ammo, Equipment, MapObject, Symbol};
+use crate::cmtp::{Ai, Ammo, Equipment, MapObject, Stack, StatusEffect, Symbol};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::{error::Error, fs, io, io::Read as _};
 
 #[derive(Debug, Deserialize)]
@@ -11,6 +11,8 @@ pub struct Item {
     pub item: cmtp::Item,
     pub equipment: Option<Equipment>,
     pub ammo: Option<Ammo>,
+    /// the stackable items have it
+    pub stack: Option<Stack>,
     #[serde(default)]
     spawn_chances: Vec<SpawnChance>,
 }
@@ -22,6 +24,28 @@ pub struct Character {
     pub character: cmtp::Character,
     pub ai: Option<Ai>,
     #[serde(default)]
+    pub status_effects: Vec<StatusEffect>,
+    #[serde(default)]
+    spawn_chances: Vec<SpawnChance>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Trap {
+    pub symbol: Symbol,
+    pub map_object: MapObject,
+    pub trap: cmtp::Trap,
+    #[serde(default)]
+    spawn_chances: Vec<SpawnChance>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Vault {
+    pub layout: Vec<String>,
+    /// the characters and items on the tiles of the layout, by their ids
+    #[serde(default)]
+    pub legend: BTreeMap<char, String>,
+    #[serde(default)]
     spawn_chances: Vec<SpawnChance>,
 }
 
@@ -32,7 +56,7 @@ struct SpawnChance {
 }
 
 pub struct ItemsLoader {
-    item_vals: HashMap<String, serde_json::Value>,
+    item_vals: BTreeMap<String, serde_json::Value>,
 }
 
 impl ItemsLoader {
@@ -40,11 +64,23 @@ impl ItemsLoader {
         let mut toml_save_state = String::new();
         let mut file = fs::File::open("assets/items.toml")?;
         file.read_to_string(&mut toml_save_state)?;
-        let item_vals: HashMap<String, serde_json::Value> = toml::from_str(&toml_save_state)?;
+        ItemsLoader::from_toml(&toml_save_state)
+    }
+
+    /// Loads the items, checking that the values make sense, e.g. the attacks
+    /// cost some energy.
+    fn from_toml(toml_save_state: &str) -> Result<ItemsLoader, Box<dyn Error>> {
+        let item_vals: BTreeMap<String, serde_json::Value> = toml::from_str(toml_save_state)?;
         for (id, item_val) in &item_vals {
-            serde_json::from_value::<Item>(item_val.clone()).map_err(|err| {
-                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", id, err))
-            })?;
+            let invalid = |msg: String| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", id, msg))
+            };
+            let item = serde_json::from_value::<Item>(item_val.clone())
+                .map_err(|err| invalid(err.to_string()))?;
+            let attack_cost = item.equipment.as_ref().and_then(|eqp| eqp.attack_cost);
+            if attack_cost.filter(|&cost| cost <= 0).is_some() {
+                return Err(invalid("equipment.attack_cost must be positive".into()).into());
+            }
         }
         Ok(ItemsLoader { item_vals })
     }
@@ -63,10 +99,14 @@ impl ItemsLoader {
     pub fn get_clone(&self, id: &str) -> Item {
         serde_json::from_value(self.item_vals[id].clone()).unwrap()
     }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.item_vals.contains_key(id)
+    }
 }
 
 pub struct CharactersLoader {
-    char_vals: HashMap<String, serde_json::Value>,
+    char_vals: BTreeMap<String, serde_json::Value>,
 }
 
 impl CharactersLoader {
@@ -74,7 +114,7 @@ impl CharactersLoader {
         let mut toml_save_state = String::new();
         let mut file = fs::File::open("assets/characters.toml")?;
         file.read_to_string(&mut toml_save_state)?;
-        let char_vals: HashMap<String, serde_json::Value> = toml::from_str(&toml_save_state)?;
+        let char_vals: BTreeMap<String, serde_json::Value> = toml::from_str(&toml_save_state)?;
         for (id, char_val) in &char_vals {
             serde_json::from_value::<Character>(char_val.clone()).map_err(|err| {
                 io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", id, err))
@@ -97,6 +137,108 @@ impl CharactersLoader {
     pub fn get_clone(&self, id: &str) -> Character {
         serde_json::from_value(self.char_vals[id].clone()).unwrap()
     }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.char_vals.contains_key(id)
+    }
+}
+
+pub struct TrapsLoader {
+    trap_vals: BTreeMap<String, serde_json::Value>,
+}
+
+impl TrapsLoader {
+    pub fn load() -> Result<TrapsLoader, Box<dyn Error>> {
+        let mut toml_save_state = String::new();
+        let mut file = fs::File::open("assets/traps.toml")?;
+        file.read_to_string(&mut toml_save_state)?;
+        let trap_vals: BTreeMap<String, serde_json::Value> = toml::from_str(&toml_save_state)?;
+        for (id, trap_val) in &trap_vals {
+            serde_json::from_value::<Trap>(trap_val.clone()).map_err(|err| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", id, err))
+            })?;
+        }
+        Ok(TrapsLoader { trap_vals })
+    }
+
+    pub fn weighted_table(&self, for_level: u32) -> (Vec<&str>, Vec<u32>) {
+        self.trap_vals
+            .iter()
+            .map(|(id, trap_val)| {
+                let trap: Trap = serde_json::from_value(trap_val.clone()).unwrap();
+                let weight = weight_for_level(&trap.spawn_chances, for_level);
+                (id.as_str(), weight)
+            })
+            .unzip()
+    }
+
+    pub fn get_clone(&self, id: &str) -> Trap {
+        serde_json::from_value(self.trap_vals[id].clone()).unwrap()
+    }
+}
+
+pub struct VaultsLoader {
+    vault_vals: BTreeMap<String, serde_json::Value>,
+}
+
+impl VaultsLoader {
+    /// Loads the vaults, checking that the layouts are rectangles and the
+    /// legends name known characters or items.
+    pub fn load(
+        char_loader: &CharactersLoader,
+        items_loader: &ItemsLoader,
+    ) -> Result<VaultsLoader, Box<dyn Error>> {
+        let mut toml_save_state = String::new();
+        let mut file = fs::File::open("assets/vaults.toml")?;
+        file.read_to_string(&mut toml_save_state)?;
+        let vault_vals: BTreeMap<String, serde_json::Value> = toml::from_str(&toml_save_state)?;
+        for (id, vault_val) in &vault_vals {
+            let invalid = |msg: String| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", id, msg))
+            };
+            let vault = serde_json::from_value::<Vault>(vault_val.clone())
+                .map_err(|err| invalid(err.to_string()))?;
+            let width = vault.layout.first().map_or(0, |row| row.chars().count());
+            if (width == 0) || vault.layout.iter().any(|row| row.chars().count() != width) {
+                return Err(invalid("the layout rows must have the same length".into()).into());
+            }
+            let map = &crate::cfg::settings().map;
+            if (width as i32 > map.width - 2) || (vault.layout.len() as i32 > map.height - 2) {
+                return Err(invalid("the layout doesn't fit the map".into()).into());
+            }
+            let unknown = vault
+                .layout
+                .iter()
+                .flat_map(|row| row.chars())
+                .find(|glyph| !"#. +=/^".contains(*glyph) && !vault.legend.contains_key(glyph));
+            if let Some(glyph) = unknown {
+                return Err(invalid(format!("'{}' is not in the legend", glyph)).into());
+            }
+            let unknown = vault
+                .legend
+                .values()
+                .find(|&id| !char_loader.contains(id) && !items_loader.contains(id));
+            if let Some(unknown_id) = unknown {
+                return Err(invalid(format!("no character or item \"{}\"", unknown_id)).into());
+            }
+        }
+        Ok(VaultsLoader { vault_vals })
+    }
+
+    pub fn weighted_table(&self, for_level: u32) -> (Vec<&str>, Vec<u32>) {
+        self.vault_vals
+            .iter()
+            .map(|(id, vault_val)| {
+                let vault: Vault = serde_json::from_value(vault_val.clone()).unwrap();
+                let weight = weight_for_level(&vault.spawn_chances, for_level);
+                (id.as_str(), weight)
+            })
+            .unzip()
+    }
+
+    pub fn get_clone(&self, id: &str) -> Vault {
+        serde_json::from_value(self.vault_vals[id].clone()).unwrap()
+    }
 }
 
 fn weight_for_level(spawn_chances: &[SpawnChance], lvl: u32) -> u32 {
@@ -144,6 +286,23 @@ mod items_loader_tests {
         let item = loader.get_clone("dummy");
         assert_eq!(item.map_object.name, "Dummy");
     }
+
+    #[test]
+    fn free_attacks_are_rejected() {
+        let club = |attack_cost: i32| {
+            format!(
+                "[club]\nmap_object.name = \"Club\"\nitem.kind = \"Melee\"\n\
+                 symbol.glyph = \"c\"\nsymbol.color = {{ r = 0, g = 0, b = 0 }}\n\
+                 equipment.slot = \"Hands\"\nequipment.max_hp_bonus = 0\n\
+                 equipment.defense_bonus = 0\nequipment.power_bonus = 1\n\
+                 equipment.attack_cost = {}\n",
+                attack_cost
+            )
+        };
+        assert!(ItemsLoader::from_toml(&club(150)).is_ok());
+        assert!(ItemsLoader::from_toml(&club(0)).is_err());
+        assert!(ItemsLoader::from_toml(&club(-50)).is_err());
+    }
 }
 
 #[cfg(test)]
@@ -184,3 +343,59 @@ mod characters_loader_tests {
         assert_eq!(char.map_object.name, "Dummy");
     }
 }
+
+#[cfg(test)]
+mod traps_loader_tests {
+    use super::*;
+
+    #[test]
+    fn load_result_is_ok() {
+        let result = TrapsLoader::load();
+        assert!(result.is_ok(), "{}", result.err().unwrap());
+    }
+
+    #[test]
+    fn weighted_table_has_non_zero() {
+        let loader = TrapsLoader::load().unwrap();
+        for for_level in 1..15 {
+            let weighted_table = loader.weighted_table(for_level);
+            assert!(
+                weighted_table.1.iter().any(|&weight| weight > 0),
+                "The table for level {} has only zeros:\n\t{:?}\nthis is a bug.",
+                for_level,
+                weighted_table
+            );
+        }
+    }
+
+    #[test]
+    fn getting_clone_of_dummy_trap() {
+        let trap = TrapsLoader::load().unwrap().get_clone("dummy");
+        assert_eq!(trap.map_object.name, "Dummy");
+    }
+}
+
+#[cfg(test)]
+mod vaults_loader_tests {
+    use super::*;
+
+    fn load() -> Result<VaultsLoader, Box<dyn Error>> {
+        VaultsLoader::load(
+            &CharactersLoader::load().unwrap(),
+            &ItemsLoader::load().unwrap(),
+        )
+    }
+
+    #[test]
+    fn load_result_is_ok() {
+        let result = load();
+        assert!(result.is_ok(), "{}", result.err().unwrap());
+    }
+
+    #[test]
+    fn getting_clone_of_dummy_vault() {
+        let vault = load().unwrap().get_clone("dummy");
+        assert_eq!(vault.layout, vec!["###", "#d#", "###"]);
+        assert_eq!(vault.legend[&'d'], "dummy");
+    }
+}
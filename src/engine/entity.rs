@@ -1,155 +1,202 @@
 use super::game;
 use crate::cmtp::{
-    AiOption, Ammo, Character, DialogBox, Equipment, Item, LogMessage, MapCell, MapObject, Symbol,
+    AiOption, Ammo, Character, DialogBox, Equipment, Feature, Item, LogMessage, MapCell, MapObject,
+    Stack, Stairs, StatusEffects, Symbol, Trap,
 };
 use serde::{Deserialize, Serialize};
+use std::{mem, ptr};
 
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct Indexes {
-    pub symbol: Option<usize>,
-    pub map_cell: Option<usize>,
-    pub map_object: Option<usize>,
-    pub character: Option<usize>,
-    pub ai: Option<usize>,
-    pub item: Option<usize>,
-    pub equipment: Option<usize>,
-    pub ammo: Option<usize>,
-    pub log_message: Option<usize>,
-    pub dialog: Option<usize>,
+/// A component type of the entities, stored in its own vector of the `World`.
+pub trait Component: Sized + 'static {
+    fn storage(world: &game::World) -> &Vec<Self>;
+    fn storage_mut(world: &mut game::World) -> &mut Vec<Self>;
+    /// Pointer to the storage without borrowing the whole world, so that
+    /// storages of different components can be borrowed together.
+    ///
+    /// # Safety
+    ///
+    /// `world` must point to a valid `World`.
+    unsafe fn storage_ptr(world: *mut game::World) -> *mut Vec<Self>;
+    fn index(indexes: &Indexes) -> Option<usize>;
+    fn index_mut(indexes: &mut Indexes) -> &mut Option<usize>;
 }
 
-fn create(
-    world: &mut game::World,
-    symbol: Option<Symbol>,
-    map_cell: Option<MapCell>,
-    map_object: Option<MapObject>,
-    character: Option<Character>,
-    ai: Option<AiOption>,
-    item: Option<Item>,
-    equipment: Option<Equipment>,
-    ammo: Option<Ammo>,
-    log_message: Option<LogMessage>,
-    dialog: Option<DialogBox>,
-) -> u32 {
-    let entity_indexes = Indexes {
-        symbol: symbol.as_ref().map(|_| world.symbols.len()),
-        map_cell: map_cell.as_ref().map(|_| world.map.len()),
-        map_object: map_object.as_ref().map(|_| world.map_objects.len()),
-        character: character.as_ref().map(|_| world.characters.len()),
-        ai: ai.as_ref().map(|_| world.ais.len()),
-        item: item.as_ref().map(|_| world.items.len()),
-        equipment: equipment.as_ref().map(|_| world.equipments.len()),
-        ammo: ammo.as_ref().map(|_| world.ammos.len()),
-        log_message: log_message.as_ref().map(|_| world.log.len()),
-        dialog: dialog.as_ref().map(|_| world.dialogs.len()),
+/// Declares the components: a field of `Indexes` and a storage of `World` for
+/// each of them. To add a new component, add it here and add its storage to
+/// the `World`.
+macro_rules! components {
+    ($($component:ty => $index:ident in $storage:ident,)*) => {
+        #[derive(Clone, Copy, Debug, Serialize, Deserialize, Default)]
+        pub struct Indexes {
+            $(#[serde(default, skip_serializing_if = "Option::is_none")]
+            pub $index: Option<usize>,)*
+        }
+
+        impl Indexes {
+            fn is_empty(&self) -> bool {
+                true $(&& self.$index.is_none())*
+            }
+        }
+
+        $(impl Component for $component {
+            fn storage(world: &game::World) -> &Vec<Self> {
+                &world.$storage
+            }
+
+            fn storage_mut(world: &mut game::World) -> &mut Vec<Self> {
+                &mut world.$storage
+            }
+
+            unsafe fn storage_ptr(world: *mut game::World) -> *mut Vec<Self> {
+                ptr::addr_of_mut!((*world).$storage)
+            }
+
+            fn index(indexes: &Indexes) -> Option<usize> {
+                indexes.$index
+            }
+
+            fn index_mut(indexes: &mut Indexes) -> &mut Option<usize> {
+                &mut indexes.$index
+            }
+        })*
+
+        /// Drops the components that no entity refers to (of the removed entities,
+        /// popped dialogs, etc.) and the entities without components. The order of
+        /// the remaining components is kept, so the map stays a grid.
+        pub(super) fn compact(world: &mut game::World) {
+            $(compact_components::<$component>(world);)*
+            world.entity_indexes.retain(|_, indexes| !indexes.is_empty());
+        }
     };
-    symbol.map(|c| world.symbols.push(c));
-    map_cell.map(|c| world.map.push(c));
-    map_object.map(|c| world.map_objects.push(c));
-    character.map(|c| world.characters.push(c));
-    ai.map(|c| world.ais.push(c));
-    item.map(|c| world.items.push(c));
-    equipment.map(|c| world.equipments.push(c));
-    ammo.map(|c| world.ammos.push(c));
-    log_message.map(|c| world.log.push(c));
-    dialog.map(|c| world.dialogs.push(c));
-    world.id_count += 1;
-    world.entity_indexes.insert(world.id_count, entity_indexes);
-    world.id_count
 }
 
-pub struct Builder {
-    symbol: Option<Symbol>,
-    map_cell: Option<MapCell>,
-    map_object: Option<MapObject>,
-    character: Option<Character>,
-    ai: Option<AiOption>,
-    item: Option<Item>,
-    equipment: Option<Equipment>,
-    ammo: Option<Ammo>,
-    log_message: Option<LogMessage>,
-    dialog: Option<DialogBox>,
+components! {
+    Symbol => symbol in symbols,
+    MapCell => map_cell in map,
+    MapObject => map_object in map_objects,
+    Character => character in characters,
+    AiOption => ai in ais,
+    StatusEffects => status_effects in status_effects,
+    Item => item in items,
+    Equipment => equipment in equipments,
+    Ammo => ammo in ammos,
+    Stack => stack in stacks,
+    Stairs => stairs in stairs,
+    Feature => feature in features,
+    Trap => trap in traps,
+    LogMessage => log_message in log,
+    DialogBox => dialog in dialogs,
 }
 
-impl Builder {
-    pub(super) fn new() -> Self {
-        Builder {
-            symbol: None,
-            map_cell: None,
-            map_object: None,
-            character: None,
-            ai: None,
-            item: None,
-            equipment: None,
-            ammo: None,
-            log_message: None,
-            dialog: None,
+fn compact_components<C: Component>(world: &mut game::World) {
+    let mut components = mem::take(C::storage_mut(world));
+    let mut is_used = vec![false; components.len()];
+    // a slot referred twice (a dialog pushed after a popped one) belongs to the newest entity
+    for indexes in world.entity_indexes.values_mut().rev() {
+        let index = C::index_mut(indexes);
+        match *index {
+            Some(i) if (i < is_used.len()) && !is_used[i] => is_used[i] = true,
+            _ => *index = None,
         }
     }
-
-    pub fn create(self, world: &mut game::World) -> u32 {
-        create(
-            world,
-            self.symbol,
-            self.map_cell,
-            self.map_object,
-            self.character,
-            self.ai,
-            self.item,
-            self.equipment,
-            self.ammo,
-            self.log_message,
-            self.dialog,
-        )
+    let new_indexes: Vec<_> = is_used
+        .iter()
+        .scan(0, |new_index, &used| {
+            let index = *new_index;
+            *new_index += used as usize;
+            Some(index)
+        })
+        .collect();
+    for indexes in world.entity_indexes.values_mut() {
+        let index = C::index_mut(indexes);
+        *index = index.map(|i| new_indexes[i]);
     }
+    let mut used = is_used.into_iter();
+    components.retain(|_| used.next().unwrap());
+    *C::storage_mut(world) = components;
+}
 
-    pub fn add_symbol(mut self, symbol: Symbol) -> Self {
-        self.symbol = Some(symbol);
-        self
-    }
+type AddComponent = Box<dyn FnOnce(&mut game::World, &mut Indexes)>;
 
-    pub fn add_map_cell(mut self, map_cell: MapCell) -> Self {
-        self.map_cell = Some(map_cell);
-        self
-    }
+pub struct Builder {
+    components: Vec<AddComponent>,
+}
 
-    pub fn add_map_object(mut self, map_object: MapObject) -> Self {
-        self.map_object = Some(map_object);
-        self
+impl Builder {
+    pub(super) fn new() -> Self {
+        Builder { components: vec![] }
     }
 
-    pub fn add_character(mut self, character: Character) -> Self {
-        self.character = Some(character);
-        self
+    pub fn create(self, world: &mut game::World) -> u32 {
+        let mut entity_indexes = Indexes::default();
+        for add_component in self.components {
+            add_component(world, &mut entity_indexes);
+        }
+        world.id_count += 1;
+        let id = world.id_count;
+        world.entity_indexes.insert(id, entity_indexes);
+        if let Some(&Symbol { x, y, .. }) = world.get::<&Symbol>(id) {
+            world.spatial_index.insert(id, (x, y));
+        }
+        id
     }
 
-    pub fn add_ai(mut self, ai: AiOption) -> Self {
-        self.ai = Some(ai);
+    pub fn add<C: Component>(mut self, component: C) -> Self {
+        self.components.push(Box::new(move |world, indexes| {
+            let storage = C::storage_mut(world);
+            *C::index_mut(indexes) = Some(storage.len());
+            storage.push(component);
+        }));
         self
     }
 
-    pub fn add_item(mut self, item: Item) -> Self {
-        self.item = Some(item);
-        self
+    pub fn add_option<C: Component>(self, component: Option<C>) -> Self {
+        match component {
+            Some(component) => self.add(component),
+            None => self,
+        }
     }
+}
 
-    pub fn add_equipment(mut self, equipment: Option<Equipment>) -> Self {
-        self.equipment = equipment;
-        self
-    }
+#[cfg(test)]
+mod compact_tests {
+    use super::*;
+    use crate::cmtp::{DialogKind, Symbol};
 
-    pub fn add_ammo(mut self, ammo: Option<Ammo>) -> Self {
-        self.ammo = ammo;
-        self
+    fn spawn_symbol(world: &mut game::World, x: i32) -> u32 {
+        game::new_entity()
+            .add(Symbol {
+                x,
+                ..Default::default()
+            })
+            .create(world)
     }
 
-    pub fn add_log_message(mut self, message: LogMessage) -> Self {
-        self.log_message = Some(message);
-        self
+    #[test]
+    fn destroyed_entity_is_gone_and_compaction_frees_its_components() {
+        let mut world = game::World::default();
+        let first = spawn_symbol(&mut world, 1);
+        let second = spawn_symbol(&mut world, 2);
+        let third = spawn_symbol(&mut world, 3);
+        world.destroy_entity(second);
+        assert!(!world.entity_indexes.contains_key(&second));
+        assert!(world.get::<&Symbol>(second).is_none());
+        assert_eq!(world.symbols.len(), 3);
+        world.compact();
+        assert_eq!(world.symbols.len(), 2);
+        let x_of = |id| world.symbols[world.entity_indexes[&id].symbol.unwrap()].x;
+        assert_eq!((x_of(first), x_of(third)), (1, 3));
     }
 
-    pub fn add_dialog(mut self, dialog: DialogBox) -> Self {
-        self.dialog = Some(dialog);
-        self
+    #[test]
+    fn compaction_drops_popped_dialogs() {
+        let mut world = game::World::default();
+        world.add_dialog_box(DialogKind::MessageBox, String::new(), vec![], 10);
+        world.dialogs.pop();
+        world.add_dialog_box(DialogKind::Inventory, String::new(), vec![], 10);
+        world.compact();
+        assert_eq!(world.entity_indexes.len(), 1);
+        assert_eq!(world.dialogs.len(), 1);
+        assert_eq!(world.dialogs[0].kind, DialogKind::Inventory);
     }
 }
@@ -1,11 +1,16 @@
 use super::entity;
+use super::los;
+use super::query::{self, Query, QueryMut};
+use super::spatial::SpatialIndex;
 use crate::cfg;
 use crate::cmtp::{
-    AiOption, Ammo, Character, DialogBox, DialogKind, Equipment, Item, LogMessage, MapCell,
-    MapObject, Player, Slot, Symbol,
+    AiOption, Ammo, Character, DialogBox, DialogKind, Equipment, Feature, Item, LogMessage,
+    MapCell, MapObject, Player, Slot, Stack, Stairs, StatusEffects, StatusKind, Symbol, Trap,
 };
+use rand::{RngCore, SeedableRng as _};
+use rand_chacha::ChaCha20Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::btree_map::BTreeMap;
+use std::collections::{btree_map::BTreeMap, HashSet};
 use tcod::{colors, console};
 
 pub struct Tcod {
@@ -16,20 +21,44 @@ pub struct Tcod {
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct World {
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default)]
+    pub rng: WorldRng,
+    #[serde(skip)]
+    pub save_slot: usize,
+    /// the name of the game in its save slot, it's saved in the header
+    #[serde(skip)]
+    pub save_name: String,
+    #[serde(skip)]
+    pub spatial_index: SpatialIndex,
+    #[serde(skip)]
+    pub projectiles: Vec<los::Projectile>,
+    /// the tiles changed, e.g. a door was opened, so the FOV map must be made again
+    #[serde(skip)]
+    pub map_changed: bool,
     pub id_count: u32,
     pub entity_indexes: BTreeMap<u32, entity::Indexes>,
     pub player: Player,
     pub must_be_destroyed: bool,
     pub symbols: Vec<Symbol>,
+    #[serde(serialize_with = "super::save::packing::serialize_map")]
     pub map: Vec<MapCell>,
     pub map_objects: Vec<MapObject>,
     pub characters: Vec<Character>,
     pub ais: Vec<AiOption>,
+    pub status_effects: Vec<StatusEffects>,
     pub items: Vec<Item>,
     pub equipments: Vec<Equipment>,
     pub ammos: Vec<Ammo>,
+    pub stacks: Vec<Stack>,
+    pub stairs: Vec<Stairs>,
+    pub features: Vec<Feature>,
+    pub traps: Vec<Trap>,
     pub log: Vec<LogMessage>,
     pub dialogs: Vec<DialogBox>,
+    /// the visited levels by their depth, except the current one
+    pub levels: BTreeMap<u32, World>,
 }
 
 impl World {
@@ -38,77 +67,89 @@ impl World {
             .map_or(false, |(.., char, _)| char.alive)
     }
 
+    /// Components of the entity, e.g. `world.get::<(&Symbol, Option<&Character>)>(id)`,
+    /// or None if the entity doesn't have all the required ones.
+    pub fn get<'a, Q: Query<'a>>(&'a self, id: u32) -> Option<Q::Item> {
+        Q::fetch(self, self.entity_indexes.get(&id)?)
+    }
+
+    /// Mutable components of the entity, e.g. `world.get_mut::<(&Symbol, &mut Character)>(id)`.
+    pub fn get_mut<'a, Q: QueryMut<'a>>(&'a mut self, id: u32) -> Option<Q::Item> {
+        debug_assert!(
+            query::components_are_distinct::<Q>(),
+            "a component appears twice in the query"
+        );
+        let indexes = *self.entity_indexes.get(&id)?;
+        // every component is borrowed once and only through the returned item
+        unsafe { Q::fetch(self, &indexes) }
+    }
+
+    /// All the entities which have the components of the query, in the order of
+    /// their ids.
+    pub fn query<'a, Q: Query<'a>>(&'a self) -> impl Iterator<Item = (u32, Q::Item)> + 'a {
+        self.entity_indexes
+            .iter()
+            .filter_map(move |(&id, indexes)| Q::fetch(self, indexes).map(|item| (id, item)))
+    }
+
+    /// Like `query`, but the components may be borrowed mutably.
+    pub fn query_mut<'a, Q: QueryMut<'a>>(
+        &'a mut self,
+    ) -> impl Iterator<Item = (u32, Q::Item)> + 'a {
+        debug_assert!(
+            query::components_are_distinct::<Q>(),
+            "a component appears twice in the query"
+        );
+        let world: *mut World = self;
+        let mut borrowed_slots = HashSet::new();
+        let mut slots = vec![];
+        // the entity indexes are not a component, so they are never borrowed by the query
+        let entity_indexes = unsafe { &(*world).entity_indexes };
+        entity_indexes.iter().filter_map(move |(&id, indexes)| {
+            slots.clear();
+            Q::mut_slots(indexes, &mut slots);
+            // a slot of a removed entity can be still referred by it until the compaction
+            if slots.iter().any(|slot| borrowed_slots.contains(slot)) {
+                return None;
+            }
+            let item = unsafe { Q::fetch(world, indexes) }?;
+            borrowed_slots.extend(slots.drain(..));
+            Some((id, item))
+        })
+    }
+
     pub fn get_character(&self, id: u32) -> Option<(&Symbol, &MapObject, &Character, &AiOption)> {
-        let indexes = self
-            .entity_indexes
-            .get(&id)
-            .map(|i| (i.symbol, i.map_object, i.character, i.ai));
-        if let Some((Some(si), Some(moi), Some(ci), Some(aii))) = indexes {
-            Some((
-                &self.symbols[si],
-                &self.map_objects[moi],
-                &self.characters[ci],
-                &self.ais[aii],
-            ))
-        } else {
-            None
-        }
+        self.get::<(&Symbol, &MapObject, &Character, &AiOption)>(id)
     }
 
     pub fn get_character_mut(
         &mut self,
         id: u32,
     ) -> Option<(&mut Symbol, &mut MapObject, &mut Character, &mut AiOption)> {
-        let indexes = self
-            .entity_indexes
-            .get(&id)
-            .map(|i| (i.symbol, i.map_object, i.character, i.ai));
-        if let Some((Some(si), Some(moi), Some(ci), Some(aii))) = indexes {
-            Some((
-                &mut self.symbols[si],
-                &mut self.map_objects[moi],
-                &mut self.characters[ci],
-                &mut self.ais[aii],
-            ))
-        } else {
-            None
-        }
+        self.get_mut::<(&mut Symbol, &mut MapObject, &mut Character, &mut AiOption)>(id)
     }
 
     pub fn character_iter(
         &self,
     ) -> impl Iterator<Item = (u32, &Symbol, &MapObject, &Character, &AiOption)> {
-        self.entity_indexes.keys().filter_map(move |&id| {
-            self.get_character(id)
-                .map(|char| (id, char.0, char.1, char.2, char.3))
-        })
+        self.query::<(&Symbol, &MapObject, &Character, &AiOption)>()
+            .map(|(id, (sym, map_obj, char, ai))| (id, sym, map_obj, char, ai))
     }
 
     pub fn check_fov(&self, id: u32) -> bool {
         let &Symbol { x, y, .. } = self.get_map_obj(id).unwrap().0;
-        self.map[(y * cfg::MAP_WIDTH + x) as usize].in_fov
+        self.map[(y * cfg::settings().map.width + x) as usize].in_fov
     }
 
     pub fn get_map_obj(
         &self,
         id: u32,
     ) -> Option<(&Symbol, &MapObject, Option<&Character>, &MapCell)> {
-        let indexes = self
-            .entity_indexes
-            .get(&id)
-            .map(|i| (i.symbol, i.map_object, i.character));
-        if let Some((Some(si), Some(moi), ci)) = indexes {
-            let symbol = &self.symbols[si];
-            let index_in_map = (symbol.y * cfg::MAP_WIDTH + symbol.x) as usize;
-            Some((
-                symbol,
-                &self.map_objects[moi],
-                ci.map(|ci| &self.characters[ci]),
-                &self.map[index_in_map],
-            ))
-        } else {
-            None
-        }
+        self.get::<(&Symbol, &MapObject, Option<&Character>)>(id)
+            .map(|(symbol, map_obj, char)| {
+                let index_in_map = (symbol.y * cfg::settings().map.width + symbol.x) as usize;
+                (symbol, map_obj, char, &self.map[index_in_map])
+            })
     }
 
     pub fn map_obj_iter(
@@ -130,21 +171,13 @@ impl World {
         Option<&Equipment>,
         Option<&Ammo>,
     )> {
-        let indexes = self
-            .entity_indexes
-            .get(&id)
-            .map(|i| (i.symbol, i.map_object, i.item, i.equipment, i.ammo));
-        if let Some((Some(si), Some(moi), Some(ii), ei, ai)) = indexes {
-            Some((
-                &self.symbols[si],
-                &self.map_objects[moi],
-                &self.items[ii],
-                ei.map(|ei| &self.equipments[ei]),
-                ai.map(|ai| &self.ammos[ai]),
-            ))
-        } else {
-            None
-        }
+        self.get::<(
+            &Symbol,
+            &MapObject,
+            &Item,
+            Option<&Equipment>,
+            Option<&Ammo>,
+        )>(id)
     }
 
     pub fn get_item_mut(
@@ -157,23 +190,13 @@ impl World {
         Option<&mut Equipment>,
         Option<&mut Ammo>,
     )> {
-        let indexes = self
-            .entity_indexes
-            .get(&id)
-            .map(|i| (i.symbol, i.map_object, i.item, i.equipment, i.ammo));
-        if let Some((Some(si), Some(moi), Some(ii), ei, ai)) = indexes {
-            let equipments = &mut self.equipments;
-            let ammos = &mut self.ammos;
-            Some((
-                &mut self.symbols[si],
-                &mut self.map_objects[moi],
-                &mut self.items[ii],
-                ei.map(move |ei| &mut equipments[ei]),
-                ai.map(move |ai| &mut ammos[ai]),
-            ))
-        } else {
-            None
-        }
+        self.get_mut::<(
+            &mut Symbol,
+            &mut MapObject,
+            &mut Item,
+            Option<&mut Equipment>,
+            Option<&mut Ammo>,
+        )>(id)
     }
 
     pub fn item_iter(
@@ -188,9 +211,15 @@ impl World {
             Option<&Ammo>,
         ),
     > {
-        self.entity_indexes.keys().filter_map(move |&id| {
-            self.get_item(id)
-                .map(|item| (id, item.0, item.1, item.2, item.3, item.4))
+        self.query::<(
+            &Symbol,
+            &MapObject,
+            &Item,
+            Option<&Equipment>,
+            Option<&Ammo>,
+        )>()
+        .map(|(id, (sym, map_obj, item, equipment, ammo))| {
+            (id, sym, map_obj, item, equipment, ammo)
         })
     }
 
@@ -212,12 +241,81 @@ impl World {
             .0
     }
 
+    /// Removes the entity. Its components are left in the storages until the
+    /// next `compact`, on a level change or before a save.
+    pub fn destroy_entity(&mut self, id: u32) {
+        if let Some(&Symbol { x, y, .. }) = self.get::<&Symbol>(id) {
+            self.spatial_index.remove(id, (x, y));
+        }
+        self.entity_indexes.remove(&id);
+    }
+
+    /// Adds the component to the existing entity, or replaces the one it has.
+    pub fn add_component<C: entity::Component>(&mut self, id: u32, component: C) {
+        let index = match self.entity_indexes.get(&id) {
+            Some(indexes) => C::index(indexes),
+            None => return,
+        };
+        match index {
+            Some(index) => C::storage_mut(self)[index] = component,
+            None => {
+                let storage = C::storage_mut(self);
+                storage.push(component);
+                let index = storage.len() - 1;
+                *C::index_mut(self.entity_indexes.get_mut(&id).unwrap()) = Some(index);
+            }
+        }
+    }
+
+    /// Ids of the entities on the tile, in the order of their creation.
+    pub fn entities_at(&self, x: i32, y: i32) -> &[u32] {
+        self.spatial_index.at((x, y))
+    }
+
+    /// Ids of the entities not farther than the radius from the tile, in the
+    /// order of their creation.
+    pub fn entities_in_radius(&self, x: i32, y: i32, radius: f32) -> Vec<u32> {
+        let r = radius.max(0.0) as i32;
+        let mut ids: Vec<_> = (y - r..=y + r)
+            .flat_map(|tile_y| (x - r..=x + r).map(move |tile_x| (tile_x, tile_y)))
+            .filter(|&(tile_x, tile_y)| World::distance_to(x, y, tile_x, tile_y) <= radius)
+            .flat_map(|(tile_x, tile_y)| self.entities_at(tile_x, tile_y))
+            .cloned()
+            .collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Moves the entity to the tile. The position of a symbol must be changed
+    /// only this way, so that the spatial index stays up to date.
+    pub fn set_position(&mut self, id: u32, x: i32, y: i32) {
+        if let Some(symbol) = self.get_mut::<&mut Symbol>(id) {
+            let old_pos = (symbol.x, symbol.y);
+            symbol.x = x;
+            symbol.y = y;
+            self.spatial_index.remove(id, old_pos);
+            self.spatial_index.insert(id, (x, y));
+        }
+    }
+
+    /// Fills the spatial index from the symbols, e.g. after loading a game.
+    pub fn rebuild_spatial_index(&mut self) {
+        let mut spatial_index = SpatialIndex::default();
+        for (id, symbol) in self.query::<&Symbol>() {
+            spatial_index.insert(id, (symbol.x, symbol.y));
+        }
+        self.spatial_index = spatial_index;
+    }
+
+    /// Frees the components which are left by removed entities.
+    pub fn compact(&mut self) {
+        entity::compact(self);
+    }
+
     pub fn add_log(&mut self, color: colors::Color, message: impl Into<String>) {
         let msg = message.into();
         println!("game log: \"{}\"", &msg);
-        new_entity()
-            .add_log_message(LogMessage(msg, color))
-            .create(self);
+        new_entity().add(LogMessage(msg, color)).create(self);
     }
 
     pub fn add_dialog_box(
@@ -228,7 +326,7 @@ impl World {
         width: i32,
     ) {
         new_entity()
-            .add_dialog(DialogBox {
+            .add(DialogBox {
                 kind,
                 header,
                 options,
@@ -260,6 +358,25 @@ impl World {
         base_defense + bonus
     }
 
+    pub fn accuracy(&self, id: u32) -> i32 {
+        let base_accuracy = self
+            .get_character(id)
+            .map_or(0, |(.., ch, _)| ch.base_accuracy);
+        let bonus: i32 = self.get_all_equipped(id).map(|eq| eq.accuracy_bonus).sum();
+        base_accuracy + bonus
+    }
+
+    pub fn evasion(&self, id: u32) -> i32 {
+        self.get_character(id)
+            .map_or(0, |(.., ch, _)| ch.base_evasion)
+    }
+
+    /// The chance of a critical hit in percents.
+    pub fn crit_chance(&self, id: u32) -> i32 {
+        let bonus: i32 = self.get_all_equipped(id).map(|eq| eq.crit_bonus).sum();
+        cfg::settings().combat.crit_chance + bonus
+    }
+
     pub fn max_hp(&self, id: u32) -> i32 {
         let base_max_hp = self
             .get_character(id)
@@ -268,23 +385,55 @@ impl World {
         base_max_hp + bonus
     }
 
+    pub fn speed(&self, id: u32) -> i32 {
+        let base_speed = self.get_character(id).map_or(0, |(.., ch, _)| ch.speed);
+        let haste: i32 = self
+            .get::<&StatusEffects>(id)
+            .iter()
+            .flat_map(|effects| &effects.0)
+            .filter(|effect| effect.kind == StatusKind::Haste)
+            .map(|effect| effect.power)
+            .sum();
+        base_speed + haste
+    }
+
+    pub fn has_status(&self, id: u32, kind: StatusKind) -> bool {
+        self.get::<&StatusEffects>(id)
+            .filter(|effects| effects.0.iter().any(|effect| effect.kind == kind))
+            .is_some()
+    }
+
+    /// Energy an attack costs, it depends on the weapon.
+    pub fn attack_cost(&self, id: u32) -> i32 {
+        self.get_all_equipped(id)
+            .filter_map(|eq| eq.attack_cost)
+            .max()
+            .unwrap_or(cfg::settings().turns.attack_cost)
+    }
+
     pub fn get_equipped_in_slot(&self, slot: Slot) -> Option<u32> {
+        self.get_equipped_by(self.player.id, slot)
+    }
+
+    pub fn get_equipped_by(&self, owner: u32, slot: Slot) -> Option<u32> {
         self.item_iter().find_map(|(id, .., itm, eqp, _)| {
-            eqp.filter(|_| itm.owner == self.player.id)
+            eqp.filter(|_| itm.owner == owner)
                 .filter(|eqp| eqp.equipped && (eqp.slot == slot))
                 .and(Some(id))
         })
     }
 
     pub fn is_blocked(&self, x: i32, y: i32) -> bool {
-        let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
+        let index_in_map = (y * cfg::settings().map.width + x) as usize;
         // first test the map tile
         if self.map[index_in_map].block {
             return true;
         }
         // now check for any blocking objects
-        self.map_obj_iter()
-            .any(|(_, sym, map_obj, ..)| map_obj.block && ((sym.x, sym.y) == (x, y)))
+        self.entities_at(x, y)
+            .iter()
+            .filter_map(|&id| self.get::<&MapObject>(id))
+            .any(|map_obj| map_obj.block)
     }
 
     /// return the distance to another object
@@ -295,6 +444,79 @@ impl World {
     }
 }
 
+/// Random number generator of the world. It is reseeded from `World::seed` and
+/// the dungeon level every time a level is generated, so the same seed always
+/// gives the same level. It is saved with its position in the stream, so a
+/// loaded game goes on with the rolls it would have had without the reload.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(from = "RngState", into = "RngState")]
+pub struct WorldRng {
+    seed: u64,
+    rng: ChaCha20Rng,
+}
+
+/// What is saved of `WorldRng`.
+#[derive(Serialize, Deserialize)]
+struct RngState {
+    seed: u64,
+    word_pos: u64,
+}
+
+impl WorldRng {
+    pub fn for_level(seed: u64, level: u32) -> Self {
+        let level_seed = seed ^ (level as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        WorldRng::from(RngState {
+            seed: level_seed,
+            word_pos: 0,
+        })
+    }
+}
+
+impl From<RngState> for WorldRng {
+    fn from(state: RngState) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(state.seed);
+        // also fills the buffer of a new generator, `get_word_pos` fails without it
+        rng.set_word_pos(state.word_pos as u128);
+        WorldRng {
+            seed: state.seed,
+            rng,
+        }
+    }
+}
+
+impl From<WorldRng> for RngState {
+    fn from(world_rng: WorldRng) -> Self {
+        RngState {
+            seed: world_rng.seed,
+            word_pos: world_rng.rng.get_word_pos() as u64,
+        }
+    }
+}
+
+impl Default for WorldRng {
+    fn default() -> Self {
+        WorldRng::for_level(0, 0)
+    }
+}
+
+impl RngCore for WorldRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
 pub fn new_entity() -> entity::Builder {
     entity::Builder::new()
 }
@@ -0,0 +1,137 @@
+use super::game::World;
+use crate::cfg;
+use crate::cmtp::{Character, MapObject};
+use std::cmp;
+use tcod::colors::Color;
+
+/// The tiles of the straight line between the tiles by Bresenham's algorithm.
+/// Returns the tiles after `from` up to `to`.
+pub fn line(from: (i32, i32), to: (i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = ((to.0 - from.0).abs(), -(to.1 - from.1).abs());
+    let (step_x, step_y) = ((to.0 - from.0).signum(), (to.1 - from.1).signum());
+    let mut error = dx + dy;
+    let mut pos = from;
+    let mut tiles = Vec::with_capacity(cmp::max(dx, -dy) as usize);
+    while pos != to {
+        let doubled_error = 2 * error;
+        if doubled_error >= dy {
+            error += dy;
+            pos.0 += step_x;
+        }
+        if doubled_error <= dx {
+            error += dx;
+            pos.1 += step_y;
+        }
+        tiles.push(pos);
+    }
+    tiles
+}
+
+/// Whether nothing on the map blocks the sight between the tiles. The tiles
+/// themselves may block it, e.g. a monster in a doorway can be seen.
+pub fn in_line_of_sight(world: &World, from: (i32, i32), to: (i32, i32)) -> bool {
+    let map_width = cfg::settings().map.width;
+    let tiles = line(from, to);
+    tiles
+        .iter()
+        .take(tiles.len().saturating_sub(1))
+        .all(|&(x, y)| !world.map[(y * map_width + x) as usize].block_sight)
+}
+
+/// Where a projectile flew and whom it hit.
+#[derive(Debug, PartialEq)]
+pub struct Trace {
+    pub path: Vec<(i32, i32)>,
+    pub hit: Option<u32>,
+}
+
+/// Traces a projectile from the tile towards the target along the line. It
+/// stops before a wall, on the first blocking creature (it doesn't have to be
+/// the target), or on the target tile.
+pub fn trace(world: &World, from: (i32, i32), to: (i32, i32)) -> Trace {
+    let map_width = cfg::settings().map.width;
+    let mut path = vec![];
+    for (x, y) in line(from, to) {
+        if world.map[(y * map_width + x) as usize].block {
+            break;
+        }
+        path.push((x, y));
+        let hit = world.entities_at(x, y).iter().cloned().find(|&id| {
+            world
+                .get::<(&MapObject, &Character)>(id)
+                .filter(|(map_obj, _)| map_obj.block)
+                .is_some()
+        });
+        if hit.is_some() {
+            return Trace { path, hit };
+        }
+    }
+    Trace { path, hit: None }
+}
+
+/// A flying projectile to show, one tile of the path every frame.
+#[derive(Debug)]
+pub struct Projectile {
+    pub path: Vec<(i32, i32)>,
+    pub glyph: char,
+    pub color: Color,
+    pub frame: usize,
+}
+
+/// Shows the projectile flying along the path, if the animation is on.
+pub fn animate(world: &mut World, path: &[(i32, i32)], glyph: char, color: Color) {
+    if cfg::settings().gui.animate_projectiles && !path.is_empty() {
+        world.projectiles.push(Projectile {
+            path: path.to_vec(),
+            glyph,
+            color,
+            frame: 0,
+        });
+    }
+}
+
+#[cfg(test)]
+mod los_tests {
+    use super::*;
+    use crate::test_util::world_with_map;
+
+    #[test]
+    fn line_goes_from_one_tile_to_another() {
+        assert_eq!(line((1, 1), (4, 1)), vec![(2, 1), (3, 1), (4, 1)]);
+        assert_eq!(line((0, 0), (4, 2)), vec![(1, 1), (2, 1), (3, 2), (4, 2)]);
+        assert_eq!(line((3, 3), (1, 1)), vec![(2, 2), (1, 1)]);
+        assert!(line((2, 2), (2, 2)).is_empty());
+    }
+
+    #[test]
+    fn walls_block_the_sight() {
+        let world = world_with_map(&[
+            "#######", //
+            "#.....#", //
+            "#..#..#", //
+            "#######", //
+        ]);
+        assert!(in_line_of_sight(&world, (1, 1), (5, 1)));
+        assert!(!in_line_of_sight(&world, (1, 2), (5, 2)));
+        assert!(in_line_of_sight(&world, (1, 2), (3, 2)));
+    }
+
+    #[test]
+    fn projectile_stops_at_wall_and_first_creature() {
+        let world = world_with_map(&[
+            "########", //
+            "#...M.M#", //
+            "#..#...#", //
+            "########", //
+        ]);
+        let to_wall = trace(&world, (1, 2), (6, 2));
+        assert_eq!(to_wall.path, vec![(2, 2)]);
+        assert_eq!(to_wall.hit, None);
+        let to_creature = trace(&world, (1, 1), (6, 1));
+        assert_eq!(to_creature.path, vec![(2, 1), (3, 1), (4, 1)]);
+        assert_eq!(to_creature.hit, world.entities_at(4, 1).first().cloned());
+        let to_tile = trace(&world, (1, 1), (3, 1));
+        assert_eq!(to_tile.path.last(), Some(&(3, 1)));
+        assert_eq!(to_tile.hit, None);
+    }
+}
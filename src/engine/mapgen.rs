@@ -0,0 +1,539 @@
+use super::game::WorldRng;
+use crate::cfg;
+use crate::cmtp::MapCell;
+use rand::Rng as _;
+use std::cmp;
+use std::collections::VecDeque;
+
+/// A rectangle on the map, used to characterise a room.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        (self.x1 <= other.x2)
+            && (self.x2 >= other.x1)
+            && (self.y1 <= other.y2)
+            && (self.y2 >= other.y1)
+    }
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.x1 <= x) && (x <= self.x2) && (self.y1 <= y) && (y <= self.y2)
+    }
+}
+
+/// Digs a level into the map, that is all walls at first.
+pub trait MapGenerator {
+    /// Returns the rooms to put the monsters and items in. The player starts in
+    /// the center of the first one, the stairs down are in the center of the
+    /// last one, and every room can be reached from the others.
+    fn generate(&self, map: &mut [MapCell], rng: &mut WorldRng) -> Vec<Rect>;
+}
+
+/// The generator of the depth from the `dungeon.generators` table of the
+/// settings.
+pub fn for_level(level: u32) -> Box<dyn MapGenerator> {
+    let generator = cfg::settings()
+        .dungeon
+        .generators
+        .iter()
+        .rev()
+        .find(|choice| level >= choice.from_level)
+        .map_or(cfg::Generator::Rooms, |choice| choice.generator);
+    match generator {
+        cfg::Generator::Rooms => Box::new(Rooms),
+        cfg::Generator::Bsp => Box::new(Bsp),
+        cfg::Generator::Caves { fill, iterations } => Box::new(Caves { fill, iterations }),
+        cfg::Generator::Mines { floor } => Box::new(Mines { floor }),
+    }
+}
+
+/// Random rooms that don't overlap, joined by L-shaped tunnels.
+pub struct Rooms;
+
+impl MapGenerator for Rooms {
+    fn generate(&self, map: &mut [MapCell], rng: &mut WorldRng) -> Vec<Rect> {
+        let dungeon = &cfg::settings().dungeon;
+        let mut rooms: Vec<Rect> = vec![];
+        for _ in 0..dungeon.max_rooms {
+            // random width and height:
+            let w = rng.gen_range(dungeon.room_min_size, dungeon.room_max_size + 1);
+            let h = rng.gen_range(dungeon.room_min_size, dungeon.room_max_size + 1);
+            // random position without going out of the boundaries of the map
+            let x = rng.gen_range(0, cfg::settings().map.width - w);
+            let y = rng.gen_range(0, cfg::settings().map.height - h);
+            let new_room = Rect::new(x, y, w, h);
+            // run through the other rooms and see if they intersect with this one
+            let failed = rooms
+                .iter()
+                .any(|other_room| new_room.intersects_with(other_room));
+            if !failed {
+                create_room(new_room, map);
+                if let Some(prev_room) = rooms.last() {
+                    connect(prev_room.center(), new_room.center(), map, rng);
+                }
+                rooms.push(new_room);
+            }
+        }
+        rooms
+    }
+}
+
+/// The map is split in two again and again, until the parts are small enough
+/// for a room. Each room is joined to the rooms of the other half.
+pub struct Bsp;
+
+impl Bsp {
+    /// Makes the rooms of the area, returns the center of one of them.
+    fn split(
+        &self,
+        area: Rect,
+        map: &mut [MapCell],
+        rng: &mut WorldRng,
+        rooms: &mut Vec<Rect>,
+    ) -> (i32, i32) {
+        let dungeon = &cfg::settings().dungeon;
+        let (min, max) = (dungeon.room_min_size, dungeon.room_max_size);
+        let (w, h) = (area.x2 - area.x1, area.y2 - area.y1);
+        let split_x = (w > max) && (w >= 2 * min);
+        let split_y = (h > max) && (h >= 2 * min);
+        if !split_x && !split_y {
+            let room_w = rng.gen_range(cmp::min(min, w), cmp::min(max, w) + 1);
+            let room_h = rng.gen_range(cmp::min(min, h), cmp::min(max, h) + 1);
+            let x = rng.gen_range(area.x1, area.x2 - room_w + 1);
+            let y = rng.gen_range(area.y1, area.y2 - room_h + 1);
+            let room = Rect::new(x, y, room_w, room_h);
+            create_room(room, map);
+            rooms.push(room);
+            return room.center();
+        }
+        // along the longer side, if both can be split
+        let (first, second) = if split_x && (!split_y || (w >= h)) {
+            let at = rng.gen_range(area.x1 + min, area.x2 - min + 1);
+            (Rect { x2: at, ..area }, Rect { x1: at, ..area })
+        } else {
+            let at = rng.gen_range(area.y1 + min, area.y2 - min + 1);
+            (Rect { y2: at, ..area }, Rect { y1: at, ..area })
+        };
+        let first_center = self.split(first, map, rng, rooms);
+        let second_center = self.split(second, map, rng, rooms);
+        connect(first_center, second_center, map, rng);
+        first_center
+    }
+}
+
+impl MapGenerator for Bsp {
+    fn generate(&self, map: &mut [MapCell], rng: &mut WorldRng) -> Vec<Rect> {
+        let map_size = &cfg::settings().map;
+        let mut rooms = vec![];
+        let whole_map = Rect::new(0, 0, map_size.width - 1, map_size.height - 1);
+        self.split(whole_map, map, rng, &mut rooms);
+        rooms
+    }
+}
+
+/// Cellular automata caves: random walls, `fill` percents of the map, are
+/// smoothed for the `iterations`. Only the biggest cave is kept.
+pub struct Caves {
+    pub fill: i32,
+    pub iterations: u32,
+}
+
+impl MapGenerator for Caves {
+    fn generate(&self, map: &mut [MapCell], rng: &mut WorldRng) -> Vec<Rect> {
+        let map_size = &cfg::settings().map;
+        let (width, height) = (map_size.width, map_size.height);
+        let is_border =
+            |x: i32, y: i32| (x == 0) || (y == 0) || (x == width - 1) || (y == height - 1);
+        let mut walls: Vec<bool> = (0..width * height)
+            .map(|i| is_border(i % width, i / width) || (rng.gen_range(0, 100) < self.fill))
+            .collect();
+        for _ in 0..self.iterations {
+            walls = (0..width * height)
+                .map(|i| {
+                    let (x, y) = (i % width, i / width);
+                    if is_border(x, y) {
+                        return true;
+                    }
+                    // a wall stays or grows if most of the tiles around are walls
+                    let walls_around = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                        .filter(|&(x, y)| walls[(y * width + x) as usize])
+                        .count();
+                    walls_around >= 5
+                })
+                .collect();
+        }
+        for (i, &wall) in walls.iter().enumerate() {
+            if !wall {
+                dig(map, ((i as i32) % width, (i as i32) / width));
+            }
+        }
+        keep_biggest_area(map);
+        match random_floor(map, rng) {
+            Some(start) => rooms_around(start, map, rng),
+            // all walls, the fill is too high
+            None => Rooms.generate(map, rng),
+        }
+    }
+}
+
+/// Mine shafts of the drunkard's walk: a miner walks straight for a few tiles,
+/// then turns, and sometimes digs a chamber, until `floor` percents of the map
+/// are dug.
+pub struct Mines {
+    pub floor: i32,
+}
+
+impl MapGenerator for Mines {
+    fn generate(&self, map: &mut [MapCell], rng: &mut WorldRng) -> Vec<Rect> {
+        let map_size = &cfg::settings().map;
+        let (width, height) = (map_size.width, map_size.height);
+        let to_dig = (width * height * self.floor / 100) as usize;
+        let start = (width / 2, height / 2);
+        let mut pos = start;
+        dig(map, pos);
+        let mut dug = 1;
+        // the map may be too small for the floor wanted
+        let mut steps_left = width * height * 10;
+        while (dug < to_dig) && (steps_left > 0) {
+            let (dx, dy) = [(1, 0), (-1, 0), (0, 1), (0, -1)][rng.gen_range(0, 4)];
+            for _ in 0..rng.gen_range(2, 8) {
+                let (x, y) = (pos.0 + dx, pos.1 + dy);
+                if (x < 1) || (y < 1) || (x > width - 2) || (y > height - 2) {
+                    break;
+                }
+                pos = (x, y);
+                if map[index(pos)].block {
+                    dig(map, pos);
+                    dug += 1;
+                }
+                steps_left -= 1;
+            }
+            if rng.gen_range(0, 20) == 0 {
+                let chamber = Rect::new(pos.0 - 2, pos.1 - 2, 4, 4);
+                let fits = (chamber.x1 >= 0)
+                    && (chamber.y1 >= 0)
+                    && (chamber.x2 < width)
+                    && (chamber.y2 < height);
+                if fits {
+                    create_room(chamber, map);
+                }
+            }
+        }
+        rooms_around(start, map, rng)
+    }
+}
+
+/// Rooms around the tiles of a map without rooms: the first one at the start,
+/// the last one at the floor farthest from it, and a few random ones.
+fn rooms_around(start: (i32, i32), map: &[MapCell], rng: &mut WorldRng) -> Vec<Rect> {
+    let distances = distances_from(map, start);
+    let exit = distances
+        .iter()
+        .enumerate()
+        .filter_map(|(i, distance)| distance.map(|distance| (i, distance)))
+        .max_by_key(|&(_, distance)| distance)
+        .map_or(start, |(i, _)| position(i));
+    let radius = cfg::settings().dungeon.room_min_size / 2;
+    let mut rooms = vec![room_around(start, radius)];
+    for _ in 0..cfg::settings().dungeon.max_rooms / 2 {
+        if let Some(pos) = random_floor(map, rng) {
+            rooms.push(room_around(pos, radius));
+        }
+    }
+    rooms.push(room_around(exit, radius));
+    rooms
+}
+
+/// The biggest room around the tile, that is inside the map and has the tile
+/// in its center.
+fn room_around((x, y): (i32, i32), radius: i32) -> Rect {
+    let map_size = &cfg::settings().map;
+    let radius = [
+        radius,
+        x,
+        y,
+        map_size.width - 1 - x,
+        map_size.height - 1 - y,
+    ]
+    .iter()
+    .cloned()
+    .min()
+    .unwrap();
+    Rect::new(x - radius, y - radius, 2 * radius, 2 * radius)
+}
+
+fn random_floor(map: &[MapCell], rng: &mut WorldRng) -> Option<(i32, i32)> {
+    let floor: Vec<_> = (0..map.len()).filter(|&i| !map[i].block).collect();
+    if floor.is_empty() {
+        None
+    } else {
+        Some(position(floor[rng.gen_range(0, floor.len())]))
+    }
+}
+
+/// Walls up all the floor that can't be reached from the biggest area.
+fn keep_biggest_area(map: &mut [MapCell]) {
+    let mut area_of = vec![None; map.len()];
+    let mut areas = vec![];
+    for i in 0..map.len() {
+        if !map[i].block && area_of[i].is_none() {
+            let distances = distances_from(map, position(i));
+            let tiles: Vec<_> = (0..map.len()).filter(|&i| distances[i].is_some()).collect();
+            for &tile in &tiles {
+                area_of[tile] = Some(areas.len());
+            }
+            areas.push(tiles);
+        }
+    }
+    let biggest = (0..areas.len()).max_by_key(|&area| areas[area].len());
+    for (area, tiles) in areas.iter().enumerate() {
+        if Some(area) != biggest {
+            for &tile in tiles {
+                map[tile].block = true;
+                map[tile].block_sight = true;
+            }
+        }
+    }
+}
+
+/// Steps to every tile from the one over the floor, `None` for the ones that
+/// can't be reached.
+pub fn distances_from(map: &[MapCell], from: (i32, i32)) -> Vec<Option<u32>> {
+    let map_size = &cfg::settings().map;
+    let mut distances = vec![None; map.len()];
+    if map[index(from)].block {
+        return distances;
+    }
+    distances[index(from)] = Some(0);
+    let mut queue = VecDeque::new();
+    queue.push_back(from);
+    while let Some((x, y)) = queue.pop_front() {
+        let distance = distances[index((x, y))].unwrap();
+        for &(dx, dy) in &[(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let next = (x + dx, y + dy);
+            let inside = (next.0 >= 0)
+                && (next.1 >= 0)
+                && (next.0 < map_size.width)
+                && (next.1 < map_size.height);
+            if inside && !map[index(next)].block && distances[index(next)].is_none() {
+                distances[index(next)] = Some(distance + 1);
+                queue.push_back(next);
+            }
+        }
+    }
+    distances
+}
+
+/// The tiles that can't be reached from the start, e.g. the stairs or the items
+/// that the generator cut off.
+pub fn unreachable(map: &[MapCell], start: (i32, i32), tiles: &[(i32, i32)]) -> Vec<(i32, i32)> {
+    let distances = distances_from(map, start);
+    tiles
+        .iter()
+        .cloned()
+        .filter(|&tile| distances[index(tile)].is_none())
+        .collect()
+}
+
+/// Digs tunnels from the tiles that can't be reached from the start to the
+/// nearest floor that can, so that every tile can be reached after it.
+pub fn join_unreachable(
+    map: &mut [MapCell],
+    start: (i32, i32),
+    tiles: &[(i32, i32)],
+    rng: &mut WorldRng,
+) {
+    dig(map, start);
+    while let Some(&tile) = unreachable(map, start, tiles).first() {
+        let distances = distances_from(map, start);
+        let distance = |(x, y): (i32, i32)| (x - tile.0).abs() + (y - tile.1).abs();
+        let nearest = (0..map.len())
+            .filter(|&i| distances[i].is_some())
+            .map(position)
+            .min_by_key(|&pos| distance(pos))
+            .unwrap();
+        connect(tile, nearest, map, rng);
+    }
+}
+
+/// Digs a tunnel from the room to the floor outside it nearest to its center,
+/// e.g. for a room made on top of a generated map. The tunnel starts at the
+/// floor of the room nearest to the outside one, so it doesn't cut through the
+/// walls inside the room.
+pub fn connect_to_floor(room: Rect, map: &mut [MapCell], rng: &mut WorldRng) {
+    let distance =
+        |(x, y): (i32, i32), (to_x, to_y): (i32, i32)| (x - to_x).abs() + (y - to_y).abs();
+    let floor = (0..map.len()).filter(|&i| !map[i].block).map(position);
+    let nearest = floor
+        .clone()
+        .filter(|&pos| !room.contains(pos))
+        .min_by_key(|&pos| distance(pos, room.center()));
+    if let Some(nearest) = nearest {
+        let from = floor
+            .filter(|&pos| room.contains(pos))
+            .min_by_key(|&pos| distance(pos, nearest))
+            .unwrap_or_else(|| room.center());
+        connect(from, nearest, map, rng);
+    }
+}
+
+/// The floor between two walls with the floor on the other sides, like a
+/// doorway or a narrow shaft.
+pub fn is_chokepoint(map: &[MapCell], (x, y): (i32, i32)) -> bool {
+    let map_size = &cfg::settings().map;
+    if (x < 1) || (y < 1) || (x > map_size.width - 2) || (y > map_size.height - 2) {
+        return false;
+    }
+    let is_wall = |x: i32, y: i32| map[index((x, y))].block;
+    !is_wall(x, y)
+        && ((is_wall(x - 1, y) && is_wall(x + 1, y) && !is_wall(x, y - 1) && !is_wall(x, y + 1))
+            || (is_wall(x, y - 1) && is_wall(x, y + 1) && !is_wall(x - 1, y) && !is_wall(x + 1, y)))
+}
+
+/// Joins the tiles with an L-shaped tunnel.
+pub fn connect(
+    (from_x, from_y): (i32, i32),
+    (to_x, to_y): (i32, i32),
+    map: &mut [MapCell],
+    rng: &mut WorldRng,
+) {
+    // toss a coin (random bool value -- either true or false)
+    if rng.gen() {
+        // first move horizontally, then vertically
+        create_h_tunnel(from_x, to_x, from_y, map);
+        create_v_tunnel(from_y, to_y, to_x, map);
+    } else {
+        // first move vertically, then horizontally
+        create_v_tunnel(from_y, to_y, from_x, map);
+        create_h_tunnel(from_x, to_x, to_y, map);
+    }
+}
+
+pub fn create_room(room: Rect, map: &mut [MapCell]) {
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            dig(map, (x, y));
+        }
+    }
+}
+
+fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut [MapCell]) {
+    for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
+        dig(map, (x, y));
+    }
+}
+
+fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut [MapCell]) {
+    for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
+        dig(map, (x, y));
+    }
+}
+
+pub fn dig(map: &mut [MapCell], pos: (i32, i32)) {
+    let cell = &mut map[index(pos)];
+    cell.block = false;
+    cell.block_sight = false;
+}
+
+fn index((x, y): (i32, i32)) -> usize {
+    (y * cfg::settings().map.width + x) as usize
+}
+
+fn position(index: usize) -> (i32, i32) {
+    let width = cfg::settings().map.width;
+    ((index as i32) % width, (index as i32) / width)
+}
+
+#[cfg(test)]
+mod mapgen_tests {
+    use super::*;
+
+    fn walls() -> Vec<MapCell> {
+        let map_size = &cfg::settings().map;
+        (0..map_size.width * map_size.height)
+            .map(|_| MapCell {
+                block: true,
+                explored: false,
+                block_sight: true,
+                in_fov: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_generator_joins_its_rooms() {
+        let generators: Vec<Box<dyn MapGenerator>> = vec![
+            Box::new(Rooms),
+            Box::new(Bsp),
+            Box::new(Caves {
+                fill: 45,
+                iterations: 4,
+            }),
+            Box::new(Mines { floor: 35 }),
+        ];
+        for (n, generator) in generators.iter().enumerate() {
+            for seed in 0..5 {
+                let mut map = walls();
+                let mut rng = WorldRng::for_level(seed, 1);
+                let rooms = generator.generate(&mut map, &mut rng);
+                assert!(rooms.len() > 1, "generator {} made {:?}", n, rooms);
+                let distances = distances_from(&map, rooms[0].center());
+                for room in &rooms {
+                    assert!(
+                        distances[index(room.center())].is_some(),
+                        "generator {} with seed {} left {:?} apart",
+                        n,
+                        seed,
+                        room
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn room_on_top_is_connected_to_floor() {
+        let mut map = walls();
+        let mut rng = WorldRng::for_level(1, 1);
+        let first = Rect::new(2, 2, 5, 5);
+        let second = Rect::new(20, 10, 5, 5);
+        create_room(first, &mut map);
+        create_room(second, &mut map);
+        assert!(distances_from(&map, first.center())[index(second.center())].is_none());
+        connect_to_floor(second, &mut map, &mut rng);
+        assert!(distances_from(&map, first.center())[index(second.center())].is_some());
+    }
+
+    #[test]
+    fn unreachable_tiles_are_joined() {
+        let mut map = walls();
+        let mut rng = WorldRng::for_level(1, 1);
+        let start = (4, 4);
+        create_room(Rect::new(2, 2, 5, 5), &mut map);
+        create_room(Rect::new(30, 20, 5, 5), &mut map);
+        // one in the other room, one in the rock
+        let tiles = [(4, 5), (32, 22), (50, 8)];
+        assert_eq!(unreachable(&map, start, &tiles), vec![(32, 22), (50, 8)]);
+        join_unreachable(&mut map, start, &tiles, &mut rng);
+        assert!(unreachable(&map, start, &tiles).is_empty());
+    }
+}
@@ -1,6 +1,11 @@
-use super::game;
+use super::{game, los};
 use crate::cfg;
-use crate::cmtp::{Character, Slot};
+use crate::cmtp::{
+    Character, Effect, Feature, ItemKind, MapObject, Slot, Stack, StatusEffect, StatusEffects,
+    StatusKind, Symbol, Trap,
+};
+use rand::Rng;
+use std::cmp;
 
 pub fn take_damage(target: &mut Character, damage: i32) -> Option<i32> {
     // apply damage if possible
@@ -16,44 +21,280 @@ pub fn take_damage(target: &mut Character, damage: i32) -> Option<i32> {
     }
 }
 
+/// A melee attack.
 pub fn attack_by(attacker_id: u32, target_id: u32, world: &mut game::World) {
+    spend_energy(attacker_id, world.attack_cost(attacker_id), world);
+    hit(attacker_id, target_id, world);
+}
+
+/// A ranged attack. The target must be within the range and in the line of
+/// sight. If the attacker has a ranged weapon, it needs the fitting ammo, and
+/// one is consumed. The shot hits the first creature on its way. Returns why
+/// the shot can't be made otherwise.
+pub fn shoot_by(
+    attacker_id: u32,
+    target_id: u32,
+    range: i32,
+    world: &mut game::World,
+) -> Result<(), &'static str> {
+    let &Symbol { x, y, .. } = world.get_character(attacker_id).unwrap().0;
+    let &Symbol {
+        x: target_x,
+        y: target_y,
+        ..
+    } = world.get_character(target_id).unwrap().0;
+    if game::World::distance_to(x, y, target_x, target_y) > range as f32 {
+        return Err("The target is out of range.");
+    }
+    if !los::in_line_of_sight(world, (x, y), (target_x, target_y)) {
+        return Err("There is no clear shot.");
+    }
+    let weapon_ammo_kind =
+        world
+            .get_equipped_by(attacker_id, Slot::Hands)
+            .and_then(|id| match world.get_item(id).unwrap().2.kind {
+                ItemKind::Ranged(ammo_kind) => Some(ammo_kind),
+                _ => None,
+            });
+    let ammo_id = match weapon_ammo_kind {
+        Some(ammo_kind) => Some(
+            world
+                .get_equipped_by(attacker_id, Slot::Ammo)
+                .filter(|&id| {
+                    world.get_item(id).unwrap().4.map(|ammo| ammo.kind) == Some(ammo_kind)
+                })
+                .ok_or("There is no ammo for the weapon.")?,
+        ),
+        // e.g. a monster that spits
+        None => None,
+    };
+    spend_energy(attacker_id, world.attack_cost(attacker_id), world);
+    let trace = los::trace(world, (x, y), (target_x, target_y));
+    let (glyph, color) = ammo_id
+        .map(|id| world.get_item(id).unwrap().0)
+        .map_or(('*', cfg::settings().colors.lightest_grey), |symbol| {
+            (symbol.glyph, symbol.color)
+        });
+    los::animate(world, &trace.path, glyph, color);
+    match trace.hit {
+        // it may be someone in the way, not the target
+        Some(hit_id) => hit(attacker_id, hit_id, world),
+        None => {
+            let attacker_name = world.get_character(attacker_id).unwrap().1.name.clone();
+            world.add_log(
+                cfg::settings().colors.lightest_grey,
+                format!("{} misses.", attacker_name),
+            );
+        }
+    }
+    if let Some(ammo_id) = ammo_id {
+        // the ammo without a stack is a single shot
+        let count_left = world.get_mut::<&mut Stack>(ammo_id).map_or(0, |stack| {
+            stack.count = stack.count.saturating_sub(1);
+            stack.count
+        });
+        if count_left == 0 {
+            world.destroy_entity(ammo_id);
+            if attacker_id == world.player.id {
+                world.add_log(cfg::settings().colors.orange, "Ammo is over");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How an attack turned out.
+#[derive(Debug, PartialEq)]
+pub enum AttackRoll {
+    Miss,
+    Hit { damage: i32, crit: bool },
+}
+
+/// Rolls to hit, for the damage and for a critical hit, see `cfg::Combat`.
+pub fn roll_attack(
+    rng: &mut impl Rng,
+    (power, accuracy, crit_chance): (i32, i32, i32),
+    (defense, evasion): (i32, i32),
+) -> AttackRoll {
+    let combat = &cfg::settings().combat;
+    let hit_chance = cmp::max(
+        combat.hit_chance + (accuracy - evasion) * combat.hit_chance_per_point,
+        combat.min_hit_chance,
+    );
+    if rng.gen_range(0, 100) >= hit_chance {
+        return AttackRoll::Miss;
+    }
+    // the power can go below zero with the bonuses, it does not vary then
+    let variance = cmp::max(power * combat.damage_variance / 100, 0);
+    let mut power = power + rng.gen_range(-variance, variance + 1);
+    let crit = rng.gen_range(0, 100) < crit_chance;
+    if crit {
+        power = (power as f32 * combat.crit_multiplier) as i32;
+    }
+    let mut damage = power - defense;
+    if (damage <= 0) && (rng.gen_range(0, 100) < combat.min_damage_chance) {
+        // it finds a gap in the armor
+        damage = 1;
+    }
+    AttackRoll::Hit { damage, crit }
+}
+
+/// Deals the damage of the attacker to the target, melee and ranged attacks
+/// alike.
+fn hit(attacker_id: u32, target_id: u32, world: &mut game::World) {
     let attacker_name = world.get_character(attacker_id).unwrap().1.name.clone();
     let target_name = world.get_character(target_id).unwrap().1.name.clone();
-    // a simple formula for attack damage
-    let damage = world.power(attacker_id) - world.defense(target_id);
-    if damage > 0 {
-        world.add_log(
-            cfg::COLOR_LIGHTEST_GREY,
-            format!(
-                "{} attacks {} for {} hit points.",
-                attacker_name, target_name, damage
-            ),
-        );
-        let target_char = world.get_character_mut(target_id).unwrap().2;
-        if let Some(xp) = take_damage(target_char, damage) {
-            // yield experience to the player
-            world.get_character_mut(attacker_id).unwrap().2.xp += xp;
+    let attack = (
+        world.power(attacker_id),
+        world.accuracy(attacker_id),
+        world.crit_chance(attacker_id),
+    );
+    let defense = (world.defense(target_id), world.evasion(target_id));
+    match roll_attack(&mut world.rng, attack, defense) {
+        AttackRoll::Miss => world.add_log(
+            cfg::settings().colors.lightest_grey,
+            format!("{} misses {}.", attacker_name, target_name),
+        ),
+        AttackRoll::Hit { damage, crit } if damage > 0 => {
+            let msg = if crit {
+                format!(
+                    "{} critically hits {} for {} hit points!",
+                    attacker_name, target_name, damage
+                )
+            } else {
+                format!(
+                    "{} attacks {} for {} hit points.",
+                    attacker_name, target_name, damage
+                )
+            };
+            world.add_log(cfg::settings().colors.lightest_grey, msg);
+            let target_char = world.get_character_mut(target_id).unwrap().2;
+            if let Some(xp) = take_damage(target_char, damage) {
+                // yield experience to the player
+                world.get_character_mut(attacker_id).unwrap().2.xp += xp;
+            } else {
+                // the weapon and the ammo may poison, etc.
+                let inflicted: Vec<_> = world
+                    .item_iter()
+                    .filter(|(.., item, eqp, _)| {
+                        (item.owner == attacker_id) && eqp.filter(|eqp| eqp.equipped).is_some()
+                    })
+                    .flat_map(|(.., item, _, _)| item.inflicts.clone())
+                    .collect();
+                let by_player = attacker_id == world.player.id;
+                for effect in inflicted {
+                    add_status_effect(
+                        target_id,
+                        StatusEffect {
+                            by_player,
+                            ..effect
+                        },
+                        world,
+                    );
+                }
+            }
         }
-    } else {
-        world.add_log(
-            cfg::COLOR_LIGHTEST_GREY,
+        AttackRoll::Hit { .. } => world.add_log(
+            cfg::settings().colors.lightest_grey,
             format!(
                 "{} attacks {} but it has no effect!",
                 attacker_name, target_name
             ),
+        ),
+    }
+}
+
+/// Puts the effect on the character. The same effects stack: poison doses add
+/// up, every wound bleeds on its own, the others last for the longest time.
+pub fn add_status_effect(id: u32, effect: StatusEffect, world: &mut game::World) {
+    let name = match world.get_character(id) {
+        Some((_, map_obj, char, _)) if char.alive => map_obj.name.clone(),
+        _ => return,
+    };
+    if world.get::<&StatusEffects>(id).is_none() {
+        world.add_component(id, StatusEffects::default());
+    }
+    let effects = &mut world.get_mut::<&mut StatusEffects>(id).unwrap().0;
+    let same = effects
+        .iter_mut()
+        .find(|other| (other.kind == effect.kind) && (effect.kind != StatusKind::Bleeding));
+    match same {
+        Some(same) if effect.kind == StatusKind::Poison => {
+            same.turns = same.turns.max(effect.turns);
+            same.power += effect.power;
+            same.by_player |= effect.by_player;
+        }
+        Some(same) => {
+            same.turns = same.turns.max(effect.turns);
+            same.power = same.power.max(effect.power);
+            same.by_player |= effect.by_player;
+        }
+        None => effects.push(effect),
+    }
+    if (id == world.player.id) || world.check_fov(id) {
+        world.add_log(
+            cfg::settings().colors.orange,
+            format!("{} is {}!", name, effect.kind),
         );
     }
-    let remaining_ammo = world
-        .get_equipped_in_slot(Slot::Ammo)
-        .and_then(|id| world.get_item_mut(id).map(|item| (id, item)))
-        .and_then(|(id, (.., ammo))| ammo.map(|ammo| (id, ammo)))
-        .map(|(id, ammo)| {
-            ammo.count -= 1;
-            (id, ammo.count)
-        });
-    if let Some((ammo_id, 0)) = remaining_ammo {
-        world.entity_indexes.remove(&ammo_id);
-        world.add_log(cfg::COLOR_ORANGE, "Ammo is over");
+}
+
+/// Heals, hurts or puts the status effect on the character, e.g. of a used
+/// item or a trap. The player gets the XP for a kill only if they caused it.
+pub fn apply_effect(target_id: u32, effect: Effect, by_player: bool, world: &mut game::World) {
+    let target_name = world.get_character(target_id).unwrap().1.name.clone();
+    let is_seen = (target_id == world.player.id) || world.check_fov(target_id);
+    match effect {
+        Effect::Heal(amount) => {
+            if target_id == world.player.id {
+                world.add_log(
+                    cfg::settings().colors.green,
+                    "Your wounds start to feel better!",
+                );
+            } else if is_seen {
+                world.add_log(
+                    cfg::settings().colors.lightest_grey,
+                    format!("The {} looks better.", target_name),
+                );
+            }
+            heal(target_id, amount, world);
+        }
+        Effect::Damage(damage) => {
+            if is_seen {
+                world.add_log(
+                    cfg::settings().colors.lightest_grey,
+                    format!(
+                        "The {} gets damaged for {} hit points.",
+                        target_name, damage
+                    ),
+                );
+            }
+            let target = world.get_character_mut(target_id).unwrap().2;
+            if let Some(xp) = take_damage(target, damage) {
+                // Don't reward the player for burning themself!
+                if by_player && (target_id != world.player.id) {
+                    world.player_char_mut().xp += xp;
+                }
+            }
+        }
+        Effect::Status(status_effect) => add_status_effect(
+            target_id,
+            StatusEffect {
+                by_player,
+                ..status_effect
+            },
+            world,
+        ),
+    }
+}
+
+/// heal by the given amount, without going over the maximum
+pub fn heal(id: u32, amount: i32, world: &mut game::World) {
+    let max_hp = world.max_hp(id);
+    let character = world.get_character_mut(id).unwrap().2;
+    character.hp += amount;
+    if character.hp > max_hp {
+        character.hp = max_hp;
     }
 }
 
@@ -65,11 +306,14 @@ pub fn equip(id: u32, world: &mut game::World) {
         if !equipment.equipped {
             equipment.equipped = true;
             let slot = equipment.slot;
-            world.add_log(cfg::COLOR_GREEN, format!("Equipped {} on {}.", name, slot));
+            world.add_log(
+                cfg::settings().colors.green,
+                format!("Equipped {} on {}.", name, slot),
+            );
         }
     } else {
         world.add_log(
-            cfg::COLOR_ORANGE,
+            cfg::settings().colors.orange,
             format!("Can't equip {} because it's not an Equipment.", name),
         );
     }
@@ -81,8 +325,321 @@ pub fn move_by(id: u32, dx: i32, dy: i32, world: &mut game::World) {
     let dy = dy.signum();
     let symbol = world.get_character(id).unwrap().0;
     let (x, y) = (symbol.x, symbol.y);
-    if !world.is_blocked(x + dx, y + dy) {
-        world.get_character_mut(id).unwrap().0.x = x + dx;
-        world.get_character_mut(id).unwrap().0.y = y + dy;
+    // bumping into a closed door opens it, unless it's the gate of a lever
+    let closed_door = world
+        .entities_at(x + dx, y + dy)
+        .iter()
+        .cloned()
+        .find(|&door_id| {
+            world.get::<&Feature>(door_id)
+                == Some(&Feature::Door {
+                    open: false,
+                    group: None,
+                })
+        });
+    if let Some(door_id) = closed_door {
+        let _ = set_door_open(door_id, true, world);
+        spend_energy(id, cfg::settings().turns.move_cost, world);
+    } else if !world.is_blocked(x + dx, y + dy) {
+        world.set_position(id, x + dx, y + dy);
+        spend_energy(id, cfg::settings().turns.move_cost, world);
+        step_on_traps(id, world);
+    }
+}
+
+/// Triggers the traps under the character. A triggered trap is found by the
+/// player, if they see it.
+pub fn step_on_traps(id: u32, world: &mut game::World) {
+    let &Symbol { x, y, .. } = world.get::<&Symbol>(id).unwrap();
+    let trap_ids: Vec<_> = world
+        .entities_at(x, y)
+        .iter()
+        .cloned()
+        .filter(|&trap_id| world.get::<&Trap>(trap_id).is_some())
+        .collect();
+    for trap_id in trap_ids {
+        let name = world.get_character(id).unwrap().1.name.clone();
+        let trap_name = world.get::<&MapObject>(trap_id).unwrap().name.clone();
+        let trap = world.get::<&Trap>(trap_id).unwrap().clone();
+        if (id == world.player.id) || world.check_fov(id) {
+            reveal(trap_id, world);
+            let who = if id == world.player.id {
+                String::from("You step")
+            } else {
+                format!("The {} steps", name)
+            };
+            world.add_log(
+                cfg::settings().colors.orange,
+                format!("{} on the {}! {}", who, trap_name, trap.message),
+            );
+        }
+        let target_ids: Vec<_> = world
+            .entities_in_radius(x, y, trap.radius as f32)
+            .into_iter()
+            .filter(|&target_id| {
+                world
+                    .get_character(target_id)
+                    .is_some_and(|(.., char, _)| char.alive)
+            })
+            .collect();
+        for target_id in target_ids {
+            for &effect in &trap.effects {
+                apply_effect(target_id, effect, false, world);
+            }
+        }
+        if trap.once {
+            world.destroy_entity(trap_id);
+        }
+    }
+}
+
+/// Shows the hidden map object, e.g. a found trap.
+pub fn reveal(id: u32, world: &mut game::World) {
+    if let Some(map_obj) = world.get_mut::<&mut MapObject>(id) {
+        map_obj.hidden = false;
+        map_obj.always_visible = true;
+    }
+}
+
+/// How the feature looks in its state.
+pub fn feature_glyph(feature: Feature) -> char {
+    match feature {
+        Feature::Door { open: true, .. } => '\'',
+        Feature::Door { open: false, .. } => '+',
+        Feature::Lever { pulled: false, .. } => '/',
+        Feature::Lever { pulled: true, .. } => '\\',
+        Feature::Support { .. } => 'H',
+        Feature::Trap => '^',
+    }
+}
+
+/// Opens or closes the door: a closed one blocks the way and the sight. It
+/// can't be closed on someone standing in it.
+pub fn set_door_open(id: u32, open: bool, world: &mut game::World) -> Result<(), &'static str> {
+    let &Symbol { x, y, .. } = world.get::<&Symbol>(id).unwrap();
+    let is_occupied = world
+        .entities_at(x, y)
+        .iter()
+        .any(|&other_id| world.get_character(other_id).is_some());
+    if !open && is_occupied {
+        return Err("Something is in the way.");
+    }
+    let (symbol, map_obj, feature) = world
+        .get_mut::<(&mut Symbol, &mut MapObject, &mut Feature)>(id)
+        .unwrap();
+    if let Feature::Door { open: is_open, .. } = feature {
+        *is_open = open;
+    }
+    symbol.glyph = feature_glyph(*feature);
+    map_obj.block = !open;
+    world.map[(y * cfg::settings().map.width + x) as usize].block_sight = !open;
+    world.map_changed = true;
+    Ok(())
+}
+
+/// Takes the cost of an action from the energy of the character.
+pub fn spend_energy(id: u32, cost: i32, world: &mut game::World) {
+    if let Some((.., char, _)) = world.get_character_mut(id) {
+        char.energy -= cost;
+    }
+}
+
+#[cfg(test)]
+mod mechanics_tests {
+    use super::*;
+    use crate::cmtp::{Ammo, AmmoKind, Character, DeathCallback};
+    use crate::test_util::{self, world_with_map};
+
+    fn spawn_character(world: &mut game::World, x: i32) -> u32 {
+        let fighter = Character {
+            base_power: 5,
+            // always hits, so that the tests don't depend on the rolls
+            base_accuracy: 100,
+            on_death: DeathCallback::Enemy,
+            ..test_util::character(100)
+        };
+        test_util::spawn_character(world, (x, 1), fighter)
+    }
+
+    fn give_equipment(
+        world: &mut game::World,
+        owner: u32,
+        kind: ItemKind,
+        slot: Slot,
+        ammo: Option<Ammo>,
+    ) -> u32 {
+        let id = test_util::give_equipment(world, owner, kind, slot);
+        if let Some(ammo) = ammo {
+            world.add_component(id, ammo);
+        }
+        id
+    }
+
+    /// The player with a slingshot and 2 stones on the left, a monster on the
+    /// right behind a wall. Returns the ids of the monster and the ammo.
+    fn shooting_range() -> (game::World, u32, u32) {
+        let mut world = world_with_map(&[
+            "##########", //
+            "#........#", //
+            "#....#...#", //
+            "##########", //
+        ]);
+        world.player.id = spawn_character(&mut world, 1);
+        let monster_id = spawn_character(&mut world, 4);
+        let player_id = world.player.id;
+        let slingshot = ItemKind::Ranged(AmmoKind::SlingshotAmmo);
+        give_equipment(&mut world, player_id, slingshot, Slot::Hands, None);
+        let stones = Ammo {
+            kind: AmmoKind::SlingshotAmmo,
+        };
+        let ammo_id = give_equipment(
+            &mut world,
+            player_id,
+            ItemKind::Ammo,
+            Slot::Ammo,
+            Some(stones),
+        );
+        world.add_component(ammo_id, Stack { count: 2 });
+        (world, monster_id, ammo_id)
+    }
+
+    fn ammo_count(world: &game::World, ammo_id: u32) -> Option<u32> {
+        world.get::<&Stack>(ammo_id).map(|stack| stack.count)
+    }
+
+    #[test]
+    fn melee_attacks_dont_consume_ammo() {
+        let (mut world, monster_id, ammo_id) = shooting_range();
+        world.set_position(monster_id, 2, 1);
+        let player_id = world.player.id;
+        attack_by(player_id, monster_id, &mut world);
+        attack_by(monster_id, player_id, &mut world);
+        assert_eq!(ammo_count(&world, ammo_id), Some(2));
+        assert!(world.player_char().hp < 100);
+    }
+
+    #[test]
+    fn shots_consume_the_attackers_ammo() {
+        let (mut world, monster_id, ammo_id) = shooting_range();
+        let player_id = world.player.id;
+        assert!(shoot_by(player_id, monster_id, 5, &mut world).is_ok());
+        assert_eq!(ammo_count(&world, ammo_id), Some(1));
+        assert!(world.get_character(monster_id).unwrap().2.hp < 100);
+        // the monster has no weapon, so it doesn't need any ammo
+        assert!(shoot_by(monster_id, player_id, 5, &mut world).is_ok());
+        assert_eq!(ammo_count(&world, ammo_id), Some(1));
+        assert!(shoot_by(player_id, monster_id, 5, &mut world).is_ok());
+        assert_eq!(ammo_count(&world, ammo_id), None);
+        assert!(shoot_by(player_id, monster_id, 5, &mut world).is_err());
+    }
+
+    #[test]
+    fn unstacked_ammo_is_a_single_shot() {
+        let (mut world, monster_id, ammo_id) = shooting_range();
+        let player_id = world.player.id;
+        world.destroy_entity(ammo_id);
+        let stone = Ammo {
+            kind: AmmoKind::SlingshotAmmo,
+        };
+        let stone_id = give_equipment(
+            &mut world,
+            player_id,
+            ItemKind::Ammo,
+            Slot::Ammo,
+            Some(stone),
+        );
+        assert!(shoot_by(player_id, monster_id, 5, &mut world).is_ok());
+        assert!(world.get_item(stone_id).is_none());
+        assert!(shoot_by(player_id, monster_id, 5, &mut world).is_err());
+    }
+
+    #[test]
+    fn shots_need_range_and_line_of_sight() {
+        let (mut world, monster_id, ammo_id) = shooting_range();
+        let player_id = world.player.id;
+        assert!(shoot_by(player_id, monster_id, 2, &mut world).is_err());
+        world.set_position(player_id, 1, 2);
+        world.set_position(monster_id, 7, 2);
+        assert!(shoot_by(player_id, monster_id, 8, &mut world).is_err());
+        assert_eq!(ammo_count(&world, ammo_id), Some(2));
+        assert_eq!(world.get_character(monster_id).unwrap().2.hp, 100);
+    }
+
+    #[test]
+    fn shot_hits_the_first_creature_in_the_way() {
+        let (mut world, monster_id, _) = shooting_range();
+        let player_id = world.player.id;
+        let shield_id = spawn_character(&mut world, 3);
+        assert!(shoot_by(player_id, monster_id, 5, &mut world).is_ok());
+        assert_eq!(world.get_character(monster_id).unwrap().2.hp, 100);
+        assert!(world.get_character(shield_id).unwrap().2.hp < 100);
+    }
+
+    #[test]
+    fn kills_by_traps_give_no_xp_and_unseen_ones_are_not_logged() {
+        let (mut world, monster_id, _) = shooting_range();
+        world.get_character_mut(monster_id).unwrap().2.xp = 35;
+        let log_len = world.log.len();
+        apply_effect(monster_id, Effect::Heal(10), false, &mut world);
+        apply_effect(monster_id, Effect::Damage(100), false, &mut world);
+        assert!(!world.get_character(monster_id).unwrap().2.alive);
+        assert_eq!(world.player_char().xp, 0);
+        assert_eq!(world.log.len(), log_len);
+        let other_id = spawn_character(&mut world, 6);
+        world.get_character_mut(other_id).unwrap().2.xp = 35;
+        apply_effect(other_id, Effect::Damage(100), true, &mut world);
+        assert_eq!(world.player_char().xp, 35);
+    }
+
+    #[test]
+    fn attack_rolls_vary_the_damage() {
+        let mut rng = game::WorldRng::for_level(1, 1);
+        let rolls: Vec<_> = (0..1000)
+            .map(|_| roll_attack(&mut rng, (20, 100, 0), (0, 0)))
+            .collect();
+        let damages: Vec<_> = rolls
+            .iter()
+            .map(|roll| match *roll {
+                AttackRoll::Hit { damage, crit } => {
+                    assert!(!crit);
+                    damage
+                }
+                AttackRoll::Miss => panic!("an accurate attack misses"),
+            })
+            .collect();
+        assert!(damages.iter().all(|&damage| (15..=25).contains(&damage)));
+        assert!(damages.contains(&15) && damages.contains(&25));
+    }
+
+    #[test]
+    fn negative_power_does_no_damage() {
+        let mut rng = game::WorldRng::for_level(1, 1);
+        for _ in 0..100 {
+            match roll_attack(&mut rng, (-20, 100, 0), (0, 0)) {
+                AttackRoll::Hit { damage, .. } => assert!(damage <= 1),
+                AttackRoll::Miss => panic!("an accurate attack misses"),
+            }
+        }
+    }
+
+    #[test]
+    fn evasive_targets_are_hit_sometimes_and_armored_hurt_sometimes() {
+        let mut rng = game::WorldRng::for_level(1, 1);
+        let rolls: Vec<_> = (0..1000)
+            .map(|_| roll_attack(&mut rng, (5, 0, 100), (50, 100)))
+            .collect();
+        let hits = rolls
+            .iter()
+            .filter(|&roll| *roll != AttackRoll::Miss)
+            .count();
+        assert!((50..200).contains(&hits), "{} hits of 1000", hits);
+        assert!(rolls.iter().all(|roll| match *roll {
+            AttackRoll::Hit { damage, crit } => crit && (damage <= 1),
+            AttackRoll::Miss => true,
+        }));
+        assert!(rolls.contains(&AttackRoll::Hit {
+            damage: 1,
+            crit: true
+        }));
     }
 }
@@ -3,4 +3,10 @@ pub use mechanics::*;
 pub mod asset;
 pub mod entity;
 pub mod game;
+pub mod los;
+pub mod mapgen;
 mod mechanics;
+pub mod pathfinding;
+pub mod query;
+pub mod save;
+pub mod spatial;
@@ -0,0 +1,189 @@
+use super::game::World;
+use crate::cfg;
+use crate::cmtp::{Feature, MapObject};
+use std::cmp::{self, Reverse};
+use std::collections::BinaryHeap;
+
+const DIRECTIONS: [(i32, i32); 8] = [
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (1, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+];
+
+/// Finds the shortest path between the tiles with A*, moving in 8 directions
+/// like the characters do. It goes around the blocked map cells and blocking
+/// entities, except the one on the goal (it is usually the target of an attack).
+/// The closed doors are passed, as bumping into them opens them, see
+/// `step_cost`. Returns the tiles after `from` up to `to`, or None if there is
+/// no way.
+pub fn find_path(world: &World, from: (i32, i32), to: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+    let map_size = &cfg::settings().map;
+    let in_map =
+        |(x, y): (i32, i32)| (0..map_size.width).contains(&x) && (0..map_size.height).contains(&y);
+    if world.map.is_empty() || !in_map(from) || !in_map(to) {
+        return None;
+    }
+    let index = |(x, y): (i32, i32)| (y * map_size.width + x) as usize;
+    // a step costs at least 1, diagonal too, so the distance is Chebyshev's one
+    let heuristic = |(x, y): (i32, i32)| cmp::max((to.0 - x).abs(), (to.1 - y).abs());
+    let mut costs = vec![i32::MAX; world.map.len()];
+    let mut came_from = vec![None; world.map.len()];
+    let mut open = BinaryHeap::new();
+    costs[index(from)] = 0;
+    open.push(Reverse((heuristic(from), 0, from)));
+    while let Some(Reverse((_, cost, pos))) = open.pop() {
+        if pos == to {
+            return Some(reconstruct(&came_from, index, from, to));
+        }
+        if cost > costs[index(pos)] {
+            // a shorter way to this tile was found after it had been queued
+            continue;
+        }
+        for &(dx, dy) in &DIRECTIONS {
+            let next = (pos.0 + dx, pos.1 + dy);
+            if !in_map(next) {
+                continue;
+            }
+            let step_cost = if next == to {
+                Some(1)
+            } else {
+                step_cost(world, next)
+            };
+            let next_cost = match step_cost {
+                Some(step_cost) => cost + step_cost,
+                None => continue,
+            };
+            if next_cost < costs[index(next)] {
+                costs[index(next)] = next_cost;
+                came_from[index(next)] = Some(pos);
+                open.push(Reverse((next_cost + heuristic(next), next_cost, next)));
+            }
+        }
+    }
+    None
+}
+
+/// How many moves it takes to step on the tile, None if it's blocked. A closed
+/// door, unless it's the gate of a lever, is opened by bumping into it first.
+fn step_cost(world: &World, (x, y): (i32, i32)) -> Option<i32> {
+    if !world.is_blocked(x, y) {
+        return Some(1);
+    }
+    let closed_door = Feature::Door {
+        open: false,
+        group: None,
+    };
+    let map_width = cfg::settings().map.width;
+    let only_doors_block = !world.map[(y * map_width + x) as usize].block
+        && world
+            .entities_at(x, y)
+            .iter()
+            .filter(|&&id| {
+                world
+                    .get::<&MapObject>(id)
+                    .is_some_and(|map_obj| map_obj.block)
+            })
+            .all(|&id| world.get::<&Feature>(id) == Some(&closed_door));
+    if only_doors_block {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+fn reconstruct(
+    came_from: &[Option<(i32, i32)>],
+    index: impl Fn((i32, i32)) -> usize,
+    from: (i32, i32),
+    to: (i32, i32),
+) -> Vec<(i32, i32)> {
+    let mut path = vec![to];
+    let mut pos = to;
+    while let Some(prev) = came_from[index(pos)].filter(|&prev| prev != from) {
+        path.push(prev);
+        pos = prev;
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod pathfinding_tests {
+    use super::*;
+    use crate::cmtp::Symbol;
+    use crate::engine::{self, game};
+    use crate::test_util::world_with_map;
+
+    #[test]
+    fn path_goes_around_wall() {
+        let world = world_with_map(&[
+            "#######", //
+            "#..#..#", //
+            "#..#..#", //
+            "#.....#", //
+            "#######", //
+        ]);
+        let path = find_path(&world, (1, 1), (5, 1)).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path[1], (3, 3));
+        assert_eq!(path.last(), Some(&(5, 1)));
+    }
+
+    #[test]
+    fn path_avoids_blocking_entities_but_not_on_goal() {
+        let world = world_with_map(&[
+            "######", //
+            "#.M.M#", //
+            "#....#", //
+            "######", //
+        ]);
+        let path = find_path(&world, (1, 1), (4, 1)).unwrap();
+        assert_eq!(path, vec![(2, 2), (3, 1), (4, 1)]);
+    }
+
+    #[test]
+    fn no_path_to_closed_room() {
+        let world = world_with_map(&[
+            "#######", //
+            "#..#..#", //
+            "#######", //
+        ]);
+        assert_eq!(find_path(&world, (1, 1), (5, 1)), None);
+        assert_eq!(find_path(&world, (1, 1), (-1, 1)), None);
+    }
+
+    #[test]
+    fn path_goes_through_closed_doors_but_not_gates() {
+        let mut world = world_with_map(&[
+            "#######", //
+            "#..#..#", //
+            "#.....#", //
+            "#######", //
+        ]);
+        let spawn_door = |world: &mut World, group| {
+            let door_id = game::new_entity()
+                .add(Symbol {
+                    x: 3,
+                    y: 2,
+                    ..Default::default()
+                })
+                .add(MapObject::default())
+                .add(Feature::Door { open: false, group })
+                .create(world);
+            engine::set_door_open(door_id, false, world).unwrap();
+            door_id
+        };
+        let door_id = spawn_door(&mut world, None);
+        let path = find_path(&world, (1, 1), (5, 1)).unwrap();
+        assert_eq!(path.len(), 4);
+        assert_eq!(path[1], (3, 2));
+        world.destroy_entity(door_id);
+        spawn_door(&mut world, Some(1));
+        assert_eq!(find_path(&world, (1, 1), (5, 1)), None);
+    }
+}
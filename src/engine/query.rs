@@ -0,0 +1,214 @@
+use super::entity::{Component, Indexes};
+use super::game::World;
+use std::any::TypeId;
+
+/// A set of components to read from an entity: `&C` for a component that the
+/// entity must have, `Option<&C>` for an optional one, or a tuple of them.
+pub trait Query<'a> {
+    type Item;
+
+    fn fetch(world: &'a World, indexes: &Indexes) -> Option<Self::Item>;
+}
+
+/// Like `Query`, but `&mut C` and `Option<&mut C>` are allowed too. A component
+/// may appear only once in a query.
+pub trait QueryMut<'a> {
+    type Item;
+
+    /// Types of all the components of the query.
+    fn component_types(types: &mut Vec<TypeId>);
+
+    /// Slots of the components borrowed mutably, to never give out one twice.
+    fn mut_slots(indexes: &Indexes, slots: &mut Vec<(TypeId, usize)>);
+
+    /// # Safety
+    ///
+    /// `world` must be valid for `'a`, and the components of the query must
+    /// not be borrowed anywhere else during `'a`.
+    unsafe fn fetch(world: *mut World, indexes: &Indexes) -> Option<Self::Item>;
+}
+
+impl<'a, C: Component> Query<'a> for &'a C {
+    type Item = &'a C;
+
+    fn fetch(world: &'a World, indexes: &Indexes) -> Option<Self::Item> {
+        C::index(indexes).and_then(|i| C::storage(world).get(i))
+    }
+}
+
+impl<'a, C: Component> Query<'a> for Option<&'a C> {
+    type Item = Option<&'a C>;
+
+    fn fetch(world: &'a World, indexes: &Indexes) -> Option<Self::Item> {
+        Some(<&C as Query>::fetch(world, indexes))
+    }
+}
+
+impl<'a, C: Component> QueryMut<'a> for &'a C {
+    type Item = &'a C;
+
+    fn component_types(types: &mut Vec<TypeId>) {
+        types.push(TypeId::of::<C>());
+    }
+
+    fn mut_slots(_: &Indexes, _: &mut Vec<(TypeId, usize)>) {}
+
+    unsafe fn fetch(world: *mut World, indexes: &Indexes) -> Option<Self::Item> {
+        let storage = &*C::storage_ptr(world);
+        C::index(indexes).and_then(|i| storage.get(i))
+    }
+}
+
+impl<'a, C: Component> QueryMut<'a> for &'a mut C {
+    type Item = &'a mut C;
+
+    fn component_types(types: &mut Vec<TypeId>) {
+        types.push(TypeId::of::<C>());
+    }
+
+    fn mut_slots(indexes: &Indexes, slots: &mut Vec<(TypeId, usize)>) {
+        slots.extend(C::index(indexes).map(|i| (TypeId::of::<C>(), i)));
+    }
+
+    unsafe fn fetch(world: *mut World, indexes: &Indexes) -> Option<Self::Item> {
+        let storage = &mut *C::storage_ptr(world);
+        C::index(indexes).and_then(move |i| storage.get_mut(i))
+    }
+}
+
+impl<'a, C: Component> QueryMut<'a> for Option<&'a C> {
+    type Item = Option<&'a C>;
+
+    fn component_types(types: &mut Vec<TypeId>) {
+        types.push(TypeId::of::<C>());
+    }
+
+    fn mut_slots(_: &Indexes, _: &mut Vec<(TypeId, usize)>) {}
+
+    unsafe fn fetch(world: *mut World, indexes: &Indexes) -> Option<Self::Item> {
+        Some(<&C as QueryMut>::fetch(world, indexes))
+    }
+}
+
+impl<'a, C: Component> QueryMut<'a> for Option<&'a mut C> {
+    type Item = Option<&'a mut C>;
+
+    fn component_types(types: &mut Vec<TypeId>) {
+        types.push(TypeId::of::<C>());
+    }
+
+    fn mut_slots(indexes: &Indexes, slots: &mut Vec<(TypeId, usize)>) {
+        <&mut C as QueryMut>::mut_slots(indexes, slots);
+    }
+
+    unsafe fn fetch(world: *mut World, indexes: &Indexes) -> Option<Self::Item> {
+        Some(<&mut C as QueryMut>::fetch(world, indexes))
+    }
+}
+
+macro_rules! tuple_queries {
+    ($($q:ident),*) => {
+        impl<'a, $($q: Query<'a>),*> Query<'a> for ($($q,)*) {
+            type Item = ($($q::Item,)*);
+
+            fn fetch(world: &'a World, indexes: &Indexes) -> Option<Self::Item> {
+                Some(($($q::fetch(world, indexes)?,)*))
+            }
+        }
+
+        impl<'a, $($q: QueryMut<'a>),*> QueryMut<'a> for ($($q,)*) {
+            type Item = ($($q::Item,)*);
+
+            fn component_types(types: &mut Vec<TypeId>) {
+                $($q::component_types(types);)*
+            }
+
+            fn mut_slots(indexes: &Indexes, slots: &mut Vec<(TypeId, usize)>) {
+                $($q::mut_slots(indexes, slots);)*
+            }
+
+            unsafe fn fetch(world: *mut World, indexes: &Indexes) -> Option<Self::Item> {
+                Some(($($q::fetch(world, indexes)?,)*))
+            }
+        }
+    };
+}
+
+tuple_queries!(A);
+tuple_queries!(A, B);
+tuple_queries!(A, B, C);
+tuple_queries!(A, B, C, D);
+tuple_queries!(A, B, C, D, E);
+tuple_queries!(A, B, C, D, E, F);
+
+/// Whether every component appears once in the query, otherwise it would be
+/// borrowed mutably twice. The queries are written in the code, so it is only
+/// checked in the debug builds, not on every fetch of the release ones.
+pub(super) fn components_are_distinct<'a, Q: QueryMut<'a>>() -> bool {
+    let mut types = vec![];
+    Q::component_types(&mut types);
+    let count = types.len();
+    types.sort();
+    types.dedup();
+    count == types.len()
+}
+
+#[cfg(test)]
+mod query_tests {
+    use crate::cmtp::{Character, MapObject, Symbol};
+    use crate::engine::game::{self, World};
+
+    fn spawn(world: &mut World, x: i32, character: Option<Character>) -> u32 {
+        game::new_entity()
+            .add(Symbol {
+                x,
+                ..Default::default()
+            })
+            .add(MapObject::default())
+            .add_option(character)
+            .create(world)
+    }
+
+    #[test]
+    fn query_gives_entities_with_required_components() {
+        let mut world = World::default();
+        let monster = spawn(&mut world, 1, Some(Character::default()));
+        let rock = spawn(&mut world, 2, None);
+        let with_char: Vec<_> = world
+            .query::<(&Symbol, &Character)>()
+            .map(|(id, (symbol, _))| (id, symbol.x))
+            .collect();
+        assert_eq!(with_char, vec![(monster, 1)]);
+        let all: Vec<_> = world
+            .query::<(&Symbol, Option<&Character>)>()
+            .map(|(id, (_, char))| (id, char.is_some()))
+            .collect();
+        assert_eq!(all, vec![(monster, true), (rock, false)]);
+        assert!(world.get::<&Character>(rock).is_none());
+    }
+
+    #[test]
+    fn query_mut_changes_components() {
+        let mut world = World::default();
+        let first = spawn(&mut world, 1, Some(Character::default()));
+        let second = spawn(&mut world, 2, Some(Character::default()));
+        for (_, (symbol, char)) in world.query_mut::<(&Symbol, &mut Character)>() {
+            char.hp = symbol.x * 10;
+        }
+        if let Some((symbol, Some(char))) =
+            world.get_mut::<(&mut Symbol, Option<&mut Character>)>(first)
+        {
+            symbol.x += char.hp;
+        }
+        assert_eq!(world.get::<&Character>(second).unwrap().hp, 20);
+        assert_eq!(world.get::<&Symbol>(first).unwrap().x, 11);
+    }
+
+    #[test]
+    #[should_panic(expected = "twice")]
+    fn same_component_cant_be_borrowed_twice() {
+        let mut world = World::default();
+        let id = spawn(&mut world, 1, None);
+        world.get_mut::<(&mut Symbol, &Symbol)>(id);
+    }
+}
@@ -0,0 +1,968 @@
+use super::game;
+use crate::cfg;
+use crate::cmtp::MapCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{error::Error, fmt, fs, io, io::Read as _, io::Write as _};
+
+/// Version of the save layout. Bump it and add a migration to `MIGRATIONS`
+/// whenever a change of the components breaks the older saves.
+pub const FORMAT_VERSION: u32 = 8;
+
+/// The binary saves start with it, the JSON ones with `{`. After it go the
+/// header and the world in MessagePack, with the maps packed by `packing`. The
+/// world is read as a JSON value, so the saves of both formats are migrated the
+/// same way.
+const BINARY_MAGIC: &[u8] = b"EXPT01\0";
+
+const SAVES_DIR: &str = "saves";
+/// The only save of the versions before the save slots.
+const LEGACY_SAVE_PATH: &str = "savegame";
+
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Migrations of the saved world: the first one upgrades version 1 to 2, the
+/// second one 2 to 3, and so on.
+const MIGRATIONS: &[Migration] = &[
+    migrate_v1_to_v2,
+    migrate_v2_to_v3,
+    migrate_v3_to_v4,
+    migrate_v4_to_v5,
+    migrate_v5_to_v6,
+    migrate_v6_to_v7,
+    migrate_v7_to_v8,
+];
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    #[serde(flatten)]
+    header: Header,
+    world: &'a game::World,
+}
+
+/// Goes before the world: as is in the binary saves, and in the envelope of
+/// the JSON ones. The slot picker reads only it.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format_version: u32,
+    game_version: String,
+    /// the saves of the older versions don't have it
+    #[serde(default)]
+    slot: Option<SlotInfo>,
+}
+
+impl Header {
+    fn of(world: &game::World) -> Self {
+        Header {
+            format_version: FORMAT_VERSION,
+            game_version: String::from(env!("CARGO_PKG_VERSION")),
+            slot: Some(SlotInfo::of(world)),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadError {
+    NotFound,
+    Incompatible {
+        format_version: u32,
+        game_version: String,
+    },
+    Corrupted(Box<dyn Error>),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::NotFound => write!(f, "No saved game to load."),
+            LoadError::Incompatible {
+                format_version,
+                game_version,
+            } => write!(
+                f,
+                "The saved game is from an incompatible version {} (save format {}) \
+                 and can't be loaded.",
+                game_version, format_version
+            ),
+            LoadError::Corrupted(err) => write!(f, "The saved game is corrupted:\n\n{}", err),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+/// What the slot picker shows about a saved game.
+#[derive(Serialize, Deserialize)]
+pub struct SlotInfo {
+    /// the name the player gave the game, may be empty
+    pub name: String,
+    pub character_level: i32,
+    pub dungeon_level: u32,
+    pub turn: u32,
+    /// the time of the save file, it's not in the save
+    #[serde(skip, default = "unix_epoch")]
+    pub saved_at: SystemTime,
+}
+
+impl SlotInfo {
+    fn of(world: &game::World) -> Self {
+        SlotInfo {
+            name: world.save_name.clone(),
+            character_level: world
+                .get_character(world.player.id)
+                .map_or(0, |(.., char, _)| char.level),
+            dungeon_level: world.player.dungeon_level,
+            turn: world.player.turn,
+            saved_at: UNIX_EPOCH,
+        }
+    }
+}
+
+fn unix_epoch() -> SystemTime {
+    UNIX_EPOCH
+}
+
+/// Creates the saves directory if needed, and moves the save of the older
+/// versions to the first slot. Must be called once before the slots are used.
+pub fn prepare_slots() -> io::Result<()> {
+    fs::create_dir_all(SAVES_DIR)?;
+    let first_slot_path = slot_path(0);
+    if Path::new(LEGACY_SAVE_PATH).exists() && !first_slot_path.exists() {
+        fs::rename(LEGACY_SAVE_PATH, &first_slot_path)?;
+    }
+    Ok(())
+}
+
+pub fn save_game(world: &game::World, slot: usize) -> Result<(), Box<dyn Error>> {
+    let save_data = if cfg::settings().saves.binary {
+        to_binary(world)?
+    } else {
+        to_string(world)?.into_bytes()
+    };
+    let mut file = fs::File::create(slot_path(slot))?;
+    file.write_all(&save_data)?;
+    Ok(())
+}
+
+pub fn load_game(slot: usize) -> Result<game::World, LoadError> {
+    let save_data = read_slot(slot)?;
+    let mut world = from_bytes(&save_data)?;
+    if let Some(info) = read_header(&save_data).and_then(|header| header.slot) {
+        world.save_name = info.name;
+    }
+    Ok(world)
+}
+
+/// Reads only the header of the save, unless it's from a version without the
+/// slot info in the header.
+pub fn slot_info(slot: usize) -> Result<SlotInfo, LoadError> {
+    let save_data = read_slot(slot)?;
+    let mut info = match read_header(&save_data).and_then(|header| header.slot) {
+        Some(info) => info,
+        None => SlotInfo::of(&from_bytes(&save_data)?),
+    };
+    info.saved_at = fs::metadata(slot_path(slot))
+        .and_then(|metadata| metadata.modified())
+        .map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+    Ok(info)
+}
+
+/// Returns the slot of the most recent save, if there is any.
+pub fn last_slot() -> Option<usize> {
+    (0..cfg::SAVE_SLOTS)
+        .filter_map(|slot| {
+            let metadata = fs::metadata(slot_path(slot)).ok()?;
+            Some((slot, metadata.modified().ok()?))
+        })
+        .max_by_key(|&(_, saved_at)| saved_at)
+        .map(|(slot, _)| slot)
+}
+
+fn slot_path(slot: usize) -> PathBuf {
+    Path::new(SAVES_DIR).join(format!("slot-{}", slot + 1))
+}
+
+fn read_slot(slot: usize) -> Result<Vec<u8>, LoadError> {
+    let mut save_data = vec![];
+    let mut file = fs::File::open(slot_path(slot)).map_err(|err| match err.kind() {
+        io::ErrorKind::NotFound => LoadError::NotFound,
+        _ => LoadError::Corrupted(Box::new(err)),
+    })?;
+    file.read_to_end(&mut save_data)
+        .map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+    Ok(save_data)
+}
+
+/// The header of a save in either format, without loading the world. None if
+/// the save is broken or has no header, as the saves of the version 1.
+fn read_header(save_data: &[u8]) -> Option<Header> {
+    if save_data.starts_with(BINARY_MAGIC) {
+        rmp_serde::from_read(&save_data[BINARY_MAGIC.len()..]).ok()
+    } else {
+        // the world is skipped, not parsed
+        serde_json::from_slice(save_data).ok()
+    }
+}
+
+/// Loads a save in either format, the binary or the JSON one.
+pub fn from_bytes(save_data: &[u8]) -> Result<game::World, LoadError> {
+    let mut world = if save_data.starts_with(BINARY_MAGIC) {
+        from_binary(save_data)?
+    } else {
+        let json_save_state =
+            std::str::from_utf8(save_data).map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+        from_str(json_save_state)?
+    };
+    world.rebuild_spatial_index();
+    Ok(world)
+}
+
+pub fn to_binary(world: &game::World) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut save_data = BINARY_MAGIC.to_vec();
+    rmp_serde::encode::write_named(&mut save_data, &Header::of(world))?;
+    rmp_serde::encode::write_named(&mut save_data, world)?;
+    Ok(save_data)
+}
+
+pub fn from_binary(save_data: &[u8]) -> Result<game::World, LoadError> {
+    let mut reader = &save_data[BINARY_MAGIC.len()..];
+    let header: Header =
+        rmp_serde::from_read(&mut reader).map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+    let world_msg =
+        rmpv::decode::read_value(&mut reader).map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+    // the packed maps become arrays of numbers
+    let mut world_val =
+        serde_json::to_value(world_msg).map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+    packing::unpack(&mut world_val).map_err(|err| LoadError::Corrupted(err.into()))?;
+    migrate(header.format_version, header.game_version, world_val)
+}
+
+pub fn to_string(world: &game::World) -> Result<String, serde_json::Error> {
+    serde_json::to_string(&Envelope {
+        header: Header::of(world),
+        world,
+    })
+}
+
+pub fn from_str(save_data: &str) -> Result<game::World, LoadError> {
+    let save_val: Value =
+        serde_json::from_str(save_data).map_err(|err| LoadError::Corrupted(Box::new(err)))?;
+    let (format_version, game_version, world_val) = open_envelope(save_val);
+    migrate(format_version, game_version, world_val)
+}
+
+/// Upgrades the world of the format version to the current one and loads it.
+fn migrate(
+    format_version: u32,
+    game_version: String,
+    mut world_val: Value,
+) -> Result<game::World, LoadError> {
+    let incompatible = || LoadError::Incompatible {
+        format_version,
+        game_version: game_version.clone(),
+    };
+    if (format_version == 0) || (format_version > FORMAT_VERSION) {
+        return Err(incompatible());
+    }
+    for migrate in &MIGRATIONS[(format_version - 1) as usize..] {
+        migrate(&mut world_val).map_err(|_| incompatible())?;
+    }
+    serde_json::from_value(world_val).map_err(|err| {
+        if format_version == FORMAT_VERSION {
+            LoadError::Corrupted(Box::new(err))
+        } else {
+            incompatible()
+        }
+    })
+}
+
+/// Returns the format version, the game version and the world. The saves of
+/// the version 1 have no envelope, it's just the world.
+fn open_envelope(save_val: Value) -> (u32, String, Value) {
+    match save_val {
+        Value::Object(mut envelope) if envelope.contains_key("format_version") => {
+            let format_version = envelope["format_version"].as_u64().unwrap_or(0) as u32;
+            let game_version = envelope
+                .get("game_version")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown")
+                .to_string();
+            let world_val = envelope.remove("world").unwrap_or(Value::Null);
+            (format_version, game_version, world_val)
+        }
+        world_val => (1, String::from("1.2.2 or older"), world_val),
+    }
+}
+
+/// Version 2 adds the world seed.
+fn migrate_v1_to_v2(world_val: &mut Value) -> Result<(), String> {
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    world.entry("seed").or_insert(Value::from(0));
+    Ok(())
+}
+
+/// Version 3 replaces the confused AI, that kept the previous one, with the
+/// status effects. The confused monsters get the previous AI back and the
+/// confusion as an effect.
+fn migrate_v2_to_v3(world_val: &mut Value) -> Result<(), String> {
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    let mut confusions = vec![];
+    if let Some(Value::Array(ais)) = world.get_mut("ais") {
+        for (index, ai_container) in ais.iter_mut().enumerate() {
+            let mut turns = None;
+            while let Some(confused) = ai_container["option"].get_mut("Confused").map(Value::take) {
+                turns = turns.or_else(|| confused["num_turns"].as_i64());
+                ai_container["option"] = confused["previous_ai"].clone();
+            }
+            if let Some(turns) = turns {
+                confusions.push((index, turns));
+            }
+        }
+    }
+    let mut status_effects = vec![];
+    if let Some(Value::Object(entity_indexes)) = world.get_mut("entity_indexes") {
+        for indexes in entity_indexes.values_mut() {
+            let confusion = confusions
+                .iter()
+                .find(|&&(index, _)| indexes["ai"].as_u64() == Some(index as u64));
+            if let Some(&(_, turns)) = confusion {
+                indexes["status_effects"] = Value::from(status_effects.len());
+                status_effects.push(serde_json::json!([
+                    { "kind": "Confusion", "turns": turns, "power": 0 }
+                ]));
+            }
+        }
+    }
+    world.insert(String::from("status_effects"), Value::from(status_effects));
+    Ok(())
+}
+
+/// Version 4 describes what the consumables do in their `on_use` instead of
+/// their kinds. The old kinds get the effects they had with the default
+/// settings, and the ranged weapons get the range of the slingshot.
+fn migrate_v3_to_v4(world_val: &mut Value) -> Result<(), String> {
+    use serde_json::json;
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    let mut ranged_items = vec![];
+    if let Some(Value::Array(items)) = world.get_mut("items") {
+        for (index, item) in items.iter_mut().enumerate() {
+            let on_use = match item["kind"].as_str() {
+                Some("Medkit") => json!({ "target": "Itself", "effects": [{ "Heal": 40 }] }),
+                Some("FutureUnknownDamager") => {
+                    json!({ "target": "Nearest", "range": 5.0, "effects": [{ "Damage": 40 }] })
+                }
+                Some("Brick") => {
+                    // the confusion it inflicted becomes its effect
+                    let mut effects: Vec<_> = item["inflicts"]
+                        .take()
+                        .as_array()
+                        .cloned()
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|effect| json!({ "Status": effect }))
+                        .collect();
+                    if effects.is_empty() {
+                        effects.push(json!({
+                            "Status": { "kind": "Confusion", "turns": 10, "power": 0 }
+                        }));
+                    }
+                    item["inflicts"] = json!([]);
+                    json!({ "target": "Creature", "range": 8.0, "effects": effects })
+                }
+                Some("BlastingCartridge") => json!({
+                    "target": "Tile",
+                    "radius": 3,
+                    "effects": [{ "Damage": 25 }],
+                    "message": "The Blasting Cartridge explodes, crushing everything within 3 tiles!",
+                }),
+                _ => {
+                    if item["kind"].get("Ranged").is_some() {
+                        ranged_items.push(index as u64);
+                    }
+                    continue;
+                }
+            };
+            item["kind"] = Value::from("Consumable");
+            item["on_use"] = on_use;
+        }
+    }
+    let ranged_equipments: Vec<_> = world
+        .get("entity_indexes")
+        .and_then(Value::as_object)
+        .map(|entity_indexes| {
+            entity_indexes
+                .values()
+                .filter(|indexes| {
+                    let index = indexes["item"].as_u64();
+                    ranged_items.iter().any(|&ranged| Some(ranged) == index)
+                })
+                .filter_map(|indexes| indexes["equipment"].as_u64())
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(Value::Array(equipments)) = world.get_mut("equipments") {
+        for index in ranged_equipments {
+            if let Some(equipment) = equipments.get_mut(index as usize) {
+                equipment["range"] = Value::from(5);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Version 5 moves the count of the ammo to the stack, that any stackable item
+/// has.
+fn migrate_v4_to_v5(world_val: &mut Value) -> Result<(), String> {
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    let mut counts = vec![];
+    if let Some(Value::Array(ammos)) = world.get_mut("ammos") {
+        for ammo in ammos.iter_mut() {
+            let count = ammo.as_object_mut().and_then(|ammo| ammo.remove("count"));
+            counts.push(count.unwrap_or_else(|| Value::from(1)));
+        }
+    }
+    let mut stacks = vec![];
+    if let Some(Value::Object(entity_indexes)) = world.get_mut("entity_indexes") {
+        for indexes in entity_indexes.values_mut() {
+            let count = indexes["ammo"]
+                .as_u64()
+                .and_then(|index| counts.get(index as usize));
+            if let Some(count) = count {
+                indexes["stack"] = Value::from(stacks.len());
+                stacks.push(serde_json::json!({ "count": count }));
+            }
+        }
+    }
+    world.insert(String::from("stacks"), Value::from(stacks));
+    Ok(())
+}
+
+/// Version 6 keeps the visited levels, and marks the stairs down with the
+/// stairs component instead of their name.
+fn migrate_v5_to_v6(world_val: &mut Value) -> Result<(), String> {
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    let stairs_map_objects: Vec<_> = world
+        .get("map_objects")
+        .and_then(Value::as_array)
+        .map(|map_objects| {
+            map_objects
+                .iter()
+                .enumerate()
+                .filter(|(_, map_obj)| map_obj["name"] == "stairs")
+                .map(|(index, _)| index as u64)
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut stairs = vec![];
+    if let Some(Value::Object(entity_indexes)) = world.get_mut("entity_indexes") {
+        for indexes in entity_indexes.values_mut() {
+            let index = indexes["map_object"].as_u64();
+            if stairs_map_objects
+                .iter()
+                .any(|&map_obj| Some(map_obj) == index)
+            {
+                indexes["stairs"] = Value::from(stairs.len());
+                stairs.push(Value::from("Down"));
+            }
+        }
+    }
+    world.insert(String::from("stairs"), Value::from(stairs));
+    world
+        .entry("levels")
+        .or_insert_with(|| Value::Object(Default::default()));
+    Ok(())
+}
+
+/// Version 7 has the map features, e.g. doors, in the current and the visited
+/// levels.
+fn migrate_v6_to_v7(world_val: &mut Value) -> Result<(), String> {
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    world
+        .entry("features")
+        .or_insert_with(|| Value::Array(vec![]));
+    if let Some(Value::Object(levels)) = world.get_mut("levels") {
+        for level in levels.values_mut() {
+            migrate_v6_to_v7(level)?;
+        }
+    }
+    Ok(())
+}
+
+/// Version 8 keeps what the traps do in the trap component, so that they can
+/// come from the assets, instead of the damage of the trap feature.
+fn migrate_v7_to_v8(world_val: &mut Value) -> Result<(), String> {
+    let world = world_val
+        .as_object_mut()
+        .ok_or("the world is not an object")?;
+    let mut damages = vec![];
+    if let Some(Value::Array(features)) = world.get_mut("features") {
+        for feature in features.iter_mut() {
+            let damage = feature.get("Trap").map(|trap| trap["damage"].clone());
+            if damage.is_some() {
+                *feature = Value::from("Trap");
+            }
+            damages.push(damage);
+        }
+    }
+    let mut traps = vec![];
+    if let Some(Value::Object(entity_indexes)) = world.get_mut("entity_indexes") {
+        for indexes in entity_indexes.values_mut() {
+            let damage = indexes["feature"]
+                .as_u64()
+                .and_then(|index| damages.get(index as usize).cloned().flatten());
+            if let Some(damage) = damage {
+                indexes["trap"] = Value::from(traps.len());
+                traps.push(serde_json::json!({
+                    "message": "",
+                    "effects": [{ "Damage": damage }],
+                }));
+            }
+        }
+    }
+    world.insert(String::from("traps"), Value::from(traps));
+    if let Some(Value::Object(levels)) = world.get_mut("levels") {
+        for level in levels.values_mut() {
+            migrate_v7_to_v8(level)?;
+        }
+    }
+    Ok(())
+}
+
+/// Packs the maps in the binary saves, two cells per byte instead of an object
+/// per cell. The JSON saves keep the cells as they are.
+pub(super) mod packing {
+    use super::*;
+    use serde::Serializer;
+
+    #[derive(Serialize)]
+    struct PackedMap<'a> {
+        len: usize,
+        packed: &'a serde_bytes::Bytes,
+    }
+
+    pub fn serialize_map<S: Serializer>(map: &[MapCell], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            return map.serialize(serializer);
+        }
+        let packed: Vec<u8> = map
+            .chunks(2)
+            .map(|cells| {
+                cells
+                    .iter()
+                    .enumerate()
+                    .fold(0, |byte, (i, cell)| byte | (pack_cell(cell) << (4 * i)))
+            })
+            .collect();
+        PackedMap {
+            len: map.len(),
+            packed: serde_bytes::Bytes::new(&packed),
+        }
+        .serialize(serializer)
+    }
+
+    /// Unpacks the maps of the world and its visited levels.
+    pub fn unpack(world_val: &mut Value) -> Result<(), String> {
+        if let Some(map_val) = world_val.get_mut("map") {
+            let len = map_val["len"]
+                .as_u64()
+                .ok_or("the packed map has no length")? as usize;
+            let packed: Vec<u8> =
+                serde_json::from_value(map_val["packed"].take()).map_err(|err| err.to_string())?;
+            if packed.len() != len / 2 + len % 2 {
+                return Err(String::from("the packed map has a wrong length"));
+            }
+            let map: Vec<_> = (0..len)
+                .map(|i| unpack_cell(packed[i / 2] >> (4 * (i % 2))))
+                .collect();
+            *map_val = serde_json::to_value(map).map_err(|err| err.to_string())?;
+        }
+        if let Some(Value::Object(levels)) = world_val.get_mut("levels") {
+            for level in levels.values_mut() {
+                unpack(level)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn pack_cell(cell: &MapCell) -> u8 {
+        (cell.block as u8)
+            | ((cell.explored as u8) << 1)
+            | ((cell.block_sight as u8) << 2)
+            | ((cell.in_fov as u8) << 3)
+    }
+
+    fn unpack_cell(bits: u8) -> MapCell {
+        MapCell {
+            block: (bits & 0b0001) != 0,
+            explored: (bits & 0b0010) != 0,
+            block_sight: (bits & 0b0100) != 0,
+            in_fov: (bits & 0b1000) != 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod save_tests {
+    use super::*;
+
+    #[test]
+    fn saved_world_loads_back() {
+        let world = game::World {
+            seed: 5,
+            id_count: 3,
+            ..Default::default()
+        };
+        let loaded = from_str(&to_string(&world).unwrap()).unwrap();
+        assert_eq!((loaded.seed, loaded.id_count), (5, 3));
+    }
+
+    #[test]
+    fn rng_goes_on_after_load() {
+        use rand::Rng as _;
+        let mut world = game::World {
+            rng: game::WorldRng::for_level(5, 2),
+            ..Default::default()
+        };
+        world.rng.gen::<u64>();
+        let mut from_json = from_str(&to_string(&world).unwrap()).unwrap();
+        let mut from_binary = from_bytes(&to_binary(&world).unwrap()).unwrap();
+        let next_roll = world.rng.gen::<u64>();
+        assert_eq!(from_json.rng.gen::<u64>(), next_roll);
+        assert_eq!(from_binary.rng.gen::<u64>(), next_roll);
+    }
+
+    #[test]
+    fn binary_save_loads_back_with_map() {
+        let mut world = game::World {
+            seed: 5,
+            ..Default::default()
+        };
+        for &(block, explored) in &[(true, false), (false, true), (true, true)] {
+            game::new_entity()
+                .add(MapCell {
+                    block,
+                    explored,
+                    block_sight: block,
+                    in_fov: false,
+                })
+                .create(&mut world);
+        }
+        let loaded = from_bytes(&to_binary(&world).unwrap()).unwrap();
+        assert_eq!(loaded.seed, 5);
+        let cells = |world: &game::World| {
+            world
+                .map
+                .iter()
+                .map(|cell| (cell.block, cell.explored, cell.block_sight, cell.in_fov))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(cells(&loaded), cells(&world));
+    }
+
+    #[test]
+    fn binary_save_has_the_map_as_bytes() {
+        let mut world = game::World::default();
+        for _ in 0..5 {
+            game::new_entity()
+                .add(MapCell {
+                    block: false,
+                    explored: false,
+                    block_sight: false,
+                    in_fov: false,
+                })
+                .create(&mut world);
+        }
+        let save_data = to_binary(&world).unwrap();
+        let mut reader = &save_data[BINARY_MAGIC.len()..];
+        rmpv::decode::read_value(&mut reader).unwrap();
+        let world_msg = rmpv::decode::read_value(&mut reader).unwrap();
+        assert_eq!(world_msg["map"]["packed"].as_slice(), Some(&[0u8; 3][..]));
+    }
+
+    #[test]
+    fn json_save_is_detected() {
+        let world = game::World {
+            seed: 5,
+            ..Default::default()
+        };
+        let loaded = from_bytes(to_string(&world).unwrap().as_bytes()).unwrap();
+        assert_eq!(loaded.seed, 5);
+    }
+
+    #[test]
+    fn binary_save_is_smaller_than_json() {
+        let world = crate::headless::Simulation::new(5).world;
+        let binary_len = to_binary(&world).unwrap().len();
+        let json_len = to_string(&world).unwrap().len();
+        assert!(
+            binary_len * 4 < json_len,
+            "binary save is {} bytes, JSON is {}",
+            binary_len,
+            json_len
+        );
+    }
+
+    #[test]
+    fn slot_info_is_read_from_header() {
+        let mut world = game::World {
+            save_name: String::from("Deep run"),
+            ..Default::default()
+        };
+        world.player.dungeon_level = 4;
+        world.player.turn = 120;
+        for save_data in &[
+            to_binary(&world).unwrap(),
+            to_string(&world).unwrap().into_bytes(),
+        ] {
+            let info = read_header(save_data)
+                .and_then(|header| header.slot)
+                .unwrap();
+            assert_eq!(info.name, "Deep run");
+            assert_eq!((info.dungeon_level, info.turn), (4, 120));
+        }
+        let broken_world = format!(
+            r#"{{"format_version":{},"game_version":"1.3.0","slot":{{"name":"Broken","character_level":1,"dungeon_level":1,"turn":0}},"world":42}}"#,
+            FORMAT_VERSION
+        );
+        let info = read_header(broken_world.as_bytes()).and_then(|header| header.slot);
+        assert_eq!(info.unwrap().name, "Broken");
+    }
+
+    #[test]
+    fn save_without_envelope_is_migrated() {
+        let mut world_val = serde_json::to_value(game::World {
+            id_count: 3,
+            ..Default::default()
+        })
+        .unwrap();
+        world_val.as_object_mut().unwrap().remove("seed");
+        let loaded = from_str(&world_val.to_string()).unwrap();
+        assert_eq!((loaded.seed, loaded.id_count), (0, 3));
+    }
+
+    #[test]
+    fn confused_ai_is_migrated_to_status_effect() {
+        use crate::cmtp::{Ai, AiOption, StatusKind};
+        let mut world = game::World::default();
+        let monster_id = game::new_entity()
+            .add(AiOption {
+                option: Some(Ai::Stationary),
+                ..Default::default()
+            })
+            .create(&mut world);
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val["ais"][0]["option"] = serde_json::json!({
+            "Confused": { "previous_ai": "Stationary", "num_turns": 4 }
+        });
+        world_val.as_object_mut().unwrap().remove("status_effects");
+        let save_data = serde_json::json!({
+            "format_version": 2,
+            "game_version": "1.3.0",
+            "world": world_val,
+        });
+        let loaded = from_str(&save_data.to_string()).unwrap();
+        let (ai, effects) = loaded
+            .get::<(&AiOption, &crate::cmtp::StatusEffects)>(monster_id)
+            .unwrap();
+        assert!(matches!(ai.option, Some(Ai::Stationary)));
+        assert_eq!(effects.0[0].kind, StatusKind::Confusion);
+        assert_eq!(effects.0[0].turns, 4);
+    }
+
+    #[test]
+    fn item_kinds_are_migrated_to_effects() {
+        use crate::cmtp::{Effect, Item, ItemKind, Targeting};
+        let mut world = game::World::default();
+        let brick_id = game::new_entity()
+            .add(Item {
+                kind: ItemKind::Consumable,
+                owner: 0,
+                inflicts: vec![],
+                on_use: None,
+            })
+            .create(&mut world);
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val["items"][0]["kind"] = Value::from("Brick");
+        world_val["items"][0]
+            .as_object_mut()
+            .unwrap()
+            .remove("on_use");
+        let save_data = serde_json::json!({
+            "format_version": 3,
+            "game_version": "1.3.0",
+            "world": world_val,
+        });
+        let loaded = from_str(&save_data.to_string()).unwrap();
+        let item = loaded.get::<&Item>(brick_id).unwrap();
+        let on_use = item.on_use.as_ref().unwrap();
+        assert_eq!(item.kind, ItemKind::Consumable);
+        assert_eq!(on_use.target, Targeting::Creature);
+        assert!(matches!(on_use.effects[..], [Effect::Status(_)]));
+    }
+
+    #[test]
+    fn ammo_count_is_migrated_to_stack() {
+        use crate::cmtp::{Ammo, AmmoKind, Stack};
+        let mut world = game::World::default();
+        let ammo_id = game::new_entity()
+            .add(Ammo {
+                kind: AmmoKind::SlingshotAmmo,
+            })
+            .create(&mut world);
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val["ammos"][0]["count"] = Value::from(7);
+        world_val.as_object_mut().unwrap().remove("stacks");
+        let save_data = serde_json::json!({
+            "format_version": 4,
+            "game_version": "1.3.0",
+            "world": world_val,
+        });
+        let loaded = from_str(&save_data.to_string()).unwrap();
+        assert_eq!(loaded.get::<&Stack>(ammo_id).unwrap().count, 7);
+    }
+
+    #[test]
+    fn old_binary_save_is_migrated() {
+        use crate::cmtp::{Ammo, AmmoKind, Stack};
+        let mut world = game::World::default();
+        let ammo_id = game::new_entity()
+            .add(Ammo {
+                kind: AmmoKind::SlingshotAmmo,
+            })
+            .create(&mut world);
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val["ammos"][0]["count"] = Value::from(7);
+        world_val.as_object_mut().unwrap().remove("stacks");
+        // the older binary saves had the packed map as numbers
+        world_val["map"] = serde_json::json!({ "len": 0, "packed": [] });
+        let header = Header {
+            format_version: 4,
+            game_version: String::from("1.3.0"),
+            slot: None,
+        };
+        let mut save_data = BINARY_MAGIC.to_vec();
+        rmp_serde::encode::write_named(&mut save_data, &header).unwrap();
+        rmp_serde::encode::write_named(&mut save_data, &world_val).unwrap();
+        let loaded = from_bytes(&save_data).unwrap();
+        assert_eq!(loaded.get::<&Stack>(ammo_id).unwrap().count, 7);
+    }
+
+    #[test]
+    fn visited_levels_are_saved() {
+        let mut world = game::World {
+            seed: 5,
+            ..Default::default()
+        };
+        world.levels.insert(
+            2,
+            game::World {
+                id_count: 7,
+                ..Default::default()
+            },
+        );
+        let loaded = from_bytes(&to_binary(&world).unwrap()).unwrap();
+        assert_eq!(loaded.levels[&2].id_count, 7);
+        let loaded = from_str(&to_string(&world).unwrap()).unwrap();
+        assert_eq!(loaded.levels[&2].id_count, 7);
+    }
+
+    #[test]
+    fn stairs_are_migrated_to_component() {
+        use crate::cmtp::{MapObject, Stairs};
+        let mut world = game::World::default();
+        let stairs_id = game::new_entity()
+            .add(MapObject {
+                name: String::from("stairs"),
+                ..Default::default()
+            })
+            .create(&mut world);
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val.as_object_mut().unwrap().remove("stairs");
+        world_val.as_object_mut().unwrap().remove("levels");
+        let save_data = serde_json::json!({
+            "format_version": 5,
+            "game_version": "1.3.0",
+            "world": world_val,
+        });
+        let loaded = from_str(&save_data.to_string()).unwrap();
+        assert_eq!(loaded.get::<&Stairs>(stairs_id), Some(&Stairs::Down));
+    }
+
+    #[test]
+    fn visited_levels_get_features() {
+        let mut world = game::World::default();
+        world.levels.insert(2, game::World::default());
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val.as_object_mut().unwrap().remove("features");
+        world_val["levels"]["2"]
+            .as_object_mut()
+            .unwrap()
+            .remove("features");
+        world_val.as_object_mut().unwrap().remove("traps");
+        world_val["levels"]["2"]
+            .as_object_mut()
+            .unwrap()
+            .remove("traps");
+        let save_data = serde_json::json!({
+            "format_version": 6,
+            "game_version": "1.3.0",
+            "world": world_val,
+        });
+        let loaded = from_str(&save_data.to_string()).unwrap();
+        assert!(loaded.levels[&2].features.is_empty());
+    }
+
+    #[test]
+    fn trap_damage_is_migrated_to_trap_component() {
+        use crate::cmtp::{Effect, Feature, Trap};
+        let mut world = game::World::default();
+        let trap_id = game::new_entity().add(Feature::Trap).create(&mut world);
+        let mut world_val = serde_json::to_value(&world).unwrap();
+        world_val["features"][0] = serde_json::json!({ "Trap": { "damage": 10 } });
+        world_val.as_object_mut().unwrap().remove("traps");
+        let save_data = serde_json::json!({
+            "format_version": 7,
+            "game_version": "1.3.0",
+            "world": world_val,
+        });
+        let loaded = from_str(&save_data.to_string()).unwrap();
+        assert_eq!(loaded.get::<&Feature>(trap_id), Some(&Feature::Trap));
+        let trap = loaded.get::<&Trap>(trap_id).unwrap();
+        assert!(matches!(trap.effects[..], [Effect::Damage(10)]));
+    }
+
+    #[test]
+    fn save_from_newer_version_is_incompatible() {
+        let save_data = format!(
+            r#"{{"format_version":{},"game_version":"99.0.0","world":{{}}}}"#,
+            FORMAT_VERSION + 1
+        );
+        match from_str(&save_data) {
+            Err(LoadError::Incompatible { game_version, .. }) => assert_eq!(game_version, "99.0.0"),
+            other => panic!("expected incompatible save, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn broken_save_is_corrupted() {
+        let save_data = format!(r#"{{"format_version":{},"world":42}}"#, FORMAT_VERSION);
+        match from_str(&save_data) {
+            Err(LoadError::Corrupted(_)) => (),
+            other => panic!("expected corrupted save, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+/// Ids of the entities with a `Symbol` by their position, so that finding what
+/// is on a tile doesn't need to go through all the entities. It is not saved
+/// but rebuilt from the symbols when a game is loaded.
+#[derive(Debug, Default)]
+pub struct SpatialIndex {
+    tiles: HashMap<(i32, i32), Vec<u32>>,
+}
+
+impl SpatialIndex {
+    /// Adds the entity to the tile, the ids on a tile are kept sorted.
+    pub fn insert(&mut self, id: u32, pos: (i32, i32)) {
+        let ids = self.tiles.entry(pos).or_default();
+        if let Err(index) = ids.binary_search(&id) {
+            ids.insert(index, id);
+        }
+    }
+
+    pub fn remove(&mut self, id: u32, pos: (i32, i32)) {
+        if let Some(ids) = self.tiles.get_mut(&pos) {
+            ids.retain(|&other_id| other_id != id);
+            if ids.is_empty() {
+                self.tiles.remove(&pos);
+            }
+        }
+    }
+
+    pub fn at(&self, pos: (i32, i32)) -> &[u32] {
+        self.tiles.get(&pos).map_or(&[], |ids| ids.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod spatial_tests {
+    use super::*;
+    use crate::cmtp::{Character, MapCell, PlayerAction, Symbol};
+    use crate::engine::{self, game};
+    use crate::{cfg, headless, test_util};
+
+    fn positions_by_index(world: &game::World) -> Vec<(u32, Vec<u32>)> {
+        world
+            .query::<&Symbol>()
+            .map(|(id, symbol)| (id, world.entities_at(symbol.x, symbol.y).to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn ids_on_tile_are_sorted_and_removed() {
+        let mut index = SpatialIndex::default();
+        index.insert(5, (1, 1));
+        index.insert(2, (1, 1));
+        index.insert(3, (2, 1));
+        assert_eq!(index.at((1, 1)), &[2, 5]);
+        index.remove(2, (1, 1));
+        index.remove(5, (1, 1));
+        assert!(index.at((1, 1)).is_empty());
+        assert!(!index.tiles.contains_key(&(1, 1)));
+        assert_eq!(index.at((2, 1)), &[3]);
+    }
+
+    #[test]
+    fn world_index_follows_moves_and_destruction() {
+        let mut world = game::World::default();
+        for _ in 0..cfg::settings().map.width * cfg::settings().map.height {
+            game::new_entity()
+                .add(MapCell {
+                    block: false,
+                    explored: false,
+                    block_sight: false,
+                    in_fov: false,
+                })
+                .create(&mut world);
+        }
+        let monster = test_util::spawn_character(&mut world, (1, 1), Character::default());
+        let other = test_util::spawn_character(&mut world, (4, 1), Character::default());
+        engine::move_by(monster, 1, 0, &mut world);
+        assert!(world.entities_at(1, 1).is_empty());
+        assert_eq!(world.entities_at(2, 1), &[monster]);
+        assert_eq!(world.entities_in_radius(3, 1, 1.0), vec![monster, other]);
+        world.destroy_entity(monster);
+        assert!(world.entities_at(2, 1).is_empty());
+        assert_eq!(world.entities_in_radius(3, 1, 1.0), vec![other]);
+    }
+
+    #[test]
+    fn index_matches_symbols_after_headless_game() {
+        let mut simulation = headless::Simulation::new(3);
+        let actions = [
+            PlayerAction::GoToLeft,
+            PlayerAction::GoToUp,
+            PlayerAction::InteractWithMap,
+        ];
+        simulation.run(actions.iter().cycle().take(200).cloned());
+        let world = &mut simulation.world;
+        let indexed = positions_by_index(world);
+        world.rebuild_spatial_index();
+        assert_eq!(indexed, positions_by_index(world));
+    }
+}
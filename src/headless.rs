@@ -0,0 +1,190 @@
+use crate::cmtp::{PlayerAction, PlayerState};
+use crate::engine::game;
+use crate::systems;
+use std::io::{self, BufRead as _};
+use tcod::map;
+
+/// Runs the gameplay systems without a tcod window: player actions come from a
+/// script instead of `systems::input`, and nothing is rendered.
+pub struct Simulation {
+    pub world: game::World,
+    fov: map::Map,
+    is_quit: bool,
+}
+
+impl Simulation {
+    /// Starts a new game with the given seed and generates the first level.
+    pub fn new(seed: u64) -> Self {
+        let mut world = game::World {
+            seed,
+            ..Default::default()
+        };
+        world.player.state = PlayerState::MakingTurn;
+        let mut simulation = Simulation {
+            world,
+            fov: map::Map::new(1, 1),
+            is_quit: false,
+        };
+        simulation.step(PlayerAction::None);
+        simulation
+    }
+
+    /// Whether the script can't go on: the player died or quit the game.
+    pub fn is_over(&self) -> bool {
+        self.is_quit || !self.world.player_is_alive()
+    }
+
+    /// Makes one frame of the game loop with the given action. Unlike the real
+    /// game, `Cancel` outside of dialogs ends the game without saving it.
+    pub fn step(&mut self, action: PlayerAction) {
+        let world = &mut self.world;
+        let is_quitting = world.dialogs.is_empty()
+            && (action == PlayerAction::Cancel)
+            && (world.player.state == PlayerState::MakingTurn);
+        if is_quitting {
+            self.is_quit = true;
+            return;
+        }
+        world.player.action = action;
+        systems::dungeon::update(world);
+        systems::message_box::update(world);
+        systems::map_interaction::update(world);
+        systems::player_action::update(world);
+        systems::search::update(world);
+        systems::ai::update(world);
+        systems::inventory::update(world);
+        systems::death::update(world);
+        systems::character::update(world);
+        systems::fov::update(world, &mut self.fov);
+        // nothing is rendered, so the projectiles don't need to fly
+        world.projectiles.clear();
+    }
+
+    /// Steps through the actions until they run out or the game is over.
+    pub fn run(&mut self, actions: impl IntoIterator<Item = PlayerAction>) {
+        for action in actions {
+            if self.is_over() {
+                break;
+            }
+            self.step(action);
+        }
+    }
+}
+
+/// Plays a game with actions read from stdin, one JSON value per line (e.g.
+/// `"GoToUp"` or `{"ClickAt":[10,5]}`), and prints how it ended.
+pub fn run_stdin(seed: u64) -> io::Result<()> {
+    let mut actions = vec![];
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            let action = serde_json::from_str::<PlayerAction>(&line)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            actions.push(action);
+        }
+    }
+    let mut simulation = Simulation::new(seed);
+    simulation.run(actions);
+    let world = &simulation.world;
+    println!(
+        "headless game over: seed {}, mine level {}, player is {}",
+        world.seed,
+        world.player.dungeon_level,
+        if world.player_is_alive() {
+            "alive"
+        } else {
+            "dead"
+        },
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod simulation_tests {
+    use super::*;
+
+    fn script() -> Vec<PlayerAction> {
+        use PlayerAction::*;
+        [
+            GoToUp,
+            GoToLeft,
+            GoToDown,
+            GoToRight,
+            SkipTurn,
+            InteractWithMap,
+        ]
+        .iter()
+        .cycle()
+        .take(300)
+        .cloned()
+        .collect()
+    }
+
+    #[test]
+    fn new_simulation_generates_first_level() {
+        let simulation = Simulation::new(7);
+        assert!(!simulation.world.map.is_empty());
+        assert_eq!(simulation.world.player.dungeon_level, 1);
+        assert!(!simulation.is_over());
+    }
+
+    #[test]
+    fn same_script_gives_same_world() {
+        let mut first = Simulation::new(7);
+        first.run(script());
+        let mut second = Simulation::new(7);
+        second.run(script());
+        assert_eq!(
+            serde_json::to_string(&first.world).unwrap(),
+            serde_json::to_string(&second.world).unwrap()
+        );
+    }
+
+    #[test]
+    fn stairs_lead_back_to_the_same_level() {
+        use crate::cmtp::{Character, Stairs, Symbol};
+        let stairs_pos = |world: &game::World, kind: Stairs| {
+            world
+                .query::<(&Symbol, &Stairs)>()
+                .find(|&(_, (_, &stairs))| stairs == kind)
+                .map(|(_, (symbol, _))| (symbol.x, symbol.y))
+                .unwrap()
+        };
+        let monsters = |world: &game::World| {
+            world
+                .query::<&Character>()
+                .map(|(id, _)| id)
+                .filter(|&id| id != world.player.id)
+                .collect::<Vec<_>>()
+        };
+        let blocks = |world: &game::World| world.map.iter().map(|c| c.block).collect::<Vec<_>>();
+        let mut simulation = Simulation::new(7);
+        let first_level = (blocks(&simulation.world), monsters(&simulation.world));
+        let (x, y) = stairs_pos(&simulation.world, Stairs::Down);
+        let player_id = simulation.world.player.id;
+        simulation.world.set_position(player_id, x, y);
+        simulation.run(vec![PlayerAction::InteractWithMap, PlayerAction::None]);
+        let world = &simulation.world;
+        assert_eq!(world.player.dungeon_level, 2);
+        assert!(world.levels.contains_key(&1));
+        assert_eq!(
+            stairs_pos(world, Stairs::Up),
+            (world.player_sym().x, world.player_sym().y)
+        );
+        simulation.step(PlayerAction::InteractWithMap);
+        let world = &simulation.world;
+        assert_eq!(world.player.dungeon_level, 1);
+        assert!(world.levels.contains_key(&2) && !world.levels.contains_key(&1));
+        assert_eq!((blocks(world), monsters(world)), first_level);
+        assert_eq!((world.player_sym().x, world.player_sym().y), (x, y));
+    }
+
+    #[test]
+    fn cancel_ends_game_without_resetting_world() {
+        let mut simulation = Simulation::new(7);
+        simulation.run(vec![PlayerAction::Cancel, PlayerAction::GoToUp]);
+        assert!(simulation.is_over());
+        assert!(!simulation.world.map.is_empty());
+    }
+}
@@ -1,13 +1,53 @@
 mod cfg;
 mod cmtp;
 mod engine;
+mod headless;
 mod systems;
+#[cfg(test)]
+mod test_util;
 
 fn main() {
     use engine::game;
     use tcod::console;
 
-    tcod::system::set_fps(cfg::LIMIT_FPS);
+    let mut world: game::World = Default::default();
+    let mut recording = systems::recording::Recording::Off;
+    let mut args = std::env::args().skip(1);
+    match args.next().as_deref() {
+        // `expt01 --headless [seed] < actions` plays without a window, see `headless::run_stdin`
+        Some("--headless") => {
+            let seed = args
+                .next()
+                .and_then(|seed| seed.parse().ok())
+                .unwrap_or_else(rand::random);
+            if let Err(err) = headless::run_stdin(seed) {
+                eprintln!("headless game failed: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        // `expt01 --record <file>` writes the actions of every new game to the file
+        Some("--record") => {
+            let path = args.next().unwrap_or_else(|| String::from("recording"));
+            recording = systems::recording::Recording::write_to(path);
+        }
+        // `expt01 --replay <file>` plays a recorded game back
+        Some("--replay") => {
+            let path = args.next().unwrap_or_else(|| String::from("recording"));
+            match systems::recording::Recording::replay(&path, &mut world) {
+                Ok(replay) => recording = replay,
+                Err(err) => {
+                    eprintln!("can't replay {}: {}", path, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => (),
+    }
+    if let Err(err) = engine::save::prepare_slots() {
+        eprintln!("can't prepare the save slots: {}", err);
+    }
+    tcod::system::set_fps(cfg::settings().screen.limit_fps);
     let spritesheet = if tcod::system::get_current_resolution() >= (1920, 1080) {
         "spritesheet-14px-2x.png"
     } else {
@@ -16,23 +56,29 @@ fn main() {
     let root = console::Root::initializer()
         .font(spritesheet, console::FontLayout::AsciiInRow)
         .font_type(console::FontType::Default)
-        .size(cfg::SCREEN_WIDTH, cfg::SCREEN_HEIGHT)
+        .size(cfg::settings().screen.width, cfg::settings().screen.height)
         .title("saintech's experiments: Expt01")
         .init();
     let mut tcod = game::Tcod {
         root: root,
-        con: console::Offscreen::new(cfg::MAP_WIDTH, cfg::MAP_HEIGHT),
-        panel: console::Offscreen::new(cfg::SCREEN_WIDTH, cfg::PANEL_HEIGHT),
+        con: console::Offscreen::new(cfg::settings().map.width, cfg::settings().map.height),
+        panel: console::Offscreen::new(
+            cfg::settings().screen.width,
+            cfg::settings().gui.panel_height,
+        ),
     };
     let mut fov = tcod::map::Map::new(1, 1);
-    let mut world: game::World = Default::default();
     while !tcod.root.window_closed() && !world.must_be_destroyed {
-        systems::input::update(&mut world);
+        if !systems::recording::play(&mut world, &mut recording) {
+            systems::input::update(&mut world);
+        }
         systems::main_menu::update(&mut world);
+        systems::recording::record(&world, &mut recording);
         systems::dungeon::update(&mut world);
         systems::message_box::update(&mut world);
         systems::map_interaction::update(&mut world);
         systems::player_action::update(&mut world);
+        systems::search::update(&mut world);
         systems::ai::update(&mut world);
         systems::inventory::update(&mut world);
         systems::death::update(&mut world);
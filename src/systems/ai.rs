@@ -1,36 +1,98 @@
 use crate::cfg;
-use crate::cmtp::{Ai, PlayerAction, PlayerState, Symbol};
+use crate::cmtp::{Ai, AiOption, Character, PlayerAction, PlayerState, StatusKind, Symbol};
 use crate::engine;
-use crate::engine::game;
+use crate::engine::{game, pathfinding};
+use crate::systems::status;
 use rand::Rng as _;
+use std::cmp;
 
 pub fn update(world: &mut game::World) {
     if world.player.state != PlayerState::MakingTurn {
         return;
     }
-    // let monsters take their turn
+    // let monsters take their turns until the player has the energy to act again
     if world.player_is_alive() && player_action_is_turn(world.player.action) {
-        let ai_ids: Vec<_> = world
-            .character_iter()
-            .filter(|(.., ai)| ai.option.is_some())
-            .map(|(id, ..)| id)
-            .collect();
-        for id in ai_ids {
-            let ai_container = world.get_character_mut(id).unwrap().3;
-            let ai = ai_container.option.take().unwrap();
-            let new_ai = match ai {
-                Ai::Basic => ai_basic(id, world),
-                Ai::Confused {
-                    previous_ai,
-                    num_turns,
-                } => ai_confused(id, world, previous_ai, num_turns),
+        world.player.turn += 1;
+        let turns = &cfg::settings().turns;
+        if world.player_char().energy >= turns.energy_to_act {
+            // the action was free, like waiting or bumping into a wall
+            engine::spend_energy(world.player.id, turns.wait_cost, world);
+        }
+        while world.player_is_alive() && (world.player_char().energy < turns.energy_to_act) {
+            give_energy(world);
+            status::tick(world);
+            take_monster_turns(world);
+        }
+    }
+}
+
+/// One tick of the scheduler: every living character gains its speed in energy.
+fn give_energy(world: &mut game::World) {
+    let speeds: Vec<_> = world
+        .query::<&Character>()
+        .filter(|(_, char)| char.alive)
+        .map(|(id, _)| (id, world.speed(id)))
+        .collect();
+    for (id, speed) in speeds {
+        // no one is stopped forever
+        world.get_character_mut(id).unwrap().2.energy += cmp::max(speed, 1);
+    }
+}
+
+/// Every monster acts while it has enough energy, so the fast ones can act
+/// several times in one tick.
+fn take_monster_turns(world: &mut game::World) {
+    let energy_to_act = cfg::settings().turns.energy_to_act;
+    let monsters: Vec<_> = world
+        .query::<(&Character, &AiOption)>()
+        .filter(|(_, (char, ai_container))| char.alive && ai_container.option.is_some())
+        .map(|(id, _)| id)
+        .collect();
+    for id in monsters {
+        while world.player_is_alive() {
+            let energy = world.get_character(id).unwrap().2.energy;
+            if energy < energy_to_act {
+                break;
+            }
+            let ai = world
+                .get_character_mut(id)
+                .unwrap()
+                .3
+                .option
+                .take()
+                .unwrap();
+            let new_ai = if world.has_status(id, StatusKind::Stun) {
+                ai
+            } else if world.has_status(id, StatusKind::Confusion) {
+                stumble(id, world);
+                ai
+            } else {
+                take_turn(id, ai, world)
             };
-            let ai_container = world.get_character_mut(id).unwrap().3;
+            let (.., char, ai_container) = world.get_character_mut(id).unwrap();
             ai_container.option.replace(new_ai);
+            if char.energy == energy {
+                // the monster did nothing, it waits
+                char.energy -= cfg::settings().turns.wait_cost;
+            }
         }
     }
 }
 
+fn take_turn(id: u32, ai: Ai, world: &mut game::World) -> Ai {
+    match ai {
+        Ai::Basic => ai_basic(id, world),
+        Ai::Coward { flee_below } => ai_coward(id, world, flee_below),
+        Ai::Ranged {
+            range,
+            keep_distance,
+        } => ai_ranged(id, world, range, keep_distance),
+        Ai::Pack { allies, radius } => ai_pack(id, world, allies, radius),
+        Ai::Stationary => ai_stationary(id, world),
+        Ai::Guard { radius, post } => ai_guard(id, world, radius, post),
+    }
+}
+
 fn player_action_is_turn(action: PlayerAction) -> bool {
     use PlayerAction::*;
     return match action {
@@ -41,28 +103,219 @@ fn player_action_is_turn(action: PlayerAction) -> bool {
 }
 
 fn ai_basic(monster_id: u32, world: &mut game::World) -> Ai {
-    let monster_symbol = world.get_character(monster_id).unwrap().0;
-    let (monster_x, monster_y) = (monster_symbol.x, monster_symbol.y);
-    let player_symbol = world.player_sym();
-    let (player_x, player_y) = (player_symbol.x, player_symbol.y);
-    if (monster_x > player_x) || ((monster_x == player_x) && (monster_y < player_y)) {
-        world.get_character_mut(monster_id).unwrap().2.looking_right = false;
+    face_player(monster_id, world);
+    if sees_player(monster_id, world) {
+        chase_player(monster_id, world);
     } else {
-        world.get_character_mut(monster_id).unwrap().2.looking_right = true;
-    }
-    if world.check_fov(monster_id) {
-        if game::World::distance_to(monster_x, monster_y, player_x, player_y) >= 2.0 {
-            // move towards player if far away
-            move_towards(monster_id, player_x, player_y, world);
-        } else if world.player_char().hp > 0 {
-            // close enough, attack! (if the player is still alive.)
-            engine::attack_by(monster_id, world.player.id, world);
-        }
+        search_player(monster_id, world);
     }
     Ai::Basic
 }
 
+fn ai_coward(monster_id: u32, world: &mut game::World, flee_below: f32) -> Ai {
+    face_player(monster_id, world);
+    let hp = world.get_character(monster_id).unwrap().2.hp;
+    let is_scared = (hp as f32) < (world.max_hp(monster_id) as f32) * flee_below;
+    if !sees_player(monster_id, world) {
+        search_player(monster_id, world);
+    } else if !is_scared || !step_away_from_player(monster_id, world) {
+        // a cornered coward fights back
+        chase_player(monster_id, world);
+    }
+    Ai::Coward { flee_below }
+}
+
+fn ai_ranged(monster_id: u32, world: &mut game::World, range: i32, keep_distance: i32) -> Ai {
+    face_player(monster_id, world);
+    if sees_player(monster_id, world) {
+        let distance = distance_to_player(monster_id, world);
+        let is_too_close = distance < keep_distance as f32;
+        if !(is_too_close && step_away_from_player(monster_id, world)) {
+            let player_id = world.player.id;
+            if engine::shoot_by(monster_id, player_id, range, world).is_err() {
+                // out of range or no clear shot
+                let (player_x, player_y) = player_pos(world);
+                move_towards(monster_id, player_x, player_y, world);
+            }
+        }
+    } else {
+        search_player(monster_id, world);
+    }
+    Ai::Ranged {
+        range,
+        keep_distance,
+    }
+}
+
+fn ai_pack(monster_id: u32, world: &mut game::World, allies: usize, radius: i32) -> Ai {
+    face_player(monster_id, world);
+    if sees_player(monster_id, world) {
+        let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+        let allies_near = world
+            .entities_in_radius(x, y, radius as f32)
+            .into_iter()
+            .filter(|&id| (id != monster_id) && (id != world.player.id))
+            .filter_map(|id| world.get_character(id))
+            // the AIs are taken out while the monsters make their turns, so
+            // only being alive is checked
+            .filter(|(.., char, _)| char.alive)
+            .count();
+        // the ones that are already in the fight don't wait
+        if (allies_near >= allies) || (distance_to_player(monster_id, world) < 2.0) {
+            chase_player(monster_id, world);
+        }
+    } else {
+        search_player(monster_id, world);
+    }
+    Ai::Pack { allies, radius }
+}
+
+fn ai_stationary(monster_id: u32, world: &mut game::World) -> Ai {
+    face_player(monster_id, world);
+    if sees_player(monster_id, world) && (distance_to_player(monster_id, world) < 2.0) {
+        engine::attack_by(monster_id, world.player.id, world);
+    }
+    Ai::Stationary
+}
+
+fn ai_guard(monster_id: u32, world: &mut game::World, radius: i32, post: Option<(i32, i32)>) -> Ai {
+    face_player(monster_id, world);
+    let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+    // the guard takes its post where it has been spawned
+    let (post_x, post_y) = post.unwrap_or((x, y));
+    let (player_x, player_y) = player_pos(world);
+    let player_is_near_post =
+        game::World::distance_to(post_x, post_y, player_x, player_y) <= radius as f32;
+    if sees_player(monster_id, world) && player_is_near_post {
+        chase_player(monster_id, world);
+    } else if (x, y) != (post_x, post_y) {
+        move_towards(monster_id, post_x, post_y, world);
+    }
+    Ai::Guard {
+        radius,
+        post: Some((post_x, post_y)),
+    }
+}
+
+fn player_pos(world: &game::World) -> (i32, i32) {
+    let player_symbol = world.player_sym();
+    (player_symbol.x, player_symbol.y)
+}
+
+fn distance_to_player(monster_id: u32, world: &game::World) -> f32 {
+    let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+    let (player_x, player_y) = player_pos(world);
+    game::World::distance_to(x, y, player_x, player_y)
+}
+
+fn face_player(monster_id: u32, world: &mut game::World) {
+    let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+    let (player_x, player_y) = player_pos(world);
+    let looking_right = !((x > player_x) || ((x == player_x) && (y < player_y)));
+    world.get_character_mut(monster_id).unwrap().2.looking_right = looking_right;
+}
+
+/// Whether the monster sees the player. If it does, it remembers where the
+/// player is.
+fn sees_player(monster_id: u32, world: &mut game::World) -> bool {
+    let is_seen = world.check_fov(monster_id);
+    if is_seen {
+        let player_pos = player_pos(world);
+        let memory = &mut world.get_character_mut(monster_id).unwrap().3.memory;
+        memory.last_seen = Some(player_pos);
+        memory.search_turns = cfg::settings().ai.search_turns;
+    }
+    is_seen
+}
+
+fn chase_player(monster_id: u32, world: &mut game::World) {
+    let (player_x, player_y) = player_pos(world);
+    if distance_to_player(monster_id, world) >= 2.0 {
+        // move towards player if far away
+        move_towards(monster_id, player_x, player_y, world);
+    } else if world.player_char().hp > 0 {
+        // close enough, attack! (if the player is still alive.)
+        engine::attack_by(monster_id, world.player.id, world);
+    }
+}
+
+/// Steps to the free tile that is the farthest from the player. Returns false if
+/// there is no way to get farther.
+fn step_away_from_player(monster_id: u32, world: &mut game::World) -> bool {
+    let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+    let (player_x, player_y) = player_pos(world);
+    let distance_from = |x, y| game::World::distance_to(x, y, player_x, player_y);
+    let best_step = (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+        .filter(|&(dx, dy)| !world.is_blocked(x + dx, y + dy))
+        .map(|(dx, dy)| (dx, dy, distance_from(x + dx, y + dy)))
+        .filter(|&(.., distance)| distance > distance_from(x, y))
+        .fold(None, |best: Option<(i32, i32, f32)>, step| match best {
+            Some(best) if best.2 >= step.2 => Some(best),
+            _ => Some(step),
+        });
+    if let Some((dx, dy, _)) = best_step {
+        engine::move_by(monster_id, dx, dy, world);
+    }
+    best_step.is_some()
+}
+
+/// Goes to the position where the player was seen last time and wanders around
+/// it, until the search turns run out and the monster forgets the player.
+fn search_player(monster_id: u32, world: &mut game::World) {
+    let memory = &mut world.get_character_mut(monster_id).unwrap().3.memory;
+    let last_seen = match memory.last_seen {
+        Some(_) if memory.search_turns <= 0 => {
+            memory.last_seen = None;
+            return;
+        }
+        Some(last_seen) => last_seen,
+        None => return,
+    };
+    memory.search_turns -= 1;
+    let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+    let path = pathfinding::find_path(world, (x, y), last_seen);
+    match path {
+        Some(path) if (x, y) != last_seen => {
+            let (next_x, next_y) = path[0];
+            engine::move_by(monster_id, next_x - x, next_y - y, world);
+        }
+        _ => wander_around(monster_id, last_seen, world),
+    }
+}
+
+fn wander_around(monster_id: u32, (center_x, center_y): (i32, i32), world: &mut game::World) {
+    let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+    let radius = cfg::settings().ai.search_radius;
+    let steps: Vec<_> = (-1..=1)
+        .flat_map(|dx| (-1..=1).map(move |dy| (dx, dy)))
+        .filter(|&(dx, dy)| {
+            ((dx, dy) != (0, 0))
+                && ((x + dx - center_x).abs() <= radius)
+                && ((y + dy - center_y).abs() <= radius)
+                && !world.is_blocked(x + dx, y + dy)
+        })
+        .collect();
+    if !steps.is_empty() {
+        let (dx, dy) = steps[world.rng.gen_range(0, steps.len())];
+        engine::move_by(monster_id, dx, dy, world);
+    }
+}
+
+/// Moves one step along the shortest path to the target, or straight to it if
+/// the way is blocked, e.g. by other monsters in a corridor.
 fn move_towards(id: u32, target_x: i32, target_y: i32, world: &mut game::World) {
+    let &Symbol { x, y, .. } = world.get_character(id).unwrap().0;
+    match pathfinding::find_path(world, (x, y), (target_x, target_y)) {
+        Some(path) => {
+            let (next_x, next_y) = path[0];
+            engine::move_by(id, next_x - x, next_y - y, world);
+        }
+        None => move_straight_towards(id, target_x, target_y, world),
+    }
+}
+
+fn move_straight_towards(id: u32, target_x: i32, target_y: i32, world: &mut game::World) {
     let &Symbol { x, y, .. } = world.get_character(id).unwrap().0;
     // vector from this object to the target, and distance
     let dx = target_x - x;
@@ -105,32 +358,275 @@ fn rotate_minus_45(x: i32, y: i32) -> (i32, i32) {
     )
 }
 
-fn ai_confused(
-    monster_id: u32,
-    world: &mut game::World,
-    previous_ai: Box<Ai>,
-    num_turns: i32,
-) -> Ai {
-    let monster_name = world.get_character(monster_id).unwrap().1.name.clone();
-    if num_turns >= 0 {
-        // still confused ...
-        // move in a random direction, and decrease the number of turns confused
-        engine::move_by(
-            monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            world,
+/// A confused monster moves in a random direction.
+fn stumble(monster_id: u32, world: &mut game::World) {
+    let dx = world.rng.gen_range(-1, 2);
+    let dy = world.rng.gen_range(-1, 2);
+    engine::move_by(monster_id, dx, dy, world);
+}
+
+#[cfg(test)]
+mod ai_tests {
+    use super::*;
+    use crate::cmtp::{DeathCallback, Equipment, Feature, ItemKind, MapObject, Slot};
+    use crate::test_util;
+    use serde::Deserialize;
+
+    fn spawn_monster(world: &mut game::World, x: i32, ai: Ai) -> u32 {
+        let monster = Character {
+            on_death: DeathCallback::Enemy,
+            ..test_util::character(10)
+        };
+        let id = test_util::spawn_character(world, (x, 1), monster);
+        world.get_mut::<&mut AiOption>(id).unwrap().option = Some(ai);
+        id
+    }
+
+    /// The player on the left end of a corridor and a monster on the right.
+    fn corridor_world() -> (game::World, u32) {
+        let corridor = ["##########", "#........#", "##########"];
+        let mut world = test_util::world_with_player(&corridor, (1, 1), test_util::character(10));
+        world.player.action = PlayerAction::SkipTurn;
+        let monster_id = spawn_monster(&mut world, 8, Ai::Basic);
+        (world, monster_id)
+    }
+
+    fn monster_x(world: &game::World, monster_id: u32) -> i32 {
+        world.get_character(monster_id).unwrap().0.x
+    }
+
+    /// Puts a monster with the AI to the corridor, where the player sees it.
+    fn spawn_seen_monster(world: &mut game::World, x: i32, ai: Ai) -> u32 {
+        let map_width = cfg::settings().map.width;
+        world.map[(map_width + x) as usize].in_fov = true;
+        spawn_monster(world, x, ai)
+    }
+
+    fn last_log(world: &game::World) -> &str {
+        &world.log.last().unwrap().0
+    }
+
+    #[test]
+    fn seen_player_is_remembered() {
+        let (mut world, monster_id) = corridor_world();
+        let map_width = cfg::settings().map.width;
+        world.map[(map_width + 8) as usize].in_fov = true;
+        update(&mut world);
+        let memory = &world.get_character(monster_id).unwrap().3.memory;
+        assert_eq!(memory.last_seen, Some((1, 1)));
+        assert_eq!(memory.search_turns, cfg::settings().ai.search_turns);
+        assert_eq!(monster_x(&world, monster_id), 7);
+    }
+
+    #[test]
+    fn monster_goes_to_last_seen_position_and_gives_up() {
+        let (mut world, monster_id) = corridor_world();
+        let memory = &mut world.get_character_mut(monster_id).unwrap().3.memory;
+        memory.last_seen = Some((5, 1));
+        memory.search_turns = 5;
+        for _ in 0..3 {
+            update(&mut world);
+        }
+        assert_eq!(monster_x(&world, monster_id), 5);
+        for _ in 0..3 {
+            update(&mut world);
+        }
+        let memory = &world.get_character(monster_id).unwrap().3.memory;
+        assert_eq!(memory.last_seen, None);
+        assert!((5 - monster_x(&world, monster_id)).abs() <= cfg::settings().ai.search_radius);
+    }
+
+    #[test]
+    fn monster_chases_the_player_through_a_door() {
+        let rooms = [
+            "#########",
+            "#...#...#",
+            "#.......#",
+            "#...#...#",
+            "#########",
+        ];
+        let mut world = test_util::world_with_player(&rooms, (1, 1), test_util::character(10));
+        world.player.action = PlayerAction::SkipTurn;
+        let door_id = game::new_entity()
+            .add(Symbol {
+                x: 4,
+                y: 2,
+                ..Default::default()
+            })
+            .add(MapObject::default())
+            .add(Feature::Door {
+                open: false,
+                group: None,
+            })
+            .create(&mut world);
+        engine::set_door_open(door_id, false, &mut world).unwrap();
+        let monster_id = spawn_monster(&mut world, 7, Ai::Basic);
+        let memory = &mut world.get_character_mut(monster_id).unwrap().3.memory;
+        memory.last_seen = Some((1, 1));
+        memory.search_turns = 10;
+        for _ in 0..8 {
+            update(&mut world);
+        }
+        assert_eq!(
+            world.get::<&Feature>(door_id),
+            Some(&Feature::Door {
+                open: true,
+                group: None
+            })
         );
-        Ai::Confused {
-            previous_ai: previous_ai,
-            num_turns: num_turns - 1,
+        assert!(monster_x(&world, monster_id) < 4);
+    }
+
+    #[test]
+    fn monster_without_memory_stays() {
+        let (mut world, monster_id) = corridor_world();
+        update(&mut world);
+        assert_eq!(monster_x(&world, monster_id), 8);
+    }
+
+    #[test]
+    fn behaviours_are_read_from_toml() {
+        #[derive(Deserialize)]
+        struct Monster {
+            ai: Ai,
         }
-    } else {
-        // restore the previous AI (this one will be deleted)
-        world.add_log(
-            cfg::COLOR_ORANGE,
-            format!("The {} is no longer confused!", monster_name),
+        let ai_of = |toml_str| toml::from_str::<Monster>(toml_str).unwrap().ai;
+        assert!(matches!(ai_of("ai = 'Stationary'"), Ai::Stationary));
+        assert!(matches!(
+            ai_of("ai = { Coward = { flee_below = 0.3 } }"),
+            Ai::Coward { flee_below } if flee_below == 0.3
+        ));
+        assert!(matches!(
+            ai_of("ai = { Guard = { radius = 5 } }"),
+            Ai::Guard {
+                radius: 5,
+                post: None
+            }
+        ));
+    }
+
+    #[test]
+    fn hurt_coward_runs_away() {
+        let (mut world, _) = corridor_world();
+        let coward = spawn_seen_monster(&mut world, 2, Ai::Coward { flee_below: 0.5 });
+        update(&mut world);
+        assert!(last_log(&world).contains("attacks"));
+        world.get_character_mut(coward).unwrap().2.hp = 4;
+        update(&mut world);
+        assert_eq!(monster_x(&world, coward), 3);
+    }
+
+    #[test]
+    fn ranged_monster_shoots_and_keeps_distance() {
+        let (mut world, monster_id) = corridor_world();
+        world.destroy_entity(monster_id);
+        let ai = Ai::Ranged {
+            range: 6,
+            keep_distance: 3,
+        };
+        let shooter = spawn_seen_monster(&mut world, 3, ai);
+        let map_width = cfg::settings().map.width;
+        world.map[(map_width + 4) as usize].in_fov = true;
+        update(&mut world);
+        assert_eq!(monster_x(&world, shooter), 4);
+        update(&mut world);
+        assert_eq!(monster_x(&world, shooter), 4);
+        assert!(last_log(&world).contains("attacks"));
+    }
+
+    #[test]
+    fn pack_monster_waits_for_allies() {
+        let (mut world, monster_id) = corridor_world();
+        world.destroy_entity(monster_id);
+        let ai = || Ai::Pack {
+            allies: 1,
+            radius: 3,
+        };
+        let first = spawn_seen_monster(&mut world, 4, ai());
+        update(&mut world);
+        assert_eq!(monster_x(&world, first), 4);
+        let second = spawn_seen_monster(&mut world, 6, ai());
+        update(&mut world);
+        assert_eq!(monster_x(&world, first), 3);
+        assert_eq!(monster_x(&world, second), 5);
+    }
+
+    #[test]
+    fn stationary_monster_doesnt_move() {
+        let (mut world, _) = corridor_world();
+        let statue = spawn_seen_monster(&mut world, 4, Ai::Stationary);
+        update(&mut world);
+        assert_eq!(monster_x(&world, statue), 4);
+    }
+
+    #[test]
+    fn guard_returns_to_post() {
+        let (mut world, guard) = corridor_world();
+        let guard_ai = Ai::Guard {
+            radius: 3,
+            post: None,
+        };
+        world.get_character_mut(guard).unwrap().3.option = Some(guard_ai);
+        update(&mut world);
+        let ai = &world.get_character(guard).unwrap().3.option;
+        assert!(matches!(
+            ai,
+            Some(Ai::Guard {
+                post: Some((8, 1)),
+                ..
+            })
+        ));
+        world.set_position(guard, 6, 1);
+        update(&mut world);
+        assert_eq!(monster_x(&world, guard), 7);
+    }
+
+    fn set_speed(world: &mut game::World, id: u32, speed: i32) {
+        world.get_character_mut(id).unwrap().2.speed = speed;
+    }
+
+    #[test]
+    fn fast_monster_acts_twice_and_slow_one_every_other_turn() {
+        let (mut world, fast) = corridor_world();
+        let slow = spawn_monster(&mut world, 8, Ai::Basic);
+        world.set_position(slow, 8, 1);
+        world.set_position(fast, 6, 1);
+        set_speed(&mut world, fast, 200);
+        set_speed(&mut world, slow, 50);
+        let map_width = cfg::settings().map.width;
+        for x in 1..9 {
+            world.map[(map_width + x) as usize].in_fov = true;
+        }
+        update(&mut world);
+        assert_eq!(monster_x(&world, fast), 4);
+        assert_eq!(monster_x(&world, slow), 8);
+        update(&mut world);
+        assert_eq!(monster_x(&world, fast), 2);
+        assert_eq!(monster_x(&world, slow), 7);
+    }
+
+    #[test]
+    fn slow_attack_gives_monsters_more_turns() {
+        let (mut world, monster_id) = corridor_world();
+        world.set_position(monster_id, 2, 1);
+        let map_width = cfg::settings().map.width;
+        world.map[(map_width + 2) as usize].in_fov = true;
+        world.player_char_mut().energy = cfg::settings().turns.energy_to_act;
+        let player_id = world.player.id;
+        let sword_id =
+            test_util::give_equipment(&mut world, player_id, ItemKind::Melee, Slot::Hands);
+        world
+            .get_mut::<&mut Equipment>(sword_id)
+            .unwrap()
+            .attack_cost = Some(200);
+        world.get_character_mut(monster_id).unwrap().2.base_power = 1;
+        engine::attack_by(world.player.id, monster_id, &mut world);
+        let log_len = world.log.len();
+        update(&mut world);
+        assert_eq!(world.log.len(), log_len + 2);
+        assert_eq!(
+            world.player_char().energy,
+            cfg::settings().turns.energy_to_act
         );
-        *previous_ai
     }
 }
@@ -4,8 +4,9 @@ use crate::engine::game;
 
 fn get_lvl_up_player(world: &mut game::World) -> Option<&mut Character> {
     if (world.player.state == PlayerState::MakingTurn) && world.player_is_alive() {
-        Some(world.player_char_mut())
-            .filter(|char| char.xp >= cfg::LEVEL_UP_BASE + char.level * cfg::LEVEL_UP_FACTOR)
+        Some(world.player_char_mut()).filter(|char| {
+            char.xp >= cfg::settings().level_up.base + char.level * cfg::settings().level_up.factor
+        })
     } else {
         None
     }
@@ -24,11 +25,12 @@ pub fn update(world: &mut game::World) {
         let base_max_hp = player.base_max_hp;
         let base_power = player.base_power;
         let base_defense = player.base_defense;
-        let level_up_xp = cfg::LEVEL_UP_BASE + player.level * cfg::LEVEL_UP_FACTOR;
+        let level_up_xp =
+            cfg::settings().level_up.base + player.level * cfg::settings().level_up.factor;
         player.level += 1;
         player.xp -= level_up_xp;
         world.add_log(
-            cfg::COLOR_ORANGE,
+            cfg::settings().colors.orange,
             format!(
                 "Your battle skills grow stronger! You reached level {}!",
                 new_level,
@@ -44,7 +46,7 @@ pub fn update(world: &mut game::World) {
             DialogKind::LevelUp,
             header,
             options,
-            cfg::LEVEL_SCREEN_WIDTH,
+            cfg::settings().gui.level_screen_width,
         );
         world.player.state = PlayerState::InDialog;
     } else if lvl_up_is_open {
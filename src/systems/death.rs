@@ -24,11 +24,11 @@ pub fn update(world: &mut game::World) {
 
 fn player_death(_id: u32, world: &mut game::World) {
     // the game ended!
-    world.add_log(cfg::COLOR_DARK_RED, "You died!");
+    world.add_log(cfg::settings().colors.dark_red, "You died!");
     // for added effect, transform the player into a corpse!
     let (symbol, _, char, _) = world.get_character_mut(world.player.id).unwrap();
     symbol.glyph = '\u{A3}';
-    symbol.color = cfg::COLOR_DARK_RED;
+    symbol.color = cfg::settings().colors.dark_red;
     char.on_death = DeathCallback::None;
 }
 
@@ -38,12 +38,12 @@ fn monster_death(monster_id: u32, world: &mut game::World) {
     // transform it into a nasty corpse! it doesn't block, can't be
     // attacked and doesn't move
     world.add_log(
-        cfg::COLOR_ORANGE,
+        cfg::settings().colors.orange,
         format!("{} is dead! You gain {} experience points.", name, xp),
     );
     let (symbol, map_obj, ..) = world.get_character_mut(monster_id).unwrap();
     symbol.glyph = '\u{A3}';
-    symbol.color = cfg::COLOR_DARK_RED;
+    symbol.color = cfg::settings().colors.dark_red;
     map_obj.block = false;
     map_obj.name = format!("remains of {}", map_obj.name);
     world.entity_indexes.get_mut(&monster_id).unwrap().character = None;
@@ -1,41 +1,15 @@
 use crate::cfg;
-use crate::cmtp::{AiOption, DialogKind, MapCell, MapObject, PlayerAction, PlayerState, Symbol};
+use crate::cmtp::{
+    AiOption, DialogKind, Feature, Item, MapCell, MapObject, PlayerAction, PlayerState, Stairs,
+    StatusEffects, Symbol,
+};
+use crate::engine;
 use crate::engine::asset;
 use crate::engine::game;
+use crate::engine::mapgen::{self, Rect};
+use crate::engine::save;
 use rand::distributions::{Distribution as _, WeightedIndex};
 use rand::Rng as _;
-use std::{cmp, fs, io::Write as _};
-
-/// A rectangle on the map, used to characterise a room.
-#[derive(Clone, Copy, Debug)]
-struct Rect {
-    x1: i32,
-    y1: i32,
-    x2: i32,
-    y2: i32,
-}
-
-impl Rect {
-    fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
-        Rect {
-            x1: x,
-            y1: y,
-            x2: x + w,
-            y2: y + h,
-        }
-    }
-    fn center(&self) -> (i32, i32) {
-        let center_x = (self.x1 + self.x2) / 2;
-        let center_y = (self.y1 + self.y2) / 2;
-        (center_x, center_y)
-    }
-    fn intersects_with(&self, other: &Rect) -> bool {
-        (self.x1 <= other.x2)
-            && (self.x2 >= other.x1)
-            && (self.y1 <= other.y2)
-            && (self.y2 >= other.y1)
-    }
-}
 
 fn is_exiting_to_main_menu(world: &game::World) -> bool {
     world.dialogs.is_empty()
@@ -45,8 +19,13 @@ fn is_exiting_to_main_menu(world: &game::World) -> bool {
 
 pub fn update(world: &mut game::World) {
     if world.map.is_empty() && (world.player.state == PlayerState::MakingTurn) {
-        match (asset::CharactersLoader::load(), asset::ItemsLoader::load()) {
-            (Err(err), _) => {
+        let loaded = (
+            asset::CharactersLoader::load(),
+            asset::ItemsLoader::load(),
+            asset::TrapsLoader::load(),
+        );
+        match loaded {
+            (Err(err), ..) => {
                 let msg = format!(
                     "Error in the characters config.\nFix the error or delete the character \
                     (see \"dummy\" character for example\"):\n\n{}",
@@ -55,7 +34,7 @@ pub fn update(world: &mut game::World) {
                 world.add_dialog_box(DialogKind::MessageBox, msg, vec![], 36);
                 world.player.state = PlayerState::InDialog;
             }
-            (_, Err(err)) => {
+            (_, Err(err), _) => {
                 let msg = format!(
                     "Error in the items config.\nFix the error or delete the item \
                     (see \"dummy\" item for example\"):\n\n{}",
@@ -64,90 +43,333 @@ pub fn update(world: &mut game::World) {
                 world.add_dialog_box(DialogKind::MessageBox, msg, vec![], 36);
                 world.player.state = PlayerState::InDialog;
             }
-            (Ok(char_loader), Ok(items_loader)) => {
-                if world.entity_indexes.get(&world.player.id).is_none() {
-                    spawn_player(world, &char_loader, &items_loader);
-                }
-                make_map(
-                    world,
-                    &char_loader,
-                    &items_loader,
-                    world.player.dungeon_level,
+            (.., Err(err)) => {
+                let msg = format!(
+                    "Error in the traps config.\nFix the error or delete the trap \
+                    (see \"dummy\" trap for example\"):\n\n{}",
+                    err,
                 );
-                world.player.action = PlayerAction::None;
+                world.add_dialog_box(DialogKind::MessageBox, msg, vec![], 36);
+                world.player.state = PlayerState::InDialog;
+            }
+            (Ok(char_loader), Ok(items_loader), Ok(traps_loader)) => {
+                match asset::VaultsLoader::load(&char_loader, &items_loader) {
+                    Err(err) => {
+                        let msg = format!(
+                            "Error in the vaults config.\nFix the error or delete the vault \
+                            (see \"dummy\" vault for example\"):\n\n{}",
+                            err,
+                        );
+                        world.add_dialog_box(DialogKind::MessageBox, msg, vec![], 36);
+                        world.player.state = PlayerState::InDialog;
+                    }
+                    Ok(vaults_loader) => {
+                        if world.entity_indexes.get(&world.player.id).is_none() {
+                            spawn_player(world, &char_loader, &items_loader);
+                        }
+                        let loaders = (&char_loader, &items_loader, &traps_loader, &vaults_loader);
+                        make_map(world, loaders, world.player.dungeon_level);
+                        world.player.action = PlayerAction::None;
+                    }
+                }
             }
         }
     } else if is_exiting_to_main_menu(world) {
         world.player.action = PlayerAction::None;
-        save_game(world);
+        world.compact();
+        save::save_game(world, world.save_slot).expect("can't write the saved game");
         *world = Default::default();
     }
 }
 
-fn make_map(
+type Loaders<'a> = (
+    &'a asset::CharactersLoader,
+    &'a asset::ItemsLoader,
+    &'a asset::TrapsLoader,
+    &'a asset::VaultsLoader,
+);
+
+fn make_map(world: &mut game::World, loaders: Loaders, level: u32) {
+    let (char_loader, items_loader, traps_loader, _) = loaders;
+    world.rng = game::WorldRng::for_level(world.seed, level);
+    fill_walls(world);
+    let mut rooms = mapgen::for_level(level).generate(&mut world.map, &mut world.rng);
+    if rooms.is_empty() {
+        // the level must have somewhere to start, so dig a room in the middle
+        let size = cfg::settings().dungeon.room_max_size;
+        let map_size = &cfg::settings().map;
+        let room = Rect::new(
+            (map_size.width - size) / 2,
+            (map_size.height - size) / 2,
+            size,
+            size,
+        );
+        mapgen::create_room(room, &mut world.map);
+        rooms.push(room);
+    }
+    // the player starts at the center of the first room
+    let (start_x, start_y) = rooms[0].center();
+    world.set_position(world.player.id, start_x, start_y);
+    if level == 1 {
+        place_hints(world);
+    }
+    if world.rng.gen_range(0, 100) < cfg::settings().dungeon.vault_chance {
+        place_vault(world, loaders, level);
+    }
+    for &room in &rooms {
+        // add some content to this room, such as monsters
+        place_objects(room, world, char_loader, items_loader, level);
+    }
+    // create stairs at the center of the last room
+    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+    spawn_stairs(world, last_room_x, last_room_y, Stairs::Down);
+    // and the ones back up where the player comes in
+    if level > 1 {
+        let &Symbol { x, y, .. } = world.player_sym();
+        spawn_stairs(world, x, y, Stairs::Up);
+    }
+    // whatever the generator and the vaults did, everything must be reachable
+    let (start, tiles) = reachability_check(world);
+    mapgen::join_unreachable(&mut world.map, start, &tiles, &mut world.rng);
+    place_doors(world, &rooms);
+    place_supports(world);
+    place_traps(world, &rooms, traps_loader, level);
+}
+
+const SUPPORT_RADIUS: i32 = 2;
+const SUPPORT_DAMAGE: i32 = 20;
+
+/// Doors in some of the doorways on the borders of the rooms.
+fn place_doors(world: &mut game::World, rooms: &[Rect]) {
+    for room in rooms {
+        let border = (room.x1..=room.x2)
+            .flat_map(|x| vec![(x, room.y1), (x, room.y2)])
+            .chain((room.y1..=room.y2).flat_map(|y| vec![(room.x1, y), (room.x2, y)]));
+        for (x, y) in border {
+            let is_free = world.entities_at(x, y).is_empty();
+            if is_free
+                && mapgen::is_chokepoint(&world.map, (x, y))
+                && (world.rng.gen_range(0, 100) < cfg::settings().dungeon.door_chance)
+            {
+                let door = Feature::Door {
+                    open: false,
+                    group: None,
+                };
+                spawn_feature(world, x, y, door);
+            }
+        }
+    }
+}
+
+/// Mine supports in the shafts, where knocking them out doesn't cut the player
+/// off the stairs and the items.
+fn place_supports(world: &mut game::World) {
+    let shafts: Vec<_> = (0..world.map.len())
+        .map(|i| {
+            let width = cfg::settings().map.width;
+            ((i as i32) % width, (i as i32) / width)
+        })
+        .filter(|&pos| mapgen::is_chokepoint(&world.map, pos))
+        .collect();
+    if shafts.is_empty() {
+        return;
+    }
+    for _ in 0..cfg::settings().dungeon.max_supports {
+        let (x, y) = shafts[world.rng.gen_range(0, shafts.len())];
+        if !world.entities_at(x, y).is_empty() {
+            continue;
+        }
+        let index_in_map = (y * cfg::settings().map.width + x) as usize;
+        let (start, tiles) = reachability_check(world);
+        world.map[index_in_map].block = true;
+        let cuts_off = !mapgen::unreachable(&world.map, start, &tiles).is_empty();
+        world.map[index_in_map].block = false;
+        if !cuts_off {
+            let support = Feature::Support {
+                radius: SUPPORT_RADIUS,
+                damage: SUPPORT_DAMAGE,
+            };
+            spawn_feature(world, x, y, support);
+        }
+    }
+}
+
+/// Hidden traps on the floor of the rooms.
+fn place_traps(
     world: &mut game::World,
-    char_loader: &asset::CharactersLoader,
-    items_loader: &asset::ItemsLoader,
+    rooms: &[Rect],
+    traps_loader: &asset::TrapsLoader,
     level: u32,
 ) {
-    fill_walls(world);
-    let mut rooms = vec![];
-    if level == 1 {
-        place_hints(world, &mut rooms);
+    let num_traps = world.rng.gen_range(0, cfg::settings().traps.max_traps + 1);
+    for _ in 0..num_traps {
+        let room = rooms[world.rng.gen_range(0, rooms.len())];
+        let x = world.rng.gen_range(room.x1 + 1, room.x2);
+        let y = world.rng.gen_range(room.y1 + 1, room.y2);
+        // not on the stairs, the player or anything else
+        if !is_blocked(x, y, world) && world.entities_at(x, y).is_empty() {
+            place_trap(world, traps_loader, level, x, y);
+        }
+    }
+}
+
+/// A random hidden trap of the level, if there are any.
+fn place_trap(
+    world: &mut game::World,
+    traps_loader: &asset::TrapsLoader,
+    level: u32,
+    x: i32,
+    y: i32,
+) {
+    let (trap_ids, trap_chances) = traps_loader.weighted_table(level);
+    if let Ok(trap_choice) = WeightedIndex::new(trap_chances) {
+        let mut trap = traps_loader.get_clone(trap_ids[trap_choice.sample(&mut world.rng)]);
+        trap.symbol.x = x;
+        trap.symbol.y = y;
+        game::new_entity()
+            .add(trap.symbol)
+            .add(MapObject {
+                hidden: true,
+                always_visible: false,
+                ..trap.map_object
+            })
+            .add(Feature::Trap)
+            .add(trap.trap)
+            .create(world);
     }
-    for _ in rooms.len()..cfg::MAX_ROOMS {
-        // random width and height:
-        let w = rand::thread_rng().gen_range(cfg::ROOM_MIN_SIZE, cfg::ROOM_MAX_SIZE + 1);
-        let h = rand::thread_rng().gen_range(cfg::ROOM_MIN_SIZE, cfg::ROOM_MAX_SIZE + 1);
-        // random position without going out of the boundaries of the map
-        let x = rand::thread_rng().gen_range(0, cfg::MAP_WIDTH - w);
-        let y = rand::thread_rng().gen_range(0, cfg::MAP_HEIGHT - h);
-        let new_room = Rect::new(x, y, w, h);
-        // run through the other rooms and see if they intersect with this one
-        let failed = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
-        if !failed {
-            // this means there are no intersections, so this room is valid
-            // "paint" it to the map's tiles
-            create_room(new_room, &mut world.map);
-            // add some content to this room, such as monsters
-            place_objects(new_room, world, char_loader, items_loader, level);
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                let player_symbol = world.get_character_mut(world.player.id).unwrap().0;
-                player_symbol.x = new_x;
-                player_symbol.y = new_y;
-            } else {
-                // all rooms after the first: connect it to the previous room with a tunnel
-                // center coordinates of the previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-                // toss a coin (random bool value -- either true or false)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut world.map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut world.map);
+}
+
+fn spawn_feature(world: &mut game::World, x: i32, y: i32, feature: Feature) {
+    let name = match feature {
+        Feature::Door { group: None, .. } => "door",
+        Feature::Door { .. } => "gate",
+        Feature::Lever { .. } => "lever",
+        Feature::Support { .. } => "mine support",
+        Feature::Trap => "trap",
+    };
+    let glyph = engine::feature_glyph(feature);
+    let color = cfg::settings().colors.light_wall;
+    let map_object = MapObject {
+        name: String::from(name),
+        block: false,
+        always_visible: true,
+        hidden: false,
+    };
+    let id = game::new_entity()
+        .add(Symbol { x, y, glyph, color })
+        .add(map_object)
+        .add(feature)
+        .create(world);
+    if let Feature::Door { open, .. } = feature {
+        // nobody is in the new door, so it closes
+        let _ = engine::set_door_open(id, open, world);
+    }
+}
+
+/// The start of the player and the tiles of the stairs and the items on the
+/// map, that must be reachable from it.
+pub fn reachability_check(world: &game::World) -> ((i32, i32), Vec<(i32, i32)>) {
+    let &Symbol { x, y, .. } = world.player_sym();
+    let stairs = world
+        .query::<(&Symbol, &Stairs)>()
+        .map(|(_, (symbol, _))| (symbol.x, symbol.y));
+    let items = world
+        .query::<(&Symbol, &MapObject, &Item)>()
+        .filter(|(_, (_, map_obj, _))| !map_obj.hidden)
+        .map(|(_, (symbol, ..))| (symbol.x, symbol.y));
+    ((x, y), stairs.chain(items).collect())
+}
+
+/// A random place of the size in the solid rock, so that nothing dug is cut.
+fn find_solid_rock(world: &mut game::World, w: i32, h: i32) -> Option<(i32, i32)> {
+    let map_size = &cfg::settings().map;
+    if (w > map_size.width - 2) || (h > map_size.height - 2) {
+        return None;
+    }
+    let (map, rng) = (&world.map, &mut world.rng);
+    (0..100)
+        .map(|_| {
+            let x = rng.gen_range(1, map_size.width - w);
+            let y = rng.gen_range(1, map_size.height - h);
+            (x, y)
+        })
+        .find(|&(x, y)| {
+            // with the walls around
+            ((y - 1)..=(y + h)).all(|y| {
+                ((x - 1)..=(x + w)).all(|x| {
+                    let inside = (x < map_size.width) && (y < map_size.height);
+                    !inside || map[(y * map_size.width + x) as usize].block
+                })
+            })
+        })
+}
+
+/// Stamps a random vault of the level onto the map, with its characters and
+/// items, and digs a tunnel to it.
+fn place_vault(world: &mut game::World, loaders: Loaders, level: u32) {
+    let (char_loader, items_loader, traps_loader, vaults_loader) = loaders;
+    let (vault_ids, vault_chances) = vaults_loader.weighted_table(level);
+    let vault_choice = match WeightedIndex::new(vault_chances) {
+        Ok(vault_choice) => vault_choice,
+        // no vaults on this level
+        Err(_) => return,
+    };
+    let vault = vaults_loader.get_clone(vault_ids[vault_choice.sample(&mut world.rng)]);
+    let (w, h) = (
+        vault.layout[0].chars().count() as i32,
+        vault.layout.len() as i32,
+    );
+    let (x, y) = match find_solid_rock(world, w, h) {
+        Some(pos) => pos,
+        None => return,
+    };
+    // the gates and the levers of the vault are in the group of its position
+    let group = (y * cfg::settings().map.width + x) as u32;
+    for (dy, row) in vault.layout.iter().enumerate() {
+        for (dx, glyph) in row.chars().enumerate() {
+            let (x, y) = (x + dx as i32, y + dy as i32);
+            if (glyph != '#') && (glyph != ' ') {
+                mapgen::dig(&mut world.map, (x, y));
+            }
+            let feature = match glyph {
+                '+' => Some(Feature::Door {
+                    open: false,
+                    group: None,
+                }),
+                '=' => Some(Feature::Door {
+                    open: false,
+                    group: Some(group),
+                }),
+                '/' => Some(Feature::Lever {
+                    pulled: false,
+                    group,
+                }),
+                _ => None,
+            };
+            if glyph == '^' {
+                place_trap(world, traps_loader, level, x, y);
+            } else if let Some(feature) = feature {
+                spawn_feature(world, x, y, feature);
+            } else if let Some(id) = vault.legend.get(&glyph) {
+                if char_loader.contains(id) {
+                    spawn_monster(world, char_loader, id, x, y);
                 } else {
-                    // first move vertically, then horizontally
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut world.map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut world.map);
+                    spawn_item(world, items_loader, id, x, y);
                 }
             }
-            // finally, append the new room to the list
-            rooms.push(new_room);
         }
     }
-    // create stairs at the center of the last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    spawn_stairs(world, last_room_x, last_room_y);
+    mapgen::connect_to_floor(
+        Rect::new(x, y, w - 1, h - 1),
+        &mut world.map,
+        &mut world.rng,
+    );
 }
 
 fn fill_walls(world: &mut game::World) {
-    for _ in 0..cfg::MAP_WIDTH * cfg::MAP_HEIGHT {
+    for _ in 0..cfg::settings().map.width * cfg::settings().map.height {
         game::new_entity()
-            .add_map_cell(MapCell {
+            .add(MapCell {
                 block: true,
                 explored: false,
                 block_sight: true,
@@ -157,11 +379,16 @@ fn fill_walls(world: &mut game::World) {
     }
 }
 
-fn place_hints(world: &mut game::World, rooms: &mut Vec<Rect>) {
-    let x = rand::thread_rng().gen_range(0, cfg::MAP_WIDTH - 6);
-    let y = rand::thread_rng().gen_range(0, cfg::MAP_HEIGHT - 6);
+/// The room with the hints on the moves, where the player starts.
+fn place_hints(world: &mut game::World) {
+    let (x, y) = find_solid_rock(world, 6, 6).unwrap_or_else(|| {
+        let x = world.rng.gen_range(0, cfg::settings().map.width - 6);
+        let y = world.rng.gen_range(0, cfg::settings().map.height - 6);
+        (x, y)
+    });
     let new_room = Rect::new(x, y, 6, 6);
-    create_room(new_room, &mut world.map);
+    mapgen::create_room(new_room, &mut world.map);
+    mapgen::connect_to_floor(new_room, &mut world.map, &mut world.rng);
     let map_object = MapObject {
         name: String::new(),
         block: false,
@@ -169,7 +396,7 @@ fn place_hints(world: &mut game::World, rooms: &mut Vec<Rect>) {
         hidden: false,
     };
     let name = "move hint";
-    let color = cfg::COLOR_LIGHT_GROUND;
+    let color = cfg::settings().colors.light_ground;
     let hints = vec![
         (x + 2, y + 2, '\u{14}'),
         (x + 2, y + 4, '\u{15}'),
@@ -182,27 +409,14 @@ fn place_hints(world: &mut game::World, rooms: &mut Vec<Rect>) {
     ];
     hints.iter().for_each(|&(x, y, glyph)| {
         game::new_entity()
-            .add_symbol(Symbol { x, y, glyph, color })
-            .add_map_object(MapObject {
+            .add(Symbol { x, y, glyph, color })
+            .add(MapObject {
                 name: name.to_string(),
                 ..map_object
             })
             .create(world);
     });
-    let player_symbol = world.get_character_mut(world.player.id).unwrap().0;
-    player_symbol.x = x + 3;
-    player_symbol.y = y + 3;
-    rooms.push(new_room);
-}
-
-fn create_room(room: Rect, map: &mut Vec<MapCell>) {
-    for x in (room.x1 + 1)..room.x2 {
-        for y in (room.y1 + 1)..room.y2 {
-            let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
-            map[index_in_map].block = false;
-            map[index_in_map].block_sight = false;
-        }
-    }
+    world.set_position(world.player.id, x + 3, y + 3);
 }
 
 struct Transition {
@@ -217,7 +431,6 @@ fn place_objects(
     items_loader: &asset::ItemsLoader,
     level: u32,
 ) {
-    let mut rng = rand::thread_rng();
     // maxumum number of monsters per room
     let max_monsters = from_dungeon_level(
         &[
@@ -228,26 +441,18 @@ fn place_objects(
         level,
     );
     // choose random number of monsters
-    let num_monsters = rng.gen_range(0, max_monsters + 1);
+    let num_monsters = world.rng.gen_range(0, max_monsters + 1);
     // monster random table
     let (monster_ids, monster_chances) = char_loader.weighted_table(level);
     let monster_choice = WeightedIndex::new(monster_chances).unwrap();
     for _ in 0..num_monsters {
         // choose random spot for this monster
-        let x = rng.gen_range(room.x1 + 1, room.x2);
-        let y = rng.gen_range(room.y1 + 1, room.y2);
+        let x = world.rng.gen_range(room.x1 + 1, room.x2);
+        let y = world.rng.gen_range(room.y1 + 1, room.y2);
         // only place it if the tile is not blocked
         if !is_blocked(x, y, world) {
-            let mut monster = char_loader.get_clone(monster_ids[monster_choice.sample(&mut rng)]);
-            monster.character.alive = true;
-            monster.symbol.x = x;
-            monster.symbol.y = y;
-            game::new_entity()
-                .add_symbol(monster.symbol)
-                .add_map_object(monster.map_object)
-                .add_character(monster.character)
-                .add_ai(AiOption { option: monster.ai })
-                .create(world);
+            let monster_id = monster_ids[monster_choice.sample(&mut world.rng)];
+            spawn_monster(world, char_loader, monster_id, x, y);
         }
     }
     // maximum number of items per room
@@ -259,57 +464,80 @@ fn place_objects(
         level,
     );
     // choose random number of items
-    let num_items = rng.gen_range(0, max_items + 1);
+    let num_items = world.rng.gen_range(0, max_items + 1);
     let (item_ids, item_chances) = items_loader.weighted_table(level);
     let item_choice = WeightedIndex::new(item_chances).unwrap();
     for _ in 0..num_items {
         // choose random spot for this item
-        let x = rng.gen_range(room.x1 + 1, room.x2);
-        let y = rng.gen_range(room.y1 + 1, room.y2);
+        let x = world.rng.gen_range(room.x1 + 1, room.x2);
+        let y = world.rng.gen_range(room.y1 + 1, room.y2);
         // only place it if the tile is not blocked
         if !is_blocked(x, y, world) {
-            let mut item = items_loader.get_clone(item_ids[item_choice.sample(&mut rng)]);
-            item.symbol.x = x;
-            item.symbol.y = y;
-            game::new_entity()
-                .add_symbol(item.symbol)
-                .add_map_object(item.map_object)
-                .add_item(item.item)
-                .add_equipment(item.equipment)
-                .add_ammo(item.ammo)
-                .create(world);
+            let item_id = item_ids[item_choice.sample(&mut world.rng)];
+            spawn_item(world, items_loader, item_id, x, y);
         }
     }
 }
 
-fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Vec<MapCell>) {
-    for x in cmp::min(x1, x2)..=cmp::max(x1, x2) {
-        let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
-        map[index_in_map].block = false;
-        map[index_in_map].block_sight = false;
-    }
+fn spawn_monster(
+    world: &mut game::World,
+    char_loader: &asset::CharactersLoader,
+    id: &str,
+    x: i32,
+    y: i32,
+) {
+    let mut monster = char_loader.get_clone(id);
+    monster.character.alive = true;
+    monster.symbol.x = x;
+    monster.symbol.y = y;
+    game::new_entity()
+        .add(monster.symbol)
+        .add(monster.map_object)
+        .add(monster.character)
+        .add(AiOption {
+            option: monster.ai,
+            ..Default::default()
+        })
+        .add(StatusEffects(monster.status_effects))
+        .create(world);
 }
 
-fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Vec<MapCell>) {
-    for y in cmp::min(y1, y2)..=cmp::max(y1, y2) {
-        let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
-        map[index_in_map].block = false;
-        map[index_in_map].block_sight = false;
-    }
+fn spawn_item(
+    world: &mut game::World,
+    items_loader: &asset::ItemsLoader,
+    id: &str,
+    x: i32,
+    y: i32,
+) {
+    let mut item = items_loader.get_clone(id);
+    item.symbol.x = x;
+    item.symbol.y = y;
+    game::new_entity()
+        .add(item.symbol)
+        .add(item.map_object)
+        .add(item.item)
+        .add_option(item.equipment)
+        .add_option(item.ammo)
+        .add_option(item.stack)
+        .create(world);
 }
 
-fn spawn_stairs(world: &mut game::World, x: i32, y: i32) {
-    let glyph = '\u{A4}';
-    let color = cfg::COLOR_LIGHT_WALL;
+fn spawn_stairs(world: &mut game::World, x: i32, y: i32, stairs: Stairs) {
+    let (glyph, name) = match stairs {
+        Stairs::Down => ('\u{A4}', "stairs"),
+        Stairs::Up => ('<', "stairs up"),
+    };
+    let color = cfg::settings().colors.light_wall;
     let map_object = MapObject {
-        name: String::from("stairs"),
+        name: String::from(name),
         block: false,
         always_visible: true,
         hidden: false,
     };
     game::new_entity()
-        .add_symbol(Symbol { x, y, glyph, color })
-        .add_map_object(map_object)
+        .add(Symbol { x, y, glyph, color })
+        .add(map_object)
+        .add(stairs)
         .create(world);
 }
 
@@ -324,19 +552,17 @@ fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
 }
 
 fn is_blocked(x: i32, y: i32, world: &game::World) -> bool {
-    let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
+    let index_in_map = (y * cfg::settings().map.width + x) as usize;
     // first test the map tile
     if world.map[index_in_map].block {
         return true;
     }
     // now check for any blocking objects
-    world.entity_indexes.values().any(|indexes| {
-        if let (Some(sy), Some(mo)) = (indexes.symbol, indexes.map_object) {
-            return world.map_objects[mo].block
-                && (world.symbols[sy].x, world.symbols[sy].y) == (x, y);
-        };
-        false
-    })
+    world
+        .entities_at(x, y)
+        .iter()
+        .filter_map(|&id| world.get::<&MapObject>(id))
+        .any(|map_obj| map_obj.block)
 }
 
 fn spawn_player(
@@ -346,25 +572,30 @@ fn spawn_player(
 ) {
     let mut player = char_loader.get_clone("player");
     player.character.alive = true;
-    player.symbol.x = cfg::SCREEN_WIDTH / 2;
-    player.symbol.y = cfg::SCREEN_HEIGHT / 2;
+    // the player makes the first move
+    player.character.energy = cfg::settings().turns.energy_to_act;
+    player.symbol.x = cfg::settings().screen.width / 2;
+    player.symbol.y = cfg::settings().screen.height / 2;
     world.player.id = game::new_entity()
-        .add_symbol(player.symbol)
-        .add_map_object(player.map_object)
-        .add_character(player.character)
-        .add_ai(AiOption { option: player.ai })
+        .add(player.symbol)
+        .add(player.map_object)
+        .add(player.character)
+        .add(AiOption {
+            option: player.ai,
+            ..Default::default()
+        })
         .create(world);
     // initial equipment: Pipe
     let mut pipe = items_loader.get_clone("pipe");
     pipe.item.owner = world.player.id;
     game::new_entity()
-        .add_symbol(pipe.symbol)
-        .add_map_object(pipe.map_object)
-        .add_item(pipe.item)
-        .add_equipment(pipe.equipment)
+        .add(pipe.symbol)
+        .add(pipe.map_object)
+        .add(pipe.item)
+        .add_option(pipe.equipment)
         .create(world);
     world.add_log(
-        cfg::COLOR_ORANGE,
+        cfg::settings().colors.orange,
         String::from(
             "Welcome stranger! Prepare to perish in the Abandoned Mines. Press F1 for help.\n",
         ),
@@ -374,8 +605,58 @@ fn spawn_player(
     };
 }
 
-fn save_game(world: &game::World) {
-    let save_data = serde_json::to_string(world).unwrap();
-    let mut file = fs::File::create("savegame").unwrap();
-    file.write_all(save_data.as_bytes()).unwrap();
+#[cfg(test)]
+mod make_map_tests {
+    use super::*;
+
+    fn generate(seed: u64, level: u32) -> game::World {
+        let char_loader = asset::CharactersLoader::load().unwrap();
+        let items_loader = asset::ItemsLoader::load().unwrap();
+        let traps_loader = asset::TrapsLoader::load().unwrap();
+        let vaults_loader = asset::VaultsLoader::load(&char_loader, &items_loader).unwrap();
+        let mut world = game::World {
+            seed,
+            ..Default::default()
+        };
+        spawn_player(&mut world, &char_loader, &items_loader);
+        make_map(
+            &mut world,
+            (&char_loader, &items_loader, &traps_loader, &vaults_loader),
+            level,
+        );
+        world
+    }
+
+    #[test]
+    fn same_seed_gives_same_level() {
+        for level in 1..5 {
+            let first = serde_json::to_string(&generate(42, level)).unwrap();
+            let second = serde_json::to_string(&generate(42, level)).unwrap();
+            assert_eq!(first, second, "level {} differs for the same seed", level);
+        }
+    }
+
+    #[test]
+    fn different_levels_give_different_maps() {
+        let blocks = |world: &game::World| world.map.iter().map(|c| c.block).collect::<Vec<_>>();
+        assert_ne!(blocks(&generate(42, 2)), blocks(&generate(42, 3)));
+    }
+
+    #[test]
+    fn stairs_and_items_are_reachable_for_many_seeds() {
+        for seed in 0..40 {
+            for level in 1..9 {
+                let world = generate(seed, level);
+                let (start, tiles) = reachability_check(&world);
+                let cut_off = mapgen::unreachable(&world.map, start, &tiles);
+                assert!(
+                    cut_off.is_empty(),
+                    "{:?} can't be reached on level {} with seed {}",
+                    cut_off,
+                    level,
+                    seed
+                );
+            }
+        }
+    }
 }
@@ -9,19 +9,25 @@ pub fn update(world: &mut game::World, fov: &mut map::Map) {
         (true, false) => *fov = map::Map::new(1, 1),
         (false, true) => {
             create_fov(world, fov);
-            compute_fov(world, fov);
+            compute_fov(world, fov, true);
         }
-        (false, false) => compute_fov(world, fov),
+        // e.g. a door was opened or the player is back on a visited level
+        (false, false) if world.map_changed => {
+            create_fov(world, fov);
+            compute_fov(world, fov, true);
+        }
+        (false, false) => compute_fov(world, fov, false),
         _ => (),
     }
 }
 
 /// create the FOV map, according to the generated map
 fn create_fov(world: &mut game::World, fov: &mut map::Map) {
-    *fov = map::Map::new(cfg::MAP_WIDTH, cfg::MAP_HEIGHT);
-    for y in 0..cfg::MAP_HEIGHT {
-        for x in 0..cfg::MAP_WIDTH {
-            let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
+    *fov = map::Map::new(cfg::settings().map.width, cfg::settings().map.height);
+    world.map_changed = false;
+    for y in 0..cfg::settings().map.height {
+        for x in 0..cfg::settings().map.width {
+            let index_in_map = (y * cfg::settings().map.width + x) as usize;
             fov.set(
                 x,
                 y,
@@ -32,20 +38,20 @@ fn create_fov(world: &mut game::World, fov: &mut map::Map) {
     }
 }
 
-fn compute_fov(world: &mut game::World, fov: &mut map::Map) {
+fn compute_fov(world: &mut game::World, fov: &mut map::Map, force: bool) {
     let player_symbol = world.player_sym();
     let (player_x, player_y) = (player_symbol.x, player_symbol.y);
-    if world.player.previous_player_position != (player_x, player_y) {
+    if force || (world.player.previous_player_position != (player_x, player_y)) {
         fov.compute_fov(
             player_x,
             player_y,
-            cfg::TORCH_RADIUS,
-            cfg::FOV_LIGHT_WALLS,
-            cfg::FOV_ALGO,
+            cfg::settings().fov.torch_radius,
+            cfg::settings().fov.light_walls,
+            cfg::settings().fov.algorithm,
         );
-        for y in 0..cfg::MAP_HEIGHT {
-            for x in 0..cfg::MAP_WIDTH {
-                let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
+        for y in 0..cfg::settings().map.height {
+            for x in 0..cfg::settings().map.width {
+                let index_in_map = (y * cfg::settings().map.width + x) as usize;
                 let in_fov = fov.is_in_fov(x, y);
                 world.map[index_in_map].in_fov = in_fov;
                 if in_fov {
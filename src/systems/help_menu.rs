@@ -12,10 +12,12 @@ pub fn update(world: &mut game::World) {
                \n\
                Save And Exit........Esc\n\
                Look.................Mouse\n\
-               Pick Up, Downstairs..Enter\n\
+               Pick Up, Stairs,\n\
+               Doors, Levers........Enter\n\
                Inventory............I\n\
                Character Info.......C\n\
                Drop Item............D\n\
+               Search For Traps.....S\n\
                Move Character.......Arrows, Home,\n\
                \x20                    End, Page Up,\n\
                \x20                    Page Down,\n\
@@ -1,18 +1,29 @@
-use crate::cmtp::{PlayerAction, PlayerState};
+use crate::cmtp::{DialogKind, PlayerAction, PlayerState};
 use crate::engine::game;
 use tcod::input;
 
 pub fn update(world: &mut game::World) {
     use input::KeyCode::*;
     world.player.action = match world.player.state {
-        PlayerState::InDialog => match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
-            Some((_, input::Event::Key(key))) => match (key.code, key.printable) {
-                (Escape, _) => PlayerAction::Cancel,
-                (Text, printable) => printable_to_action(printable),
+        PlayerState::InDialog => {
+            let is_typing = world
+                .dialogs
+                .last()
+                .filter(|dialog| matches!(dialog.kind, DialogKind::SlotName(_)))
+                .is_some();
+            match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+                Some((_, input::Event::Key(key))) => match (key.code, key.printable) {
+                    (Escape, _) => PlayerAction::Cancel,
+                    (Enter, _) | (NumPadEnter, _) if is_typing => PlayerAction::Confirm,
+                    (Backspace, _) if is_typing => PlayerAction::EraseChar,
+                    (Spacebar, _) if is_typing => PlayerAction::TypeChar(' '),
+                    (Text, printable) if is_typing => PlayerAction::TypeChar(printable),
+                    (Text, printable) => printable_to_action(printable),
+                    _ => PlayerAction::None,
+                },
                 _ => PlayerAction::None,
-            },
-            _ => PlayerAction::None,
-        },
+            }
+        }
 
         PlayerState::MakingTurn | PlayerState::TargetingTile(_) => {
             match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
@@ -32,6 +43,7 @@ pub fn update(world: &mut game::World) {
                     (Text, 'i') => PlayerAction::OpenInventory,
                     (Text, 'c') => PlayerAction::OpenCharInfo,
                     (Text, 'd') => PlayerAction::DropItem,
+                    (Text, 's') => PlayerAction::Search,
                     _ => PlayerAction::None,
                 },
                 Some((_, input::Event::Mouse(m))) => {
@@ -1,7 +1,10 @@
 use crate::cfg;
-use crate::cmtp::{Ai, AmmoKind, DialogBox, DialogKind, ItemKind, PlayerAction, PlayerState, Slot};
+use crate::cmtp::{
+    AmmoKind, DialogBox, DialogKind, Effect, Equipment, ItemKind, PlayerAction, PlayerState, Slot,
+    Stack, Symbol, Targeting,
+};
 use crate::engine;
-use crate::engine::game;
+use crate::engine::{game, los};
 use std::f32;
 
 fn is_opening_inventory(world: &game::World) -> bool {
@@ -19,6 +22,13 @@ fn inventory_kind(dialog_box: &DialogBox) -> Option<DialogKind> {
     }
 }
 
+fn stack_to_drop(world: &game::World) -> Option<u32> {
+    match world.dialogs.last().map(|dialog_box| dialog_box.kind) {
+        Some(DialogKind::DropCount(inventory_id)) => Some(inventory_id),
+        _ => None,
+    }
+}
+
 fn used_targetable_item(world: &game::World) -> Option<u32> {
     if let PlayerState::TargetingTile(inventory_id) = world.player.state {
         if let PlayerAction::ClickAt(..) | PlayerAction::Cancel = world.player.action {
@@ -34,6 +44,7 @@ fn used_targetable_item(world: &game::World) -> Option<u32> {
 pub fn update(world: &mut game::World) {
     let is_opening_inventory = is_opening_inventory(world);
     let opened_menu = world.dialogs.last().and_then(inventory_kind);
+    let stack_to_drop = stack_to_drop(world);
     if is_opening_inventory {
         let (dialog_kind, menu_title) = match world.player.action {
             PlayerAction::OpenInventory => (
@@ -65,16 +76,35 @@ pub fn update(world: &mut game::World) {
             _ => None,
         };
         if let Some(inventory_id) = inventory_id {
+            // closed first, dropping a stack asks how many in a new dialog
+            world.dialogs.pop();
             match dialog_kind {
                 DialogKind::Inventory => use_item(inventory_id, world, false),
                 DialogKind::DropItem => drop_item(inventory_id, world),
                 _ => unreachable!(),
             }
-            world.dialogs.pop();
             if world.dialogs.is_empty() && (world.player.state == PlayerState::InDialog) {
                 world.player.state = PlayerState::MakingTurn;
             };
         }
+    } else if let Some(inventory_id) = stack_to_drop {
+        let count = match world.player.action {
+            PlayerAction::SelectMenuItem(i) => {
+                let stack_count = world.get::<&Stack>(inventory_id).unwrap().count;
+                drop_counts(stack_count).get(i).cloned()
+            }
+            PlayerAction::Cancel => Some(0),
+            _ => None,
+        };
+        if let Some(count) = count {
+            world.dialogs.pop();
+            if count > 0 {
+                drop_some(inventory_id, count, world);
+            }
+            if world.dialogs.is_empty() {
+                world.player.state = PlayerState::MakingTurn;
+            };
+        }
     } else if let Some(inventory_id) = used_targetable_item(world) {
         use_item(inventory_id, world, true);
         world.player.state = PlayerState::MakingTurn;
@@ -86,8 +116,10 @@ fn add_inventory_menu(world: &mut game::World, kind: DialogKind, header: String)
     let mut options: Vec<_> = world
         .item_iter()
         .filter(|(.., item, _, _)| item.owner == world.player.id)
-        .map(|(.., map_obj, _, eqp, ammo)| {
-            let count_postfix = ammo.map_or(String::new(), |ammo| format!(" x{}", ammo.count));
+        .map(|(id, _, map_obj, _, eqp, _)| {
+            let count_postfix = world
+                .get::<&Stack>(id)
+                .map_or(String::new(), |stack| format!(" x{}", stack.count));
             let equipped_postfix = eqp
                 .filter(|eqp| eqp.equipped)
                 .map_or(String::new(), |eqp| format!(" (on {})", eqp.slot));
@@ -97,7 +129,7 @@ fn add_inventory_menu(world: &mut game::World, kind: DialogKind, header: String)
     if options.is_empty() {
         options.push(String::from("Inventory is empty."));
     }
-    world.add_dialog_box(kind, header, options, cfg::INVENTORY_WIDTH);
+    world.add_dialog_box(kind, header, options, cfg::settings().gui.inventory_width);
 }
 
 enum UseResult {
@@ -112,10 +144,7 @@ fn use_item(inventory_id: u32, world: &mut game::World, by_targeting: bool) {
     if let Some((.., item, _, _)) = world.get_item(inventory_id) {
         use ItemKind::*;
         let on_use = match item.kind {
-            Medkit => use_medkit,
-            FutureUnknownDamager => shoot_slingshot,
-            Brick => throw_brick,
-            BlastingCartridge => throw_blasting_cartridge,
+            Consumable => use_consumable,
             Melee => toggle_equipment,
             Clothing => toggle_equipment,
             Ranged(_) => toggle_equipment,
@@ -124,11 +153,13 @@ fn use_item(inventory_id: u32, world: &mut game::World, by_targeting: bool) {
         match on_use(inventory_id, world, by_targeting) {
             UseResult::UsedUp => {
                 // destroy after use, unless it was cancelled for some reason
-                world.get_item_mut(inventory_id).unwrap().2.owner = 0;
-                world.entity_indexes.remove(&inventory_id);
+                let used_id = take_from_stack(inventory_id, 1, world);
+                world.destroy_entity(used_id);
             }
             UseResult::UsedAndKept => (),
-            UseResult::Cancelled => world.add_log(cfg::COLOR_LIGHTEST_GREY, "Cancelled"),
+            UseResult::Cancelled => {
+                world.add_log(cfg::settings().colors.lightest_grey, "Cancelled")
+            }
             UseResult::NeedTargeting => {
                 world.player.state = PlayerState::TargetingTile(inventory_id)
             }
@@ -136,62 +167,140 @@ fn use_item(inventory_id: u32, world: &mut game::World, by_targeting: bool) {
     } else {
         let name = world.get_item_mut(inventory_id).unwrap().1.name.clone();
         world.add_log(
-            cfg::COLOR_LIGHTEST_GREY,
+            cfg::settings().colors.lightest_grey,
             format!("The {} cannot be used.", name),
         );
     }
 }
 
-fn use_medkit(_inventory_id: u32, world: &mut game::World, _by_targeting: bool) -> UseResult {
-    // heal the player
-    if world.player_char().hp == world.max_hp(world.player.id) {
-        world.add_log(cfg::COLOR_ORANGE, "You are already at full health.");
-        return UseResult::Cancelled;
-    }
-    world.add_log(cfg::COLOR_GREEN, "Your wounds start to feel better!");
-    heal(world.player.id, cfg::HEAL_AMOUNT, world);
-    UseResult::UsedUp
-}
-
-/// heal by the given amount, without going over the maximum
-fn heal(id: u32, amount: i32, world: &mut game::World) {
-    let max_hp = world.max_hp(id);
-    let character = world.get_character_mut(id).unwrap().2;
-    character.hp += amount;
-    if character.hp > max_hp {
-        character.hp = max_hp;
-    }
-}
-
-fn shoot_slingshot(_inventory_id: u32, world: &mut game::World, _by_targeting: bool) -> UseResult {
-    // find closest enemy (inside a maximum range and damage it)
-    let monster_id = closest_monster(cfg::SLINGSHOT_RANGE, world);
-    if let Some(monster_id) = monster_id {
-        let monster = world.get_character_mut(monster_id).unwrap().2;
-        if let Some(xp) = engine::take_damage(monster, cfg::SLINGSHOT_DAMAGE) {
-            world.player_char_mut().xp += xp;
+/// Uses the item as its `on_use` from the items file says: finds the target,
+/// throws the item if needed, and applies the effects to the ones it reaches.
+fn use_consumable(inventory_id: u32, world: &mut game::World, by_targeting: bool) -> UseResult {
+    let (_, map_obj, item, ..) = world.get_item(inventory_id).unwrap();
+    let name = map_obj.name.clone();
+    let on_use = match item.on_use.clone() {
+        Some(on_use) => on_use,
+        None => {
+            world.add_log(
+                cfg::settings().colors.lightest_grey,
+                format!("The {} cannot be used.", name),
+            );
+            return UseResult::Cancelled;
+        }
+    };
+    let max_range = on_use.range.unwrap_or(f32::INFINITY);
+    let player_symbol = world.player_sym();
+    let player_pos = (player_symbol.x, player_symbol.y);
+    let landed_at = |trace: &los::Trace| trace.path.last().cloned().unwrap_or(player_pos);
+    let (center, hit) = match on_use.target {
+        Targeting::Itself => {
+            let only_heals = on_use
+                .effects
+                .iter()
+                .all(|effect| matches!(effect, Effect::Heal(_)));
+            if only_heals && (world.player_char().hp == world.max_hp(world.player.id)) {
+                world.add_log(
+                    cfg::settings().colors.orange,
+                    "You are already at full health.",
+                );
+                return UseResult::Cancelled;
+            }
+            (player_pos, Some(world.player.id))
+        }
+        Targeting::Nearest => match closest_monster(max_range, world) {
+            Some(monster_id) => {
+                let &Symbol { x, y, .. } = world.get_character(monster_id).unwrap().0;
+                // it hits the one in the way, if any
+                let trace = throw(inventory_id, world, (x, y));
+                (landed_at(&trace), trace.hit)
+            }
+            None => {
+                // no enemy found within maximum range
+                world.add_log(cfg::settings().colors.dark_sky, "No enemy is close enough.");
+                return UseResult::Cancelled;
+            }
+        },
+        Targeting::Tile | Targeting::Creature => {
+            if !by_targeting {
+                // ask the player for a target
+                world.add_log(
+                    cfg::settings().colors.dark_sky,
+                    format!(
+                        "Left-click a target to throw the {}, or right-click to cancel.",
+                        name
+                    ),
+                );
+                return UseResult::NeedTargeting;
+            }
+            let position = match world.player.action {
+                PlayerAction::ClickAt(x, y) => (x, y),
+                PlayerAction::Cancel => return UseResult::Cancelled,
+                _ => unreachable!(),
+            };
+            if !target_tile(world, max_range, position) {
+                world.add_log(
+                    cfg::settings().colors.dark_sky,
+                    "The target is too far to throw.",
+                );
+                return UseResult::Cancelled;
+            }
+            let trace = throw(inventory_id, world, position);
+            if (on_use.target == Targeting::Creature) && trace.hit.is_none() {
+                // it falls where it stopped
+                world.add_log(
+                    cfg::settings().colors.dark_sky,
+                    format!("The {} falls to the ground.", name),
+                );
+                let thrown_id = take_from_stack(inventory_id, 1, world);
+                put_on_floor(thrown_id, world, landed_at(&trace));
+                return UseResult::UsedAndKept;
+            }
+            (landed_at(&trace), trace.hit)
         }
-        let monster_name = world.get_character(monster_id).unwrap().1.name.clone();
+    };
+    if !on_use.message.is_empty() {
+        world.add_log(cfg::settings().colors.orange, on_use.message);
+    }
+    let targets: Vec<_> = if on_use.radius > 0 {
+        world
+            .entities_in_radius(center.0, center.1, on_use.radius as f32)
+            .into_iter()
+            .filter(|&id| world.get_character(id).is_some())
+            .collect()
+    } else {
+        hit.into_iter().collect()
+    };
+    if targets.is_empty() {
         world.add_log(
-            cfg::COLOR_LIGHTEST_GREY,
-            format!(
-                "A Steel Ball whizzed to a {}! The damage is {} hit points.",
-                monster_name,
-                cfg::SLINGSHOT_DAMAGE
-            ),
+            cfg::settings().colors.lightest_grey,
+            format!("The {} hits nothing.", name),
         );
-        UseResult::UsedUp
-    } else {
-        // no enemy found within maximum range
-        world.add_log(cfg::COLOR_DARK_SKY, "No enemy is close enough to shoot.");
-        UseResult::Cancelled
     }
+    for target_id in targets {
+        for &effect in &on_use.effects {
+            if world.get_character(target_id).unwrap().2.alive {
+                engine::apply_effect(target_id, effect, true, world);
+            }
+        }
+    }
+    UseResult::UsedUp
+}
+
+/// Throws the item from the player to the tile, it flies until it hits a wall
+/// or a creature.
+fn throw(inventory_id: u32, world: &mut game::World, target: (i32, i32)) -> los::Trace {
+    let player_symbol = world.player_sym();
+    let player_pos = (player_symbol.x, player_symbol.y);
+    let trace = los::trace(world, player_pos, target);
+    let &Symbol { glyph, color, .. } = world.get_item(inventory_id).unwrap().0;
+    los::animate(world, &trace.path, glyph, color);
+    trace
 }
 
 /// find closest enemy, up to a maximum range, and in the player's FOV
-fn closest_monster(max_range: i32, world: &game::World) -> Option<u32> {
+fn closest_monster(max_range: f32, world: &game::World) -> Option<u32> {
     let mut closest_enemy = None;
-    let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) maximum range
+    let mut closest_dist = max_range + 1.0; // start with (slightly more than) maximum range
     let enemies = world
         .character_iter()
         .filter(|(id, ..)| (*id != world.player.id) && world.check_fov(*id))
@@ -209,104 +318,6 @@ fn closest_monster(max_range: i32, world: &game::World) -> Option<u32> {
     closest_enemy
 }
 
-fn throw_brick(_inventory_id: u32, world: &mut game::World, by_targeting: bool) -> UseResult {
-    if !by_targeting {
-        // ask the player for a target to confuse
-        world.add_log(
-            cfg::COLOR_DARK_SKY,
-            "Left-click an enemy to throw the brick, or right-click to cancel.",
-        );
-        UseResult::NeedTargeting
-    } else {
-        let position = match world.player.action {
-            PlayerAction::ClickAt(x, y) => (x, y),
-            PlayerAction::Cancel => return UseResult::Cancelled,
-            _ => unreachable!(),
-        };
-        let monster_id = target_monster(world, cfg::BRICK_RANGE, position);
-        if let Some(monster_id) = monster_id {
-            let monster_ai = world.get_character_mut(monster_id).unwrap().3;
-            let old_ai = monster_ai.option.take().unwrap_or(Ai::Basic);
-            // replace the monster's AI with a "confused" one; after
-            // some turns it will restore the old AI
-            monster_ai.option = Some(Ai::Confused {
-                previous_ai: Box::new(old_ai),
-                num_turns: cfg::BRICK_NUM_TURNS,
-            });
-            let monster_name = world.get_character(monster_id).unwrap().1.name.clone();
-            world.add_log(
-                cfg::COLOR_LIGHTEST_GREY,
-                format!(
-                    "The eyes of {} look vacant, as he starts to stumble around!",
-                    monster_name
-                ),
-            );
-            UseResult::UsedUp
-        } else {
-            world.add_log(cfg::COLOR_DARK_SKY, "No enemy is close enough to throw.");
-            UseResult::Cancelled
-        }
-    }
-}
-
-fn throw_blasting_cartridge(
-    _inventory_id: u32,
-    world: &mut game::World,
-    by_targeting: bool,
-) -> UseResult {
-    if !by_targeting {
-        world.add_log(
-            cfg::COLOR_DARK_SKY,
-            "Left-click a target tile to throw the charge, or right-click to cancel.",
-        );
-        UseResult::NeedTargeting
-    } else {
-        let (x, y) = match world.player.action {
-            PlayerAction::ClickAt(x, y) => (x, y),
-            PlayerAction::Cancel => return UseResult::Cancelled,
-            _ => unreachable!(),
-        };
-        if !target_tile(world, f32::INFINITY, (x, y)) {
-            return UseResult::Cancelled;
-        }
-        world.add_log(
-            cfg::COLOR_ORANGE,
-            format!(
-                "The Blasting Cartridge explodes, crushing everything within {} tiles!",
-                cfg::BLASTING_RADIUS
-            ),
-        );
-        let mut xp_to_gain = 0;
-        let targets: Vec<_> = world
-            .character_iter()
-            .filter(|(_, sym, ..)| {
-                game::World::distance_to(sym.x, sym.y, x, y) <= cfg::BLASTING_RADIUS as f32
-            })
-            .map(|(id, ..)| id)
-            .collect();
-        for target_id in targets {
-            let target = world.get_character_mut(target_id).unwrap().2;
-            if let Some(xp) = engine::take_damage(target, cfg::BLASTING_DAMAGE) {
-                if target_id != world.player.id {
-                    // Don't reward the player for burning themself!
-                    xp_to_gain += xp;
-                }
-            }
-            let target_name = world.get_character(target_id).unwrap().1.name.clone();
-            world.add_log(
-                cfg::COLOR_LIGHTEST_GREY,
-                format!(
-                    "The {} gets damaged for {} hit points.",
-                    target_name,
-                    cfg::BLASTING_DAMAGE
-                ),
-            );
-        }
-        world.player_char_mut().xp += xp_to_gain;
-        UseResult::UsedUp
-    }
-}
-
 fn get_ammo_kind(kind: ItemKind) -> Option<AmmoKind> {
     match kind {
         ItemKind::Ranged(ammo_kind) => Some(ammo_kind),
@@ -331,7 +342,7 @@ fn toggle_equipment(inventory_id: u32, world: &mut game::World, _by_targeting: b
             .map(|id| dequip(id, world));
     } else if incompatible_ammo {
         world.add_log(
-            cfg::COLOR_ORANGE,
+            cfg::settings().colors.orange,
             format!("This ammo doesn't seem to fit right."),
         );
     } else {
@@ -352,52 +363,152 @@ fn dequip(id: u32, world: &mut game::World) {
             equipment.equipped = false;
             let slot = equipment.slot;
             world.add_log(
-                cfg::COLOR_DARK_SKY,
+                cfg::settings().colors.dark_sky,
                 format!("Dequipped {} from {}.", name, slot),
             );
         }
     } else {
         world.add_log(
-            cfg::COLOR_ORANGE,
+            cfg::settings().colors.orange,
             format!("Can't dequip {} because it's not an Equipment.", name),
         );
     }
 }
 
-/// returns a clicked monster inside FOV up to a range, or None
-fn target_monster(world: &game::World, max_range: f32, (x, y): (i32, i32)) -> Option<u32> {
-    if target_tile(world, max_range, (x, y)) {
-        world
-            .character_iter()
-            .find(|(id, sym, ..)| ((sym.x, sym.y) == (x, y)) && (*id != world.player.id))
-            .map(|(id, ..)| id)
-    } else {
-        None
-    }
-}
-
 /// return tue if the position of a tile is clicked in player's FOV (optionally in a
 /// range).
 fn target_tile(world: &game::World, max_range: f32, (x, y): (i32, i32)) -> bool {
     let player_symbol = world.player_sym();
     let (player_x, player_y) = (player_symbol.x, player_symbol.y);
-    let target_index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
+    let target_index_in_map = (y * cfg::settings().map.width + x) as usize;
     world.map[target_index_in_map].in_fov
         && (game::World::distance_to(player_x, player_y, x, y) <= max_range)
 }
 
 fn drop_item(inventory_id: u32, world: &mut game::World) {
-    let maybe_equipment = world.get_item(inventory_id).unwrap().3;
+    let stack_count = world
+        .get::<&Stack>(inventory_id)
+        .map_or(1, |stack| stack.count);
+    if stack_count > 1 {
+        let options = drop_counts(stack_count)
+            .into_iter()
+            .map(|count| {
+                if count == stack_count {
+                    format!("All ({})", count)
+                } else {
+                    count.to_string()
+                }
+            })
+            .collect();
+        world.add_dialog_box(
+            DialogKind::DropCount(inventory_id),
+            String::from("How many to drop?"),
+            options,
+            cfg::settings().gui.inventory_width,
+        );
+    } else {
+        drop_some(inventory_id, 1, world);
+    }
+}
+
+/// The choices of how many items of the stack to drop: one, half or all.
+fn drop_counts(stack_count: u32) -> Vec<u32> {
+    let mut counts = vec![1, stack_count / 2, stack_count];
+    counts.dedup();
+    counts
+}
+
+fn drop_some(inventory_id: u32, count: u32, world: &mut game::World) {
+    let dropped_id = take_from_stack(inventory_id, count, world);
+    let maybe_equipment = world.get_item(dropped_id).unwrap().3;
     if maybe_equipment.is_some() {
-        dequip(inventory_id, world);
+        dequip(dropped_id, world);
     }
     let player_symbol = world.player_sym();
     let (player_x, player_y) = (player_symbol.x, player_symbol.y);
-    let (symbol, map_obj, item, ..) = world.get_item_mut(inventory_id).unwrap();
+    let name = world.get_item(dropped_id).unwrap().1.name.clone();
+    put_on_floor(dropped_id, world, (player_x, player_y));
+    let msg = match world.get::<&Stack>(dropped_id) {
+        Some(stack) if stack.count > 1 => format!("You dropped {} x{}.", name, stack.count),
+        _ => format!("You dropped a {}.", name),
+    };
+    world.add_log(cfg::settings().colors.dark_sky, msg);
+}
+
+/// Splits the items off the stack as a new one, unless that's all of them.
+/// Returns the id of the items taken.
+fn take_from_stack(inventory_id: u32, count: u32, world: &mut game::World) -> u32 {
+    let stack = match world.get_mut::<&mut Stack>(inventory_id) {
+        Some(stack) if stack.count > count => stack,
+        _ => return inventory_id,
+    };
+    stack.count -= count;
+    let (symbol, map_obj, item, equipment, ammo) = world.get_item(inventory_id).unwrap();
+    let (symbol, map_obj, item) = (symbol.clone(), map_obj.clone(), item.clone());
+    // the split off items are not worn, even if the stack is
+    let equipment = equipment.cloned().map(|equipment| Equipment {
+        equipped: false,
+        ..equipment
+    });
+    let ammo = ammo.cloned();
+    game::new_entity()
+        .add(symbol)
+        .add(map_obj)
+        .add(item)
+        .add_option(equipment)
+        .add_option(ammo)
+        .add(Stack { count })
+        .create(world)
+}
+
+/// Takes the item from the inventory and puts it on the tile.
+fn put_on_floor(inventory_id: u32, world: &mut game::World, (x, y): (i32, i32)) {
+    let (_, map_obj, item, ..) = world.get_item_mut(inventory_id).unwrap();
     item.owner = 0;
     map_obj.hidden = false;
-    symbol.x = player_x;
-    symbol.y = player_y;
-    let name = map_obj.name.clone();
-    world.add_log(cfg::COLOR_DARK_SKY, format!("You dropped a {}.", name));
+    world.set_position(inventory_id, x, y);
+}
+
+#[cfg(test)]
+mod inventory_tests {
+    use super::*;
+    use crate::cmtp::{Item, MapObject};
+    use crate::systems::map_interaction;
+    use crate::test_util;
+
+    fn stack_count(world: &game::World, id: u32) -> u32 {
+        world.get::<&Stack>(id).unwrap().count
+    }
+
+    #[test]
+    fn dropped_part_of_stack_is_split_and_merged_back_on_pickup() {
+        let room = ["####", "#..#", "####"];
+        let mut world = test_util::world_with_player(&room, (1, 1), test_util::character(10));
+        let medkits_id = game::new_entity()
+            .add(Symbol::default())
+            .add(MapObject {
+                name: String::from("Medkit"),
+                hidden: true,
+                ..Default::default()
+            })
+            .add(Item {
+                kind: ItemKind::Consumable,
+                owner: world.player.id,
+                inflicts: vec![],
+                on_use: None,
+            })
+            .add(Stack { count: 5 })
+            .create(&mut world);
+        drop_some(medkits_id, 2, &mut world);
+        assert_eq!(stack_count(&world, medkits_id), 3);
+        let dropped_id = *world.entities_at(1, 1).last().unwrap();
+        assert_ne!(dropped_id, medkits_id);
+        assert_eq!(stack_count(&world, dropped_id), 2);
+        assert_eq!(world.get_item(dropped_id).unwrap().2.owner, 0);
+        world.player.state = PlayerState::MakingTurn;
+        world.player.action = PlayerAction::InteractWithMap;
+        map_interaction::update(&mut world);
+        assert!(world.get_item(dropped_id).is_none());
+        assert_eq!(stack_count(&world, medkits_id), 5);
+    }
 }
@@ -1,51 +1,312 @@
+use crate::cfg;
 use crate::cmtp::{DialogBox, DialogKind, PlayerAction, PlayerState};
-use crate::engine::game;
-use std::{error::Error, fs, io::Read as _};
+use crate::engine::{game, save};
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{env, mem};
+
+// the names of the save slots are cut to it, so the slot picker fits the screen
+const MAX_SLOT_NAME_LEN: usize = 12;
 
 fn is_main_menu(dialog_box: &&DialogBox) -> bool {
     dialog_box.kind == DialogKind::MainMenu
 }
 
+fn slots_menu_kind(dialog_box: &DialogBox) -> Option<DialogKind> {
+    if let DialogKind::NewGameSlots | DialogKind::LoadGameSlots = dialog_box.kind {
+        Some(dialog_box.kind)
+    } else {
+        None
+    }
+}
+
+fn overwrite_dialog(dialog_box: &DialogBox) -> Option<usize> {
+    if let DialogKind::OverwriteSlot(slot) = dialog_box.kind {
+        Some(slot)
+    } else {
+        None
+    }
+}
+
+fn slot_name_dialog(dialog_box: &DialogBox) -> Option<usize> {
+    if let DialogKind::SlotName(slot) = dialog_box.kind {
+        Some(slot)
+    } else {
+        None
+    }
+}
+
 pub fn update(world: &mut game::World) {
     let world_is_empty = (world.id_count == 0) && world.entity_indexes.is_empty();
     let menu_is_open = world.dialogs.last().filter(is_main_menu).is_some();
+    let opened_slots_menu = world.dialogs.last().and_then(slots_menu_kind);
+    let overwritten_slot = world.dialogs.last().and_then(overwrite_dialog);
+    let named_slot = world.dialogs.last().and_then(slot_name_dialog);
     if world_is_empty {
-        let choices: Vec<_> = ["Play a new game", "Continue last game", "Quit"]
-            .iter()
-            .map(|s| s.to_string())
-            .collect();
+        let choices: Vec<_> = [
+            "Play a new game",
+            "Continue last game",
+            "Load a saved game",
+            "Quit",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
         world.add_dialog_box(DialogKind::MainMenu, String::from(""), choices, 24)
     } else if menu_is_open {
         world.player.state = PlayerState::InDialog;
         match world.player.action {
             // "Play a new game"
-            PlayerAction::SelectMenuItem(0) => {
-                *world = Default::default();
-                world.player.state = PlayerState::MakingTurn;
-            }
+            PlayerAction::SelectMenuItem(0) => add_slots_menu(
+                world,
+                DialogKind::NewGameSlots,
+                "Choose a slot for the new game, or Esc to cancel.",
+            ),
             // "Continue last game"
-            PlayerAction::SelectMenuItem(1) => {
-                if load_game(world).is_ok() {
-                    world.player.state = PlayerState::MakingTurn;
-                } else {
-                    let msg = "\nNo saved game to load.\n";
-                    world.add_dialog_box(DialogKind::MessageBox, String::from(msg), vec![], 24);
-                }
-            }
+            PlayerAction::SelectMenuItem(1) => match save::last_slot() {
+                Some(slot) => load_game(world, slot),
+                None => show_load_error(world, save::LoadError::NotFound),
+            },
+            // "Load a saved game"
+            PlayerAction::SelectMenuItem(2) => add_slots_menu(
+                world,
+                DialogKind::LoadGameSlots,
+                "Choose a game to load, or Esc to cancel.",
+            ),
             // "Quit"
-            PlayerAction::SelectMenuItem(2) | PlayerAction::Cancel => {
+            PlayerAction::SelectMenuItem(3) | PlayerAction::Cancel => {
                 world.must_be_destroyed = true;
             }
             _ => (),
         }
+    } else if let Some(dialog_kind) = opened_slots_menu {
+        match world.player.action {
+            PlayerAction::SelectMenuItem(slot) if slot < cfg::SAVE_SLOTS => {
+                world.dialogs.pop();
+                match dialog_kind {
+                    DialogKind::NewGameSlots => match save::slot_info(slot) {
+                        // someone else's run may be there, so ask first
+                        Ok(info) => add_overwrite_dialog(world, slot, &info.name),
+                        Err(_) => add_slot_name_dialog(world, slot),
+                    },
+                    DialogKind::LoadGameSlots => load_game(world, slot),
+                    _ => unreachable!(),
+                }
+            }
+            PlayerAction::Cancel => {
+                world.dialogs.pop();
+            }
+            _ => (),
+        }
+    } else if let Some(slot) = overwritten_slot {
+        match world.player.action {
+            PlayerAction::SelectMenuItem(0) => {
+                world.dialogs.pop();
+                add_slot_name_dialog(world, slot);
+            }
+            PlayerAction::SelectMenuItem(1) | PlayerAction::Cancel => {
+                world.dialogs.pop();
+            }
+            _ => (),
+        }
+    } else if let Some(slot) = named_slot {
+        match world.player.action {
+            PlayerAction::TypeChar(c) if world.save_name.chars().count() < MAX_SLOT_NAME_LEN => {
+                world.save_name.push(c);
+            }
+            PlayerAction::EraseChar => {
+                world.save_name.pop();
+            }
+            PlayerAction::Confirm => {
+                world.dialogs.pop();
+                let name = mem::take(&mut world.save_name);
+                new_game(world, slot, name);
+                return;
+            }
+            PlayerAction::Cancel => {
+                world.dialogs.pop();
+                world.save_name.clear();
+                return;
+            }
+            _ => (),
+        }
+        let header = slot_name_header(slot, &world.save_name);
+        if let Some(dialog_box) = world.dialogs.last_mut() {
+            dialog_box.header = header;
+        }
     }
 }
 
-fn load_game(world: &mut game::World) -> Result<(), Box<dyn Error>> {
-    let mut json_save_state = String::new();
-    let mut file = fs::File::open("savegame")?;
-    file.read_to_string(&mut json_save_state)?;
-    let result = serde_json::from_str::<game::World>(&json_save_state)?;
-    *world = result;
-    Ok(())
+fn add_overwrite_dialog(world: &mut game::World, slot: usize, name: &str) {
+    let name = if name.is_empty() {
+        "an unnamed game"
+    } else {
+        name
+    };
+    let header = format!("Slot {} is used by {}, overwrite?", slot + 1, name);
+    let options = vec![String::from("Yes, start the new game"), String::from("No")];
+    world.add_dialog_box(
+        DialogKind::OverwriteSlot(slot),
+        header,
+        options,
+        cfg::settings().gui.save_slots_width,
+    );
+}
+
+fn add_slot_name_dialog(world: &mut game::World, slot: usize) {
+    world.save_name.clear();
+    world.add_dialog_box(
+        DialogKind::SlotName(slot),
+        slot_name_header(slot, ""),
+        vec![],
+        cfg::settings().gui.save_slots_width,
+    );
+}
+
+fn slot_name_header(slot: usize, name: &str) -> String {
+    format!(
+        "Name the game in slot {}, or leave it empty.\nEnter to start, Esc to cancel.\n\n{}_",
+        slot + 1,
+        name
+    )
+}
+
+fn add_slots_menu(world: &mut game::World, kind: DialogKind, header: &str) {
+    let options = (0..cfg::SAVE_SLOTS)
+        .map(|slot| match save::slot_info(slot) {
+            Ok(info) => format!(
+                "{}: level {}, mine {}, turn {}, {}",
+                if info.name.is_empty() {
+                    format!("Slot {}", slot + 1)
+                } else {
+                    info.name
+                },
+                info.character_level,
+                info.dungeon_level,
+                info.turn,
+                format_time(info.saved_at),
+            ),
+            Err(save::LoadError::NotFound) => format!("Slot {}: empty", slot + 1),
+            Err(_) => format!("Slot {}: can't be loaded", slot + 1),
+        })
+        .collect();
+    world.add_dialog_box(
+        kind,
+        String::from(header),
+        options,
+        cfg::settings().gui.save_slots_width,
+    );
+}
+
+fn new_game(world: &mut game::World, slot: usize, name: String) {
+    *world = Default::default();
+    world.seed = new_seed();
+    world.save_slot = slot;
+    world.save_name = name;
+    world.player.state = PlayerState::MakingTurn;
+}
+
+fn load_game(world: &mut game::World, slot: usize) {
+    match save::load_game(slot) {
+        Ok(saved_world) => {
+            *world = saved_world;
+            world.save_slot = slot;
+            world.player.state = PlayerState::MakingTurn;
+        }
+        Err(err) => show_load_error(world, err),
+    }
+}
+
+fn show_load_error(world: &mut game::World, err: save::LoadError) {
+    let width = match err {
+        save::LoadError::NotFound => 24,
+        _ => 36,
+    };
+    let msg = format!("\n{}\n", err);
+    world.add_dialog_box(DialogKind::MessageBox, msg, vec![], width);
+}
+
+/// Seed for a new game: `EXPT01_SEED` environment variable if it is set (to
+/// reproduce a recorded game), random otherwise. The character screen shows it.
+fn new_seed() -> u64 {
+    env::var("EXPT01_SEED")
+        .ok()
+        .and_then(|seed| seed.parse().ok())
+        .unwrap_or_else(rand::random)
+}
+
+/// Formats the time as "YYYY-MM-DD HH:MM" in UTC.
+fn format_time(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) as i64;
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60
+    )
+}
+
+#[cfg(test)]
+mod main_menu_tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn time_is_formatted_as_utc_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_792_195_200 + 13 * 3600 + 7 * 60);
+        assert_eq!(format_time(time), "2026-10-17 13:07");
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01 00:00");
+    }
+
+    #[test]
+    fn used_slot_is_overwritten_only_if_confirmed() {
+        let mut world = game::World::default();
+        add_overwrite_dialog(&mut world, 1, "Deep run");
+        assert_eq!(
+            world.dialogs[0].header,
+            "Slot 2 is used by Deep run, overwrite?"
+        );
+        world.player.action = PlayerAction::SelectMenuItem(1);
+        update(&mut world);
+        assert!(world.dialogs.is_empty());
+        add_overwrite_dialog(&mut world, 1, "");
+        world.player.action = PlayerAction::SelectMenuItem(0);
+        update(&mut world);
+        assert_eq!(world.dialogs.len(), 1);
+        assert_eq!(world.dialogs[0].kind, DialogKind::SlotName(1));
+    }
+
+    #[test]
+    fn new_game_gets_typed_slot_name() {
+        let mut world = game::World::default();
+        add_slot_name_dialog(&mut world, 2);
+        for &action in &[
+            PlayerAction::TypeChar('A'),
+            PlayerAction::TypeChar('x'),
+            PlayerAction::EraseChar,
+            PlayerAction::TypeChar('b'),
+        ] {
+            world.player.action = action;
+            update(&mut world);
+        }
+        assert!(world.dialogs[0].header.ends_with("Ab_"));
+        world.player.action = PlayerAction::Confirm;
+        update(&mut world);
+        assert!(world.dialogs.is_empty());
+        assert_eq!((world.save_slot, world.save_name.as_str()), (2, "Ab"));
+        assert_eq!(world.player.state, PlayerState::MakingTurn);
+    }
 }
@@ -1,10 +1,11 @@
 use crate::cfg;
 use crate::cmtp::{
-    AiOption, Ammo, Character, Equipment, Item, ItemKind, LogMessage, MapObject, Player,
-    PlayerAction, PlayerState, Slot, Symbol,
+    AiOption, Character, Equipment, Feature, Item, LogMessage, MapObject, Player, PlayerAction,
+    PlayerState, Slot, Stack, Stairs, StatusEffects, Symbol,
 };
 use crate::engine;
 use crate::engine::game;
+use std::{iter, mem};
 
 pub fn update(world: &mut game::World) {
     if world.player.state != PlayerState::MakingTurn {
@@ -15,26 +16,156 @@ pub fn update(world: &mut game::World) {
     }
     let player_symbol = world.player_sym();
     let player_pos = (player_symbol.x, player_symbol.y);
-    // pick up an item or go to next level
-    let item_id = world
-        .item_iter()
-        .find(|(_, sym, map_obj, ..)| ((sym.x, sym.y) == player_pos) && !map_obj.hidden)
-        .map(|(id, ..)| id);
-    let player_on_stairs = world
-        .map_obj_iter()
-        .any(|(_, sym, map_obj, ..)| ((sym.x, sym.y) == player_pos) && (map_obj.name == "stairs"));
+    // pick up an item, go to next level or use a feature at hand
+    let entities_here = world.entities_at(player_pos.0, player_pos.1);
+    let item_id = entities_here.iter().cloned().find(|&id| {
+        world
+            .get::<(&MapObject, &Item)>(id)
+            .filter(|(map_obj, _)| !map_obj.hidden)
+            .is_some()
+    });
+    let stairs = entities_here
+        .iter()
+        .find_map(|&id| world.get::<&Stairs>(id))
+        .cloned();
     if let Some(item_id) = item_id {
-        let maybe_existing_ammo = get_existing_ammo(item_id, world);
-        if let Some(existing_ammo_id) = maybe_existing_ammo {
-            add_ammo_to_existing(existing_ammo_id, item_id, world);
+        let maybe_existing_stack = get_existing_stack(item_id, world);
+        if let Some(existing_stack_id) = maybe_existing_stack {
+            add_to_existing_stack(existing_stack_id, item_id, world);
         } else {
             pick_item_up(item_id, world);
         }
-    } else if player_on_stairs {
-        next_level(world);
+    } else if let Some(stairs) = stairs {
+        match stairs {
+            Stairs::Down => go_down(world),
+            Stairs::Up => go_up(world),
+        }
+    } else if let Some(feature_id) = feature_at_hand(world, player_pos) {
+        use_feature(feature_id, world);
     };
 }
 
+/// The feature under the player, or else next to them. The open door the
+/// player stands in is skipped, as it can't be closed anyway, and so are the
+/// traps that aren't found yet.
+fn feature_at_hand(world: &game::World, (x, y): (i32, i32)) -> Option<u32> {
+    let around = (-1..=1)
+        .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+        .filter(|&pos| pos != (x, y));
+    iter::once((x, y))
+        .chain(around)
+        .flat_map(|(x, y)| world.entities_at(x, y).iter().map(move |&id| (id, (x, y))))
+        .find(|&(id, pos)| match world.get::<(&Feature, &MapObject)>(id) {
+            Some((_, map_obj)) if map_obj.hidden => false,
+            Some((Feature::Door { open: true, .. }, _)) => pos != (x, y),
+            Some(_) => true,
+            None => false,
+        })
+        .map(|(id, _)| id)
+}
+
+/// Uses the feature by its kind, it takes a turn if it works.
+fn use_feature(id: u32, world: &mut game::World) {
+    let feature = *world.get::<&Feature>(id).unwrap();
+    let result = match feature {
+        Feature::Door { group: Some(_), .. } => {
+            Err("The gate doesn't move, there must be a lever somewhere.")
+        }
+        Feature::Door { open, group: None } => engine::set_door_open(id, !open, world),
+        Feature::Lever { pulled, group } => {
+            pull_lever(id, !pulled, group, world);
+            Ok(())
+        }
+        Feature::Support { radius, damage } => knock_out_support(id, radius, damage, world),
+        Feature::Trap => {
+            world.destroy_entity(id);
+            world.add_log(cfg::settings().colors.green, "You disarm the trap.");
+            Ok(())
+        }
+    };
+    match result {
+        Ok(()) => world.player.action = PlayerAction::SkipTurn,
+        Err(reason) => world.add_log(cfg::settings().colors.orange, reason),
+    }
+}
+
+/// Opens the gates of the group when pulled, closes them when pulled back.
+fn pull_lever(id: u32, pulled: bool, group: u32, world: &mut game::World) {
+    let (symbol, feature) = world.get_mut::<(&mut Symbol, &mut Feature)>(id).unwrap();
+    *feature = Feature::Lever { pulled, group };
+    symbol.glyph = engine::feature_glyph(*feature);
+    let gate_ids: Vec<_> = world
+        .query::<&Feature>()
+        .filter(|&(_, &feature)| match feature {
+            Feature::Door {
+                group: gate_group, ..
+            } => gate_group == Some(group),
+            _ => false,
+        })
+        .map(|(id, _)| id)
+        .collect();
+    for gate_id in gate_ids {
+        // the gate with someone in it stays open
+        let _ = engine::set_door_open(gate_id, pulled, world);
+    }
+    world.add_log(
+        cfg::settings().colors.lightest_grey,
+        "You pull the lever. Something rumbles nearby.",
+    );
+}
+
+/// The rock comes down on the tile of the support, and hurts everyone around
+/// but the player, who knocks it out from aside.
+fn knock_out_support(
+    id: u32,
+    radius: i32,
+    damage: i32,
+    world: &mut game::World,
+) -> Result<(), &'static str> {
+    let &Symbol { x, y, .. } = world.get::<&Symbol>(id).unwrap();
+    let is_occupied = world
+        .entities_at(x, y)
+        .iter()
+        .any(|&other_id| world.get_character(other_id).is_some());
+    if is_occupied {
+        return Err("Someone is under the support.");
+    }
+    world.destroy_entity(id);
+    let cell = &mut world.map[(y * cfg::settings().map.width + x) as usize];
+    cell.block = true;
+    cell.block_sight = true;
+    world.map_changed = true;
+    world.add_log(
+        cfg::settings().colors.orange,
+        "You knock out the support, and the ceiling comes down!",
+    );
+    let crushed_ids: Vec<_> = world
+        .entities_in_radius(x, y, radius as f32)
+        .into_iter()
+        .filter(|&id| id != world.player.id)
+        .filter(|&id| {
+            world
+                .get_character(id)
+                .is_some_and(|(.., char, _)| char.alive)
+        })
+        .collect();
+    for crushed_id in crushed_ids {
+        let name = world.get_character(crushed_id).unwrap().1.name.clone();
+        world.add_log(
+            cfg::settings().colors.lightest_grey,
+            format!(
+                "The {} is hit by the rocks for {} hit points.",
+                name, damage
+            ),
+        );
+        let target = world.get_character_mut(crushed_id).unwrap().2;
+        if let Some(xp) = engine::take_damage(target, damage) {
+            world.player_char_mut().xp += xp;
+        }
+    }
+    Ok(())
+}
+
 /// add to the player's inventory and remove from the map
 fn pick_item_up(object_id: u32, world: &mut game::World) {
     let name = world.get_item(object_id).unwrap().1.name.clone();
@@ -45,11 +176,14 @@ fn pick_item_up(object_id: u32, world: &mut game::World) {
         .count();
     if inventory_len >= 35 {
         world.add_log(
-            cfg::COLOR_DARK_RED,
+            cfg::settings().colors.dark_red,
             format!("Your inventory is full, cannot pick up {}.", name),
         );
     } else {
-        world.add_log(cfg::COLOR_GREEN, format!("You picked up a {}!", name));
+        world.add_log(
+            cfg::settings().colors.green,
+            format!("You picked up a {}!", name),
+        );
         let player_id = world.player.id;
         let (_, map_obj, item, eqp, _) = world.get_item_mut(object_id).unwrap();
         item.owner = player_id;
@@ -63,161 +197,328 @@ fn pick_item_up(object_id: u32, world: &mut game::World) {
     }
 }
 
-fn get_existing_ammo(unknown_item_id: u32, world: &game::World) -> Option<u32> {
+/// The stack of the same items in the inventory, if the item is stackable.
+fn get_existing_stack(unknown_item_id: u32, world: &game::World) -> Option<u32> {
+    world.get::<&Stack>(unknown_item_id)?;
     let player_id = world.player.id;
     let item_name = &world.get_item(unknown_item_id).unwrap().1.name;
-    let maybe_ammo = world
-        .get_item(unknown_item_id)
-        .and_then(|(.., ammo)| ammo)
-        .map(|ammo| (item_name, ammo.kind));
-    if let Some(new_ammo) = maybe_ammo {
-        world
-            .item_iter()
-            .filter(|&(.., item, _, _)| (item.kind == ItemKind::Ammo) && (item.owner == player_id))
-            .map(|(id, _, map_obj, .., ammo)| (id, (&map_obj.name, ammo.unwrap().kind)))
-            .map(|(id, existing_ammo)| (id, existing_ammo, new_ammo))
-            .find(|&(_, existing_ammo, new_ammo)| existing_ammo == new_ammo)
-            .map(|(id, ..)| id)
-    } else {
-        None
-    }
-}
-
-fn add_ammo_to_existing(existing_ammo_id: u32, new_ammo_id: u32, world: &mut game::World) {
-    let name = world.get_item(new_ammo_id).unwrap().1.name.clone();
-    let count_of_new = world.get_item(new_ammo_id).unwrap().4.unwrap().count;
-    let mut existing_ammo = world.get_item_mut(existing_ammo_id).unwrap().4.unwrap();
-    existing_ammo.count += count_of_new;
-    world.entity_indexes.remove(&new_ammo_id);
-    world.add_log(cfg::COLOR_GREEN, format!("You picked up a {}!", name));
+    world
+        .item_iter()
+        .filter(|&(id, _, map_obj, item, ..)| {
+            (id != unknown_item_id) && (item.owner == player_id) && (&map_obj.name == item_name)
+        })
+        .map(|(id, ..)| id)
+        .find(|&id| world.get::<&Stack>(id).is_some())
 }
 
-/// Advance to the next level
-fn next_level(world: &mut game::World) {
-    clear_dungeon(world);
+fn add_to_existing_stack(existing_stack_id: u32, new_stack_id: u32, world: &mut game::World) {
+    let name = world.get_item(new_stack_id).unwrap().1.name.clone();
+    let count_of_new = world.get::<&Stack>(new_stack_id).unwrap().count;
+    world
+        .get_mut::<&mut Stack>(existing_stack_id)
+        .unwrap()
+        .count += count_of_new;
+    world.destroy_entity(new_stack_id);
     world.add_log(
-        cfg::COLOR_GREEN,
-        "You take a moment to rest, and recover your strength.",
+        cfg::settings().colors.green,
+        format!("You picked up {} x{}!", name, count_of_new),
     );
-    let heal_hp = world.max_hp(world.player.id) / 2;
-    heal(world.player.id, heal_hp, world);
+}
+
+/// Goes down to the next level, a new one the first time.
+fn go_down(world: &mut game::World) {
+    let level = world.player.dungeon_level + 1;
+    let is_new = !world.levels.contains_key(&level);
+    change_level(world, level);
+    if is_new {
+        world.add_log(
+            cfg::settings().colors.green,
+            "You take a moment to rest, and recover your strength.",
+        );
+        let heal_hp = world.max_hp(world.player.id) / 2;
+        engine::heal(world.player.id, heal_hp, world);
+        world.add_log(
+            cfg::settings().colors.orange,
+            "After a rare moment of peace, you descend deeper into \
+             the heart of the mine...",
+        );
+    } else {
+        world.add_log(
+            cfg::settings().colors.orange,
+            "You descend to the familiar level of the mine.",
+        );
+    }
+}
+
+fn go_up(world: &mut game::World) {
+    let level = world.player.dungeon_level - 1;
+    change_level(world, level);
     world.add_log(
-        cfg::COLOR_ORANGE,
-        "After a rare moment of peace, you descend deeper into \
-         the heart of the mine...",
+        cfg::settings().colors.orange,
+        "You climb back up the stairs.",
     );
-    world.player.dungeon_level += 1;
 }
 
-fn clear_dungeon(world: &mut game::World) {
-    // create new world for storing entities that should be saved
-    let mut temp_world = game::World::default();
+/// Leaves the current level for the one at the depth. The left level is kept
+/// as it is, only without the player and their inventory. A visited level is
+/// restored with the player on its stairs, a new one is made by the dungeon
+/// system as the map is empty.
+fn change_level(world: &mut game::World, level: u32) {
+    let from_level = world.player.dungeon_level;
+    let mut levels = mem::take(&mut world.levels);
+    let mut next_world = levels.remove(&level).unwrap_or_default();
+    move_player(world, &mut next_world);
+    levels.insert(from_level, mem::replace(world, next_world));
+    world.levels = levels;
+    world.player.dungeon_level = level;
+    world.map_changed = true;
+    if !world.map.is_empty() {
+        world.rebuild_spatial_index();
+        let arrival = if level > from_level {
+            Stairs::Up
+        } else {
+            Stairs::Down
+        };
+        let stairs_pos = world
+            .query::<(&Symbol, &Stairs)>()
+            .find(|&(_, (_, &stairs))| stairs == arrival)
+            .map(|(_, (symbol, _))| (symbol.x, symbol.y));
+        if let Some((x, y)) = stairs_pos {
+            world.set_position(world.player.id, x, y);
+        }
+    }
+}
+
+/// Moves the player, their inventory and the log to the other world.
+fn move_player(world: &mut game::World, temp_world: &mut game::World) {
     temp_world.id_count = world.id_count;
+    temp_world.seed = world.seed;
+    temp_world.save_slot = world.save_slot;
+    temp_world.save_name = world.save_name.clone();
     //copy player
-    let player = &world.player;
     temp_world.player = Player {
-        id: player.id,
-        dungeon_level: player.dungeon_level,
-        state: player.state,
-        action: player.action,
         looking_at: None,
-        previous_player_position: player.previous_player_position,
+        ..world.player.clone()
     };
     // move player entity if exist
-    if let Some(indexes) = world.entity_indexes.remove(&world.player.id) {
-        let symbol = Symbol {
-            x: world.symbols[indexes.symbol.unwrap()].x,
-            y: world.symbols[indexes.symbol.unwrap()].y,
-            glyph: world.symbols[indexes.symbol.unwrap()].glyph,
-            color: world.symbols[indexes.symbol.unwrap()].color,
-        };
-        let map_object = MapObject {
-            name: world.map_objects[indexes.map_object.unwrap()].name.clone(),
-            block: world.map_objects[indexes.map_object.unwrap()].block,
-            always_visible: world.map_objects[indexes.map_object.unwrap()].always_visible,
-            hidden: world.map_objects[indexes.map_object.unwrap()].hidden,
-        };
-        let character = Character {
-            alive: world.characters[indexes.character.unwrap()].alive,
-            level: world.characters[indexes.character.unwrap()].level,
-            hp: world.characters[indexes.character.unwrap()].hp,
-            base_max_hp: world.characters[indexes.character.unwrap()].base_max_hp,
-            base_defense: world.characters[indexes.character.unwrap()].base_defense,
-            base_power: world.characters[indexes.character.unwrap()].base_power,
-            xp: world.characters[indexes.character.unwrap()].xp,
-            on_death: world.characters[indexes.character.unwrap()].on_death,
-            looking_right: world.characters[indexes.character.unwrap()].looking_right,
-        };
+    let player_components = world
+        .get::<(&Symbol, &MapObject, &Character, Option<&StatusEffects>)>(world.player.id)
+        .map(|(symbol, map_obj, character, effects)| {
+            (
+                symbol.clone(),
+                map_obj.clone(),
+                character.clone(),
+                effects.cloned(),
+            )
+        });
+    if let Some((symbol, map_obj, character, status_effects)) = player_components {
+        world.entity_indexes.remove(&world.player.id);
         temp_world.player.id = game::new_entity()
-            .add_symbol(symbol)
-            .add_map_object(map_object)
-            .add_character(character)
-            .add_ai(AiOption { option: None })
-            .create(&mut temp_world);
+            .add(symbol)
+            .add(map_obj)
+            .add(character)
+            .add(AiOption::default())
+            .add_option(status_effects)
+            .create(temp_world);
     }
+    let mut moved_ids = vec![];
     // copy inventory
     let inventory = world
         .item_iter()
         .filter(|(.., item, _, _)| item.owner == world.player.id);
-    for (_, sym, map_obj, item, equipment, ammo) in inventory {
-        let symbol = Symbol {
-            x: sym.x,
-            y: sym.y,
-            glyph: sym.glyph,
-            color: sym.color,
-        };
-        let map_object = MapObject {
-            name: map_obj.name.clone(),
-            block: map_obj.block,
-            always_visible: map_obj.always_visible,
-            hidden: map_obj.hidden,
-        };
+    for (id, symbol, map_obj, item, equipment, ammo) in inventory {
+        moved_ids.push(id);
         let item = Item {
-            kind: item.kind,
             owner: temp_world.player.id,
+            ..item.clone()
         };
-        let equipment = equipment.map(|equipment| Equipment {
-            slot: equipment.slot,
-            equipped: equipment.equipped,
-            max_hp_bonus: equipment.max_hp_bonus,
-            defense_bonus: equipment.defense_bonus,
-            power_bonus: equipment.power_bonus,
-        });
-        let ammo = ammo.map(|ammo| Ammo {
-            kind: ammo.kind,
-            count: ammo.count,
-        });
         game::new_entity()
-            .add_symbol(symbol)
-            .add_map_object(map_object)
-            .add_item(item)
-            .add_equipment(equipment)
-            .add_ammo(ammo)
-            .create(&mut temp_world);
+            .add(symbol.clone())
+            .add(map_obj.clone())
+            .add(item)
+            .add_option(equipment.cloned())
+            .add_option(ammo.cloned())
+            .add_option(world.get::<&Stack>(id).cloned())
+            .create(temp_world);
     }
     // copy logs
-    let logs = world
-        .entity_indexes
-        .values()
-        .filter(|indexes| indexes.log_message.is_some());
-    for indexes in logs {
-        let log_message = LogMessage(
-            world.log[indexes.log_message.unwrap()].0.clone(),
-            world.log[indexes.log_message.unwrap()].1,
-        );
+    for (id, log_message) in world.query::<&LogMessage>() {
+        moved_ids.push(id);
         game::new_entity()
-            .add_log_message(log_message)
-            .create(&mut temp_world);
+            .add(log_message.clone())
+            .create(temp_world);
     }
-    *world = temp_world;
+    // the rest stays as it is
+    for id in moved_ids {
+        world.entity_indexes.remove(&id);
+    }
+    world.compact();
 }
 
-/// heal by the given amount, without going over the maximum
-fn heal(id: u32, amount: i32, world: &mut game::World) {
-    let max_hp = world.max_hp(id);
-    let character = world.get_character_mut(id).unwrap().2;
-    character.hp += amount;
-    if character.hp > max_hp {
-        character.hp = max_hp;
+#[cfg(test)]
+mod map_interaction_tests {
+    use super::*;
+    use crate::cmtp::ItemKind;
+    use crate::test_util::{self, world_with_map};
+
+    fn spawn_feature(world: &mut game::World, x: i32, feature: Feature) -> u32 {
+        let id = game::new_entity()
+            .add(Symbol {
+                x,
+                y: 1,
+                glyph: engine::feature_glyph(feature),
+                ..Default::default()
+            })
+            .add(MapObject::default())
+            .add(feature)
+            .create(world);
+        if let Feature::Door { open, .. } = feature {
+            engine::set_door_open(id, open, world).unwrap();
+        }
+        id
+    }
+
+    fn interact(world: &mut game::World) {
+        world.player.state = PlayerState::MakingTurn;
+        world.player.action = PlayerAction::InteractWithMap;
+        update(world);
+    }
+
+    fn cell(world: &game::World, x: i32) -> &crate::cmtp::MapCell {
+        &world.map[(cfg::settings().map.width + x) as usize]
+    }
+
+    #[test]
+    fn door_is_opened_by_bumping_and_closed_by_hand() {
+        let mut world = world_with_map(&["#####", "#...#", "#####"]);
+        world.player.id = test_util::spawn_character(&mut world, (1, 1), test_util::character(30));
+        let closed = Feature::Door {
+            open: false,
+            group: None,
+        };
+        let door_id = spawn_feature(&mut world, 2, closed);
+        assert!(world.is_blocked(2, 1) && cell(&world, 2).block_sight);
+        world.map_changed = false;
+        engine::move_by(world.player.id, 1, 0, &mut world);
+        assert_eq!(world.player_sym().x, 1);
+        assert!(!world.is_blocked(2, 1) && !cell(&world, 2).block_sight);
+        assert!(world.map_changed);
+        interact(&mut world);
+        assert_eq!(world.get::<&Feature>(door_id), Some(&closed));
+        assert_eq!(world.get::<&Symbol>(door_id).unwrap().glyph, '+');
+        assert_eq!(world.player.action, PlayerAction::SkipTurn);
+    }
+
+    #[test]
+    fn lever_opens_the_gates_of_its_group() {
+        let mut world = world_with_map(&["#######", "#.....#", "#######"]);
+        world.player.id = test_util::spawn_character(&mut world, (1, 1), test_util::character(30));
+        let gate = |group| Feature::Door {
+            open: false,
+            group: Some(group),
+        };
+        spawn_feature(
+            &mut world,
+            2,
+            Feature::Lever {
+                pulled: false,
+                group: 7,
+            },
+        );
+        let gate_id = spawn_feature(&mut world, 4, gate(7));
+        let other_gate_id = spawn_feature(&mut world, 5, gate(8));
+        // the gates can't be opened by bumping
+        for _ in 0..3 {
+            engine::move_by(world.player.id, 1, 0, &mut world);
+        }
+        assert_eq!(world.player_sym().x, 3);
+        assert_eq!(world.get::<&Feature>(gate_id), Some(&gate(7)));
+        interact(&mut world);
+        assert!(!world.is_blocked(4, 1));
+        assert_eq!(world.get::<&Feature>(other_gate_id), Some(&gate(8)));
+        assert_eq!(
+            world.get::<&Feature>(gate_id),
+            Some(&Feature::Door {
+                open: true,
+                group: Some(7),
+            })
+        );
+    }
+
+    #[test]
+    fn knocked_out_support_blocks_the_shaft_and_hurts_around() {
+        let mut world = world_with_map(&["#######", "#.....#", "#######"]);
+        world.player.id = test_util::spawn_character(&mut world, (1, 1), test_util::character(30));
+        let support = Feature::Support {
+            radius: 2,
+            damage: 20,
+        };
+        let support_id = spawn_feature(&mut world, 2, support);
+        let monster_id = test_util::spawn_character(&mut world, (3, 1), test_util::character(30));
+        let far_monster_id =
+            test_util::spawn_character(&mut world, (5, 1), test_util::character(30));
+        interact(&mut world);
+        assert!(world.get::<&Feature>(support_id).is_none());
+        assert!(cell(&world, 2).block && cell(&world, 2).block_sight);
+        let hp = |world: &game::World, id| world.get_character(id).unwrap().2.hp;
+        assert_eq!(hp(&world, monster_id), 10);
+        assert_eq!(hp(&world, far_monster_id), 30);
+        assert_eq!(hp(&world, world.player.id), 30);
+    }
+
+    #[test]
+    fn visited_level_is_restored_with_the_player_on_its_stairs() {
+        let spawn_stairs = |world: &mut game::World, x: i32, stairs: Stairs| {
+            game::new_entity()
+                .add(Symbol {
+                    x,
+                    y: 1,
+                    ..Default::default()
+                })
+                .add(MapObject::default())
+                .add(stairs)
+                .create(world)
+        };
+        let mut upper_level = world_with_map(&["#######", "#.....#", "#######"]);
+        spawn_stairs(&mut upper_level, 4, Stairs::Down);
+        let mut world = world_with_map(&["#######", "#.....#", "#######"]);
+        world.player.id = test_util::spawn_character(&mut world, (2, 1), test_util::character(30));
+        world.player.dungeon_level = 2;
+        world.levels.insert(1, upper_level);
+        spawn_stairs(&mut world, 2, Stairs::Up);
+        world.map_changed = false;
+        interact(&mut world);
+        assert_eq!(world.player.dungeon_level, 1);
+        assert_eq!((world.player_sym().x, world.player_sym().y), (4, 1));
+        assert!(world.levels.contains_key(&2));
+        // the FOV map is made again for the restored map
+        assert!(world.map_changed);
+    }
+
+    #[test]
+    fn player_keeps_everything_on_the_stairs() {
+        let mut world = world_with_map(&["#####", "#...#", "#####"]);
+        let player = Character {
+            base_accuracy: 7,
+            speed: 150,
+            xp: 40,
+            ..test_util::character(30)
+        };
+        world.player.id = test_util::spawn_character(&mut world, (1, 1), player);
+        let player_id = world.player.id;
+        let sword_id =
+            test_util::give_equipment(&mut world, player_id, ItemKind::Melee, Slot::Hands);
+        world
+            .get_mut::<&mut Equipment>(sword_id)
+            .unwrap()
+            .crit_bonus = 12;
+        world.add_component(sword_id, Stack { count: 3 });
+        change_level(&mut world, 2);
+        let (.., char, _) = world.get_character(world.player.id).unwrap();
+        assert_eq!((char.base_accuracy, char.speed, char.xp), (7, 150, 40));
+        let (id, .., item, equipment, _) = world.item_iter().next().unwrap();
+        assert_eq!(item.owner, world.player.id);
+        assert_eq!(equipment.map(|equipment| equipment.crit_bonus), Some(12));
+        assert_eq!(world.get::<&Stack>(id).map(|stack| stack.count), Some(3));
     }
 }
@@ -10,5 +10,8 @@ pub mod main_menu;
 pub mod map_interaction;
 pub mod message_box;
 pub mod player_action;
+pub mod recording;
 pub mod render;
+pub mod search;
 pub mod stats_menu;
+pub mod status;
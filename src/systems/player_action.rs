@@ -1,12 +1,19 @@
 use crate::cfg;
-use crate::cmtp::{Item, ItemKind, PlayerAction, PlayerState, Slot};
+use crate::cmtp::{Item, ItemKind, PlayerAction, PlayerState, Slot, StatusKind};
 use crate::engine;
 use crate::engine::game;
+use rand::Rng as _;
 
 pub fn update(world: &mut game::World) {
     if (world.player.state != PlayerState::MakingTurn) || !world.player_is_alive() {
         return;
     }
+    let is_acting = player_moves_or_clicks(world.player.action);
+    if is_acting && world.has_status(world.player.id, StatusKind::Stun) {
+        world.add_log(cfg::settings().colors.orange, "You are stunned!");
+        world.player.action = PlayerAction::SkipTurn;
+        return;
+    }
 
     let (dx, dy) = {
         use PlayerAction::*;
@@ -25,11 +32,27 @@ pub fn update(world: &mut game::World) {
             }
             ClickAt(x, y) if cell_in_fov(world, x, y) => {
                 world.player.action = SkipTurn;
-                delta_by_click(world, x, y)
+                if player_has_ranged(world) {
+                    if let Some(target_id) = find_target(world, (x, y)) {
+                        shoot_at(world, target_id);
+                        return;
+                    }
+                }
+                let player_symbol = world.player_sym();
+                (
+                    (x - player_symbol.x).signum(),
+                    (y - player_symbol.y).signum(),
+                )
             }
             _ => return,
         }
     };
+    let (dx, dy) = if world.has_status(world.player.id, StatusKind::Confusion) {
+        // the player stumbles in a random direction
+        (world.rng.gen_range(-1, 2), world.rng.gen_range(-1, 2))
+    } else {
+        (dx, dy)
+    };
     // the coordinates the player is moving to/attacking
     let player_symbol = world.player_sym();
     let new_pos = (player_symbol.x + dx, player_symbol.y + dy);
@@ -39,10 +62,7 @@ pub fn update(world: &mut game::World) {
         world.player_char_mut().looking_right = true;
     }
     // try to find an attackable object there
-    let target_id = world
-        .character_iter()
-        .find(|&(id, sym, ..)| ((sym.x, sym.y) == new_pos) && (id != world.player.id))
-        .map(|(id, ..)| id);
+    let target_id = find_target(world, new_pos);
     // attack if target found, move otherwise
     match target_id {
         Some(target_id) => {
@@ -54,14 +74,52 @@ pub fn update(world: &mut game::World) {
     }
 }
 
+fn player_moves_or_clicks(action: PlayerAction) -> bool {
+    use PlayerAction::*;
+    matches!(
+        action,
+        GoToUp
+            | GoToDown
+            | GoToLeft
+            | GoToRight
+            | GoToUpLeft
+            | GoToUpRight
+            | GoToDownLeft
+            | GoToDownRight
+            | ClickAt(..)
+    )
+}
+
 fn cell_in_fov(world: &game::World, x: i32, y: i32) -> bool {
-    if (x >= cfg::MAP_WIDTH) || (y >= cfg::MAP_HEIGHT) {
+    if (x >= cfg::settings().map.width) || (y >= cfg::settings().map.height) {
         return false;
     }
-    let index_in_map = (y * cfg::MAP_WIDTH + x) as usize;
+    let index_in_map = (y * cfg::settings().map.width + x) as usize;
     world.map[index_in_map].in_fov
 }
 
+fn find_target(world: &game::World, (x, y): (i32, i32)) -> Option<u32> {
+    world
+        .entities_at(x, y)
+        .iter()
+        .cloned()
+        .find(|&id| (id != world.player.id) && world.get_character(id).is_some())
+}
+
+/// Shoots with the ranged weapon. If the shot can't be made, the turn isn't
+/// spent.
+fn shoot_at(world: &mut game::World, target_id: u32) {
+    let range = world
+        .get_equipped_in_slot(Slot::Hands)
+        .and_then(|id| world.get_item(id).unwrap().3)
+        .and_then(|equipment| equipment.range)
+        .unwrap_or(0);
+    if let Err(reason) = engine::shoot_by(world.player.id, target_id, range, world) {
+        world.add_log(cfg::settings().colors.orange, reason);
+        world.player.action = PlayerAction::None;
+    }
+}
+
 fn is_ranged_weapon(item: &&Item) -> bool {
     match item.kind {
         ItemKind::Ranged(_) => true,
@@ -69,16 +127,10 @@ fn is_ranged_weapon(item: &&Item) -> bool {
     }
 }
 
-fn delta_by_click(world: &game::World, x: i32, y: i32) -> (i32, i32) {
-    let (dx, dy) = (x - world.player_sym().x, y - world.player_sym().y);
-    let player_has_ranged = world
+fn player_has_ranged(world: &game::World) -> bool {
+    world
         .get_equipped_in_slot(Slot::Hands)
         .map(|id| world.get_item(id).unwrap().2)
         .filter(is_ranged_weapon)
-        .is_some();
-    if player_has_ranged {
-        (dx, dy)
-    } else {
-        (dx.signum(), dy.signum())
-    }
+        .is_some()
 }
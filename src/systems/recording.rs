@@ -0,0 +1,206 @@
+use crate::cmtp::{PlayerAction, PlayerState};
+use crate::engine::game;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::{error::Error, fs, io, io::BufRead as _, io::Write as _};
+
+/// The first line of a recording file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    seed: u64,
+}
+
+/// Every next line of a recording file: a frame since the start of the game and
+/// the player's action on it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Entry {
+    frame: u64,
+    action: PlayerAction,
+}
+
+pub enum Recording {
+    Off,
+    Writing {
+        path: PathBuf,
+        file: Option<fs::File>,
+        frame: u64,
+    },
+    Playing {
+        entries: VecDeque<Entry>,
+        frame: u64,
+    },
+}
+
+impl Recording {
+    /// Records every new game to the file, overwriting the previous one.
+    pub fn write_to(path: impl AsRef<Path>) -> Self {
+        Recording::Writing {
+            path: path.as_ref().to_path_buf(),
+            file: None,
+            frame: 0,
+        }
+    }
+
+    /// Loads a recording and starts a new game with the recorded seed in the world.
+    pub fn replay(path: impl AsRef<Path>, world: &mut game::World) -> Result<Self, Box<dyn Error>> {
+        let file = io::BufReader::new(fs::File::open(path)?);
+        let mut lines = file.lines();
+        let header_line = lines.next().ok_or("the recording is empty")??;
+        let header: Header = serde_json::from_str(&header_line)?;
+        let mut entries = VecDeque::new();
+        for line in lines {
+            entries.push_back(serde_json::from_str(&line?)?);
+        }
+        *world = Default::default();
+        world.seed = header.seed;
+        world.player.state = PlayerState::MakingTurn;
+        super::dungeon::update(world);
+        Ok(Recording::Playing { entries, frame: 0 })
+    }
+}
+
+/// Sets the player's action from the replay instead of `input::update`. Returns
+/// false if there is nothing to replay, so the live input should be used.
+pub fn play(world: &mut game::World, recording: &mut Recording) -> bool {
+    if let Recording::Playing { entries, frame } = recording {
+        if entries.is_empty() {
+            println!("the replay is over");
+            *recording = Recording::Off;
+            return false;
+        }
+        *frame += 1;
+        world.player.action = match entries.front() {
+            Some(entry) if entry.frame <= *frame => entries.pop_front().unwrap().action,
+            _ => PlayerAction::None,
+        };
+        true
+    } else {
+        false
+    }
+}
+
+/// Writes the player's action to the recording. Must be called after the main
+/// menu, so that a new game is started with the seed already set. The file is
+/// closed when the game is left, so a loaded game, that can't be replayed from
+/// its seed, is not recorded.
+pub fn record(world: &game::World, recording: &mut Recording) {
+    if let Recording::Writing { path, file, frame } = recording {
+        let is_new_game = (world.id_count == 0) && (world.player.state == PlayerState::MakingTurn);
+        let result = if is_new_game {
+            *frame = 0;
+            start_file(path, world.seed).map(|new_file| {
+                file.replace(new_file);
+            })
+        } else if world.map.is_empty() {
+            file.take();
+            Ok(())
+        } else if let Some(file) = file.as_mut() {
+            *frame += 1;
+            if world.player.action != PlayerAction::None {
+                write_line(
+                    file,
+                    &Entry {
+                        frame: *frame,
+                        action: world.player.action,
+                    },
+                )
+            } else {
+                Ok(())
+            }
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            println!("recording to {} stopped: {}", path.display(), err);
+            *recording = Recording::Off;
+        }
+    }
+}
+
+fn start_file(path: &Path, seed: u64) -> Result<fs::File, Box<dyn Error>> {
+    let mut file = fs::File::create(path)?;
+    write_line(&mut file, &Header { seed })?;
+    Ok(file)
+}
+
+fn write_line(file: &mut fs::File, value: &impl Serialize) -> Result<(), Box<dyn Error>> {
+    let mut line = serde_json::to_string(value)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod recording_tests {
+    use super::*;
+    use crate::systems::dungeon;
+    use std::{env, mem};
+
+    #[test]
+    fn replay_gives_recorded_seed_and_actions() {
+        let path = env::temp_dir().join("expt01_recording_test");
+        let mut recording = Recording::write_to(&path);
+        let mut world = game::World {
+            seed: 9,
+            ..Default::default()
+        };
+        world.player.state = PlayerState::MakingTurn;
+        record(&world, &mut recording);
+        dungeon::update(&mut world);
+        for &action in &[
+            PlayerAction::GoToUp,
+            PlayerAction::None,
+            PlayerAction::SkipTurn,
+        ] {
+            world.player.action = action;
+            record(&world, &mut recording);
+        }
+        let mut replayed_world = Default::default();
+        let mut replay = Recording::replay(&path, &mut replayed_world).unwrap();
+        assert_eq!(replayed_world.seed, 9);
+        let mut replayed_actions = vec![];
+        while play(&mut replayed_world, &mut replay) {
+            replayed_actions.push(replayed_world.player.action);
+        }
+        assert_eq!(
+            replayed_actions,
+            vec![
+                PlayerAction::GoToUp,
+                PlayerAction::None,
+                PlayerAction::SkipTurn
+            ]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn loaded_game_is_not_appended() {
+        let path = env::temp_dir().join("expt01_recording_load_test");
+        let mut recording = Recording::write_to(&path);
+        let mut world = game::World {
+            seed: 9,
+            ..Default::default()
+        };
+        world.player.state = PlayerState::MakingTurn;
+        record(&world, &mut recording);
+        dungeon::update(&mut world);
+        world.player.action = PlayerAction::GoToUp;
+        record(&world, &mut recording);
+        // the player goes to the main menu and loads a saved game
+        let saved_world = mem::take(&mut world);
+        world.player.state = PlayerState::InDialog;
+        record(&world, &mut recording);
+        world = saved_world;
+        world.player.action = PlayerAction::SkipTurn;
+        record(&world, &mut recording);
+        let mut replayed_world = Default::default();
+        let mut replay = Recording::replay(&path, &mut replayed_world).unwrap();
+        let mut replayed_actions = vec![];
+        while play(&mut replayed_world, &mut replay) {
+            replayed_actions.push(replayed_world.player.action);
+        }
+        assert_eq!(replayed_actions, vec![PlayerAction::GoToUp]);
+        fs::remove_file(path).unwrap();
+    }
+}
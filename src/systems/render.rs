@@ -1,5 +1,5 @@
 use crate::cfg;
-use crate::cmtp::{DialogBox, LogMessage, Symbol};
+use crate::cmtp::{DialogBox, LogMessage, StatusEffects, Symbol};
 use crate::engine::game;
 use tcod::{colors, console, Console as _};
 
@@ -7,11 +7,12 @@ pub fn update(world: &mut game::World, tcod: &mut game::Tcod) {
     if !world.map.is_empty() {
         render_map(world, &mut tcod.con);
         render_map_objects(world, &mut tcod.con);
+        render_projectiles(world, &mut tcod.con);
         // blit the contents of "con" to the root console
         console::blit(
             &tcod.con,
             (0, 0),
-            (cfg::MAP_WIDTH, cfg::MAP_HEIGHT),
+            (cfg::settings().map.width, cfg::settings().map.height),
             &mut tcod.root,
             (0, 0),
             1.0,
@@ -26,9 +27,12 @@ pub fn update(world: &mut game::World, tcod: &mut game::Tcod) {
         console::blit(
             &tcod.panel,
             (0, 0),
-            (cfg::SCREEN_WIDTH, cfg::PANEL_HEIGHT),
+            (
+                cfg::settings().screen.width,
+                cfg::settings().gui.panel_height,
+            ),
             &mut tcod.root,
-            (0, cfg::PANEL_Y),
+            (0, cfg::settings().panel_y()),
             1.0,
             1.0,
         );
@@ -39,34 +43,34 @@ pub fn update(world: &mut game::World, tcod: &mut game::Tcod) {
 
 fn render_map(world: &game::World, con: &mut impl console::Console) {
     let map = &world.map;
-    con.set_default_background(cfg::COLOR_DARK_GROUND_BG);
+    let map_size = &cfg::settings().map;
+    let colors = &cfg::settings().colors;
+    con.set_default_background(colors.dark_ground_bg);
     con.clear();
     for i in 0..map.len() {
-        let (x, y) = ((i as i32) % cfg::MAP_WIDTH, (i as i32) / cfg::MAP_WIDTH);
+        let (x, y) = ((i as i32) % map_size.width, (i as i32) / map_size.width);
         let visible = map[i].in_fov;
         let wall = map[i].block_sight;
-        let wall_bottom = ((y + 1) < cfg::MAP_HEIGHT)
+        let wall_bottom = ((y + 1) < map_size.height)
             && wall
-            && !map[((y + 1) * cfg::MAP_WIDTH + x) as usize].block_sight;
+            && !map[((y + 1) * map_size.width + x) as usize].block_sight;
         let ground_sprite = (cfg::GROUND_BITMAP & 1usize.rotate_left(i as u32)) != 0;
         let ground_bg = if world.player.looking_at == Some((x, y)) {
-            cfg::COLOR_LIGHT_GROUND_BG_HIGHLIGHTED
+            colors.light_ground_bg_highlighted
         } else {
-            cfg::COLOR_LIGHT_GROUND_BG
+            colors.light_ground_bg
         };
         let (fg, bg, glyph) = match (visible, wall, wall_bottom, ground_sprite) {
             // outside of field of view:
-            (false, true, false, _) => (cfg::COLOR_DARK_WALL, cfg::COLOR_DARK_WALL_BG, '\u{A0}'),
-            (false, true, true, _) => (cfg::COLOR_DARK_WALL, cfg::COLOR_DARK_WALL_BG, '\u{A1}'),
-            (false, false, _, false) => (cfg::COLOR_DARK_GROUND, cfg::COLOR_DARK_GROUND_BG, ' '),
-            (false, false, _, true) => {
-                (cfg::COLOR_DARK_GROUND, cfg::COLOR_DARK_GROUND_BG, '\u{A2}')
-            }
+            (false, true, false, _) => (colors.dark_wall, colors.dark_wall_bg, '\u{A0}'),
+            (false, true, true, _) => (colors.dark_wall, colors.dark_wall_bg, '\u{A1}'),
+            (false, false, _, false) => (colors.dark_ground, colors.dark_ground_bg, ' '),
+            (false, false, _, true) => (colors.dark_ground, colors.dark_ground_bg, '\u{A2}'),
             // inside fov:
-            (true, true, false, _) => (cfg::COLOR_LIGHT_WALL, cfg::COLOR_LIGHT_WALL_BG, '\u{A0}'),
-            (true, true, true, _) => (cfg::COLOR_LIGHT_WALL, cfg::COLOR_LIGHT_WALL_BG, '\u{A1}'),
-            (true, false, _, false) => (cfg::COLOR_LIGHT_GROUND, ground_bg, ' '),
-            (true, false, _, true) => (cfg::COLOR_LIGHT_GROUND, ground_bg, '\u{A2}'),
+            (true, true, false, _) => (colors.light_wall, colors.light_wall_bg, '\u{A0}'),
+            (true, true, true, _) => (colors.light_wall, colors.light_wall_bg, '\u{A1}'),
+            (true, false, _, false) => (colors.light_ground, ground_bg, ' '),
+            (true, false, _, true) => (colors.light_ground, ground_bg, '\u{A2}'),
         };
         if map[i].explored {
             // show explored tiles only (any visible tile is explored already)
@@ -97,22 +101,39 @@ fn render_map_objects(world: &game::World, con: &mut impl console::Console) {
     }
 }
 
+/// Draws every flying projectile one tile further than in the last frame, the
+/// ones that have arrived are dropped.
+fn render_projectiles(world: &mut game::World, con: &mut impl console::Console) {
+    let map_width = cfg::settings().map.width;
+    for projectile in &mut world.projectiles {
+        let (x, y) = projectile.path[projectile.frame];
+        if world.map[(y * map_width + x) as usize].in_fov {
+            con.set_default_foreground(projectile.color);
+            con.put_char(x, y, projectile.glyph, console::BackgroundFlag::None);
+        }
+        projectile.frame += 1;
+    }
+    world
+        .projectiles
+        .retain(|projectile| projectile.frame < projectile.path.len());
+}
+
 fn render_main_menu_bg(con: &mut impl console::Console) {
     let img = tcod::image::Image::from_file("menu_background.png")
         .ok()
         .expect("Background image not found");
     tcod::image::blit_2x(&img, (0, 0), (-1, -1), con, (0, 0));
-    con.set_default_foreground(cfg::COLOR_DARK_RED);
+    con.set_default_foreground(cfg::settings().colors.dark_red);
     con.print_ex(
-        cfg::SCREEN_WIDTH / 2,
-        cfg::SCREEN_HEIGHT / 2 - 4,
+        cfg::settings().screen.width / 2,
+        cfg::settings().screen.height / 2 - 4,
         console::BackgroundFlag::None,
         console::TextAlignment::Center,
         "EXPERIMENT 01: ABANDONED MINES",
     );
     con.print_ex(
-        cfg::SCREEN_WIDTH / 2,
-        cfg::SCREEN_HEIGHT - 2,
+        cfg::settings().screen.width / 2,
+        cfg::settings().screen.height - 2,
         console::BackgroundFlag::None,
         console::TextAlignment::Center,
         "by saintech",
@@ -120,20 +141,26 @@ fn render_main_menu_bg(con: &mut impl console::Console) {
 }
 
 fn render_panel(world: &game::World, con: &mut impl console::Console) {
+    let settings = cfg::settings();
     // prepare to render the GUI panel
-    con.set_default_background(cfg::COLOR_DARKEST_GREY);
+    con.set_default_background(settings.colors.darkest_grey);
     con.clear();
     // print the game messages, one line at a time
-    let mut y = cfg::MSG_HEIGHT;
+    let mut y = settings.msg_height();
     for &LogMessage(ref msg, color) in world.log.iter().rev() {
-        let msg_height =
-            con.get_height_rect(cfg::MSG_X, cfg::MSG_HEIGHT - y, cfg::MSG_WIDTH, 0, msg);
+        let msg_height = con.get_height_rect(
+            settings.msg_x(),
+            settings.msg_height() - y,
+            settings.msg_width(),
+            0,
+            msg,
+        );
         y -= msg_height;
         if y < 0 {
             break;
         }
         con.set_default_foreground(color);
-        con.print_rect(cfg::MSG_X, y, cfg::MSG_WIDTH, 0, msg);
+        con.print_rect(settings.msg_x(), y, settings.msg_width(), 0, msg);
     }
     // show the player's stats
     let hp = world.player_char().hp;
@@ -142,12 +169,12 @@ fn render_panel(world: &game::World, con: &mut impl console::Console) {
         con,
         1,
         2,
-        cfg::BAR_WIDTH,
+        settings.gui.bar_width,
         "HP",
         hp,
         max_hp,
-        cfg::COLOR_DARK_RED,
-        cfg::COLOR_DARKEST_SEPIA,
+        settings.colors.dark_red,
+        settings.colors.darkest_sepia,
     );
     con.print_ex(
         1,
@@ -156,12 +183,22 @@ fn render_panel(world: &game::World, con: &mut impl console::Console) {
         console::TextAlignment::Left,
         format!("Mine level: {}", world.player.dungeon_level),
     );
+    // show what lasts on the player, with the turns left
+    let status_effects = world
+        .get::<&StatusEffects>(world.player.id)
+        .iter()
+        .flat_map(|effects| &effects.0)
+        .map(|effect| format!("{} {}", effect.kind, effect.turns))
+        .collect::<Vec<_>>()
+        .join(", ");
+    con.set_default_foreground(settings.colors.orange);
+    con.print_rect(1, 3, settings.gui.bar_width, 0, status_effects);
     // display names of objects under the mouse
-    con.set_default_foreground(cfg::COLOR_LIGHTEST_GREY);
+    con.set_default_foreground(settings.colors.lightest_grey);
     con.print_rect(
         1,
-        3,
-        cfg::BAR_WIDTH,
+        4,
+        settings.gui.bar_width,
         0,
         String::from("You see: ") + &get_names_under_mouse(world),
     );
@@ -189,7 +226,7 @@ fn render_bar(
         panel.rect(x, y, bar_width, 1, false, console::BackgroundFlag::Set);
     }
     // finally, some centered text with the values
-    panel.set_default_foreground(cfg::COLOR_LIGHTEST_GREY);
+    panel.set_default_foreground(cfg::settings().colors.lightest_grey);
     panel.print_ex(
         x + total_width / 2,
         y,
@@ -205,7 +242,7 @@ fn get_names_under_mouse(world: &game::World) -> String {
     if let Some((x, y)) = world.player.looking_at {
         names = world
             .map_obj_iter()
-            .filter(|(_, sym, ..)| (sym.x, sym.y) == (x, y))
+            .filter(|(_, sym, map_obj, ..)| ((sym.x, sym.y) == (x, y)) && !map_obj.hidden)
             .map(|(_, _, map_obj, ..)| map_obj.name.clone())
             .collect();
     }
@@ -237,7 +274,13 @@ fn render_dialogs(world: &game::World, destination_console: &mut impl console::C
         let header_height = if header.is_empty() {
             -1
         } else {
-            destination_console.get_height_rect(0, 0, width - 2, cfg::SCREEN_HEIGHT - 2, header)
+            destination_console.get_height_rect(
+                0,
+                0,
+                width - 2,
+                cfg::settings().screen.height - 2,
+                header,
+            )
         };
         let height = if options.len() > 0 {
             header_height + options.len() as i32 + 3
@@ -246,8 +289,8 @@ fn render_dialogs(world: &game::World, destination_console: &mut impl console::C
         };
         // create an off-screen console that represents the menu's window
         let mut window = console::Offscreen::new(*width, height);
-        window.set_default_background(cfg::COLOR_DARK_SKY);
-        window.set_default_foreground(cfg::COLOR_DARKEST_SEPIA);
+        window.set_default_background(cfg::settings().colors.dark_sky);
+        window.set_default_foreground(cfg::settings().colors.darkest_sepia);
         window.clear();
         // print the header, with auto-wrap
         window.print_rect(1, 1, width - 1, height, header);
@@ -257,8 +300,8 @@ fn render_dialogs(world: &game::World, destination_console: &mut impl console::C
             let text = format!("[{}] {}", menu_letter, option_text);
             window.print(1, header_height + 2 + index as i32, text);
         }
-        let x = cfg::SCREEN_WIDTH / 2 - width / 2;
-        let y = cfg::SCREEN_HEIGHT / 2 - height / 2;
+        let x = cfg::settings().screen.width / 2 - width / 2;
+        let y = cfg::settings().screen.height / 2 - height / 2;
         tcod::console::blit(
             &mut window,
             (0, 0),
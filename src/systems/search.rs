@@ -0,0 +1,142 @@
+use crate::cfg;
+use crate::cmtp::{MapObject, PlayerAction, PlayerState, Symbol, Trap};
+use crate::engine;
+use crate::engine::game;
+use rand::Rng as _;
+
+pub fn update(world: &mut game::World) {
+    if (world.player.state != PlayerState::MakingTurn) || !world.player_is_alive() {
+        return;
+    }
+    let traps = &cfg::settings().traps;
+    use PlayerAction::*;
+    match world.player.action {
+        Search => {
+            let found = find_traps(world, traps.search_radius as f32, traps.search_chance);
+            if found == 0 {
+                world.add_log(
+                    cfg::settings().colors.lightest_grey,
+                    "You search around, but find nothing.",
+                );
+            }
+            world.player.action = SkipTurn;
+        }
+        // the traps next to the player can be noticed on the way, but not when
+        // they bump into a wall or attack
+        GoToUp | GoToDown | GoToLeft | GoToRight | GoToUpLeft | GoToUpRight | GoToDownLeft
+        | GoToDownRight => {
+            let &Symbol { x, y, .. } = world.player_sym();
+            if world.player.previous_player_position != (x, y) {
+                find_traps(world, 1.5, traps.notice_chance);
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Looks for the hidden traps around the player, each one is found by the
+/// chance. Returns how many are found.
+fn find_traps(world: &mut game::World, radius: f32, chance: i32) -> usize {
+    let &Symbol { x, y, .. } = world.player_sym();
+    let hidden_trap_ids: Vec<_> = world
+        .entities_in_radius(x, y, radius)
+        .into_iter()
+        .filter(|&id| {
+            world
+                .get::<(&MapObject, &Trap)>(id)
+                .is_some_and(|(map_obj, _)| map_obj.hidden)
+        })
+        .collect();
+    let mut found = 0;
+    for id in hidden_trap_ids {
+        if world.rng.gen_range(0, 100) < chance {
+            engine::reveal(id, world);
+            let name = world.get::<&MapObject>(id).unwrap().name.clone();
+            world.add_log(
+                cfg::settings().colors.orange,
+                format!("You found a {}!", name),
+            );
+            found += 1;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::*;
+    use crate::cmtp::{Effect, Feature};
+    use crate::test_util;
+
+    fn spawn_trap(world: &mut game::World, x: i32) -> u32 {
+        game::new_entity()
+            .add(Symbol {
+                x,
+                y: 1,
+                ..Default::default()
+            })
+            .add(MapObject {
+                name: String::from("pit"),
+                hidden: true,
+                ..Default::default()
+            })
+            .add(Feature::Trap)
+            .add(Trap {
+                message: String::from("The ground gives way!"),
+                radius: 0,
+                effects: vec![Effect::Damage(5)],
+                once: false,
+            })
+            .create(world)
+    }
+
+    fn world_with_player() -> game::World {
+        let corridor = ["#######", "#.....#", "#######"];
+        test_util::world_with_player(&corridor, (1, 1), test_util::character(20))
+    }
+
+    #[test]
+    fn trap_is_triggered_and_found_when_stepped_on() {
+        let mut world = world_with_player();
+        let trap_id = spawn_trap(&mut world, 2);
+        engine::move_by(world.player.id, 1, 0, &mut world);
+        assert_eq!(world.player_char().hp, 15);
+        assert!(!world.get::<&MapObject>(trap_id).unwrap().hidden);
+    }
+
+    #[test]
+    fn searching_finds_the_traps_around() {
+        let mut world = world_with_player();
+        let near_id = spawn_trap(&mut world, 3);
+        let far_id = spawn_trap(&mut world, 5);
+        // every search finds a trap in the radius by chance, so search a lot
+        for _ in 0..50 {
+            world.player.action = PlayerAction::Search;
+            update(&mut world);
+            assert_eq!(world.player.action, PlayerAction::SkipTurn);
+        }
+        let is_hidden = |id| world.get::<&MapObject>(id).unwrap().hidden;
+        assert!(!is_hidden(near_id));
+        assert!(is_hidden(far_id));
+    }
+
+    #[test]
+    fn traps_are_noticed_only_on_the_way() {
+        let mut world = world_with_player();
+        let trap_id = spawn_trap(&mut world, 2);
+        let is_hidden = |world: &game::World| world.get::<&MapObject>(trap_id).unwrap().hidden;
+        world.player.previous_player_position = (1, 1);
+        // bumping into the wall again and again
+        for _ in 0..100 {
+            world.player.action = PlayerAction::GoToLeft;
+            update(&mut world);
+        }
+        assert!(is_hidden(&world));
+        world.set_position(world.player.id, 3, 1);
+        for _ in 0..100 {
+            world.player.action = PlayerAction::GoToRight;
+            update(&mut world);
+        }
+        assert!(!is_hidden(&world));
+    }
+}
@@ -11,7 +11,8 @@ pub fn update(world: &mut game::World) {
     if world.get_character(world.player.id).is_some() {
         // show character information
         let player = world.player_char();
-        let level_up_xp = cfg::LEVEL_UP_BASE + player.level * cfg::LEVEL_UP_FACTOR;
+        let level_up_xp =
+            cfg::settings().level_up.base + player.level * cfg::settings().level_up.factor;
         let msg = format!(
             "Character information\n\
              \n\
@@ -21,19 +22,28 @@ pub fn update(world: &mut game::World) {
              \n\
              Maximum HP: {}\n\
              Attack: {}\n\
-             Defense: {}",
+             Defense: {}\n\
+             Accuracy: {}\n\
+             Evasion: {}\n\
+             Critical hits: {}%\n\
+             \n\
+             World seed:\n{}",
             player.level,
             player.xp,
             level_up_xp,
             world.max_hp(world.player.id),
             world.power(world.player.id),
             world.defense(world.player.id),
+            world.accuracy(world.player.id),
+            world.evasion(world.player.id),
+            world.crit_chance(world.player.id),
+            world.seed,
         );
         world.add_dialog_box(
             DialogKind::MessageBox,
             msg,
             vec![],
-            cfg::CHARACTER_SCREEN_WIDTH,
+            cfg::settings().gui.character_screen_width,
         );
     }
 }
@@ -0,0 +1,137 @@
+use crate::cfg;
+use crate::cmtp::{Character, StatusEffects, StatusKind};
+use crate::engine;
+use crate::engine::game;
+
+/// One turn of the status effects of the living characters: poison and
+/// bleeding deal their damage, then the effects run out.
+pub fn tick(world: &mut game::World) {
+    let damages: Vec<_> = world
+        .query::<(&Character, &StatusEffects)>()
+        .filter(|(_, (char, _))| char.alive)
+        .map(|(id, (_, effects))| {
+            let hurting: Vec<_> = effects
+                .0
+                .iter()
+                .filter(|effect| {
+                    (effect.kind == StatusKind::Poison) || (effect.kind == StatusKind::Bleeding)
+                })
+                .collect();
+            let damage: i32 = hurting.iter().map(|effect| effect.power).sum();
+            let by_player = hurting.iter().any(|effect| effect.by_player);
+            (id, damage, by_player)
+        })
+        .filter(|&(_, damage, _)| damage > 0)
+        .collect();
+    for (id, damage, by_player) in damages {
+        hurt(id, damage, by_player, world);
+    }
+    let mut expired = vec![];
+    for (id, (char, effects)) in world.query_mut::<(&Character, &mut StatusEffects)>() {
+        if !char.alive {
+            continue;
+        }
+        for effect in effects.0.iter_mut() {
+            effect.turns -= 1;
+        }
+        let mut kinds: Vec<_> = effects
+            .0
+            .iter()
+            .filter(|effect| effect.turns <= 0)
+            .map(|effect| effect.kind)
+            .collect();
+        effects.0.retain(|effect| effect.turns > 0);
+        // a wound may stop bleeding while another one goes on
+        kinds.retain(|&kind| effects.0.iter().all(|effect| effect.kind != kind));
+        kinds.dedup();
+        expired.extend(kinds.into_iter().map(|kind| (id, kind)));
+    }
+    for (id, kind) in expired {
+        if (id == world.player.id) || world.check_fov(id) {
+            let name = world.get_character(id).unwrap().1.name.clone();
+            world.add_log(
+                cfg::settings().colors.dark_sky,
+                format!("{} is no longer {}.", name, kind),
+            );
+        }
+    }
+}
+
+/// Deals the damage of poison or bleeding, the kill is the player's if they
+/// poisoned or wounded the character.
+fn hurt(id: u32, damage: i32, by_player: bool, world: &mut game::World) {
+    let name = world.get_character(id).unwrap().1.name.clone();
+    if (id == world.player.id) || world.check_fov(id) {
+        world.add_log(
+            cfg::settings().colors.dark_red,
+            format!("{} loses {} hit points.", name, damage),
+        );
+    }
+    let char = world.get_character_mut(id).unwrap().2;
+    if let Some(xp) = engine::take_damage(char, damage) {
+        if by_player && (id != world.player.id) {
+            world.player_char_mut().xp += xp;
+        }
+    }
+}
+
+#[cfg(test)]
+mod status_tests {
+    use super::*;
+    use crate::cmtp::{DeathCallback, StatusEffect};
+    use crate::test_util::{self, world_with_map};
+
+    fn effect(kind: StatusKind, turns: i32, power: i32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            turns,
+            power,
+            by_player: false,
+        }
+    }
+
+    #[test]
+    fn poison_and_bleeding_hurt_until_they_run_out() {
+        let mut world = world_with_map(&["###", "#.#", "###"]);
+        let monster = Character {
+            on_death: DeathCallback::Enemy,
+            ..test_util::character(20)
+        };
+        let id = test_util::spawn_character(&mut world, (1, 1), monster);
+        engine::add_status_effect(id, effect(StatusKind::Poison, 2, 3), &mut world);
+        engine::add_status_effect(id, effect(StatusKind::Bleeding, 1, 1), &mut world);
+        engine::add_status_effect(id, effect(StatusKind::Bleeding, 3, 1), &mut world);
+        tick(&mut world);
+        assert_eq!(world.get_character(id).unwrap().2.hp, 15);
+        tick(&mut world);
+        assert_eq!(world.get_character(id).unwrap().2.hp, 11);
+        assert!(!world.has_status(id, StatusKind::Poison));
+        assert!(world.has_status(id, StatusKind::Bleeding));
+        tick(&mut world);
+        assert_eq!(world.get_character(id).unwrap().2.hp, 10);
+        assert!(!world.has_status(id, StatusKind::Bleeding));
+    }
+
+    #[test]
+    fn only_the_poison_of_the_player_gives_them_xp() {
+        let mut world = world_with_map(&["####", "#..#", "####"]);
+        world.player.id = test_util::spawn_character(&mut world, (1, 1), test_util::character(20));
+        for &by_player in &[false, true] {
+            let monster = Character {
+                on_death: DeathCallback::Enemy,
+                xp: 35,
+                ..test_util::character(2)
+            };
+            let id = test_util::spawn_character(&mut world, (2, 1), monster);
+            let poison = StatusEffect {
+                by_player,
+                ..effect(StatusKind::Poison, 5, 3)
+            };
+            engine::add_status_effect(id, poison, &mut world);
+            tick(&mut world);
+            assert!(!world.get_character(id).unwrap().2.alive);
+            let xp = if by_player { 35 } else { 0 };
+            assert_eq!(world.player_char().xp, xp);
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Worlds and entities shared by the tests of the systems.
+
+use crate::cfg;
+use crate::cmtp::{
+    AiOption, Character, Equipment, Item, ItemKind, MapCell, MapObject, PlayerState, Slot, Symbol,
+};
+use crate::engine::game;
+
+/// A world with the map drawn in the top left corner: `#` is a wall, `M` is
+/// a blocking creature, everything else is the floor. The rest of the map is
+/// walls.
+pub fn world_with_map(rows: &[&str]) -> game::World {
+    let mut world = game::World::default();
+    let map_size = &cfg::settings().map;
+    for y in 0..map_size.height {
+        for x in 0..map_size.width {
+            let tile = rows
+                .get(y as usize)
+                .and_then(|row| row.chars().nth(x as usize))
+                .unwrap_or('#');
+            game::new_entity()
+                .add(MapCell {
+                    block: tile == '#',
+                    explored: false,
+                    block_sight: tile == '#',
+                    in_fov: false,
+                })
+                .create(&mut world);
+            if tile == 'M' {
+                spawn_character(&mut world, (x, y), character(1));
+            }
+        }
+    }
+    world
+}
+
+/// A world with the map and the player on the tile, ready to make a turn.
+pub fn world_with_player(rows: &[&str], pos: (i32, i32), player: Character) -> game::World {
+    let mut world = world_with_map(rows);
+    world.player.id = spawn_character(&mut world, pos, player);
+    world.player.state = PlayerState::MakingTurn;
+    world
+}
+
+/// A living character with the HP, the rest is default.
+pub fn character(hp: i32) -> Character {
+    Character {
+        alive: true,
+        hp,
+        base_max_hp: hp,
+        ..Default::default()
+    }
+}
+
+/// Puts the character on the tile as a blocking creature without an AI.
+pub fn spawn_character(world: &mut game::World, (x, y): (i32, i32), character: Character) -> u32 {
+    game::new_entity()
+        .add(Symbol {
+            x,
+            y,
+            ..Default::default()
+        })
+        .add(MapObject {
+            block: true,
+            ..Default::default()
+        })
+        .add(character)
+        .add(AiOption::default())
+        .create(world)
+}
+
+/// Gives the owner an equipped item without any bonuses.
+pub fn give_equipment(world: &mut game::World, owner: u32, kind: ItemKind, slot: Slot) -> u32 {
+    game::new_entity()
+        .add(Symbol::default())
+        .add(MapObject::default())
+        .add(Item {
+            kind,
+            owner,
+            inflicts: vec![],
+            on_use: None,
+        })
+        .add(Equipment {
+            slot,
+            equipped: true,
+            max_hp_bonus: 0,
+            defense_bonus: 0,
+            power_bonus: 0,
+            accuracy_bonus: 0,
+            crit_bonus: 0,
+            attack_cost: None,
+            range: None,
+        })
+        .create(world)
+}